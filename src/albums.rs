@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::collate::sort_key;
+use crate::metadata::TrackInfo;
+
+/// What tells albums apart: the title and whose album it is. `artist` is `None`
+/// for compilations and soundtracks, shown as Various Artists.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AlbumKey {
+    pub title: String,
+    pub artist: Option<String>,
+}
+
+impl AlbumKey {
+    pub fn artist_name(&self) -> &str {
+        self.artist.as_deref().unwrap_or("Various Artists")
+    }
+}
+
+impl std::fmt::Display for AlbumKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} — {}", self.title, self.artist_name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Album {
+    pub key: AlbumKey,
+    pub tracks: Vec<PathBuf>, // By disc then track number
+}
+
+/// Groups the tagged tracks in `files` into albums, sorted by artist then title.
+/// Tracks go by their album artist, so a guest appearance doesn't split an album.
+/// Without one, tracks sharing an album title in one folder but with different
+/// artists are taken to be a compilation, as are any flagged as one, so a
+/// soundtrack stays a single album rather than one per artist.
+pub fn group_albums(files: &[PathBuf], track_info: &HashMap<PathBuf, TrackInfo>) -> Vec<Album> {
+    // Artists of the tracks with no album artist, per album title and folder
+    let mut folder_artists: HashMap<(&str, Option<&Path>), Vec<String>> = HashMap::new();
+    for (file_path, info) in tagged(files, track_info) {
+        if album_artist(info).is_none() && !info.compilation {
+            let artists = folder_artists.entry((info.album.as_deref().unwrap_or_default(), file_path.parent())).or_default();
+            let artist = info.artist.as_deref().unwrap_or_default().to_lowercase();
+            if !artists.contains(&artist) {
+                artists.push(artist);
+            }
+        }
+    }
+
+    let mut albums: Vec<Album> = Vec::new();
+    // Keyed by lowercase title and artist, so differently capitalised tags still match
+    let mut index: HashMap<(String, Option<String>), usize> = HashMap::new();
+    for (file_path, info) in tagged(files, track_info) {
+        let title = info.album.clone().unwrap_or_default();
+        let artist = if info.compilation {
+            None
+        } else if let Some(album_artist) = album_artist(info) {
+            Some(album_artist.to_string())
+        } else if folder_artists.get(&(title.as_str(), file_path.parent())).is_some_and(|artists| artists.len() > 1) {
+            None
+        } else {
+            info.artist.clone()
+        };
+        let lookup = (title.to_lowercase(), artist.as_ref().map(|artist| artist.to_lowercase()));
+        let position = *index.entry(lookup).or_insert_with(|| {
+            albums.push(Album { key: AlbumKey { title, artist }, tracks: Vec::new() });
+            albums.len() - 1
+        });
+        albums[position].tracks.push(file_path.clone());
+    }
+
+    for album in &mut albums {
+        // Stable, so tracks without numbers stay in library order after the numbered ones
+        album.tracks.sort_by_key(|file_path| {
+            let track_number = track_info.get(file_path).and_then(|info| info.track_number);
+            (disc_of(file_path, track_info), track_number.unwrap_or(u32::MAX))
+        });
+    }
+    albums.sort_by_cached_key(|album| (sort_key(album.key.artist_name()), sort_key(&album.key.title)));
+    albums
+}
+
+/// An artist or composer and every track of theirs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Person {
+    pub name: String,
+    pub tracks: Vec<PathBuf>,
+}
+
+/// Groups the tracks in `files` with a composer tag by composer, sorted by name.
+/// Each composer's tracks are sorted by work, so the movements of a piece stay
+/// together and in order even when they're spread over albums.
+pub fn group_composers(files: &[PathBuf], track_info: &HashMap<PathBuf, TrackInfo>) -> Vec<Person> {
+    let mut composers = group_people(files, track_info, |info| info.composer.as_deref());
+    for composer in &mut composers {
+        composer.tracks.sort_by_cached_key(|file_path| {
+            let info = track_info.get(file_path);
+            let work = info.and_then(|info| info.work.as_deref());
+            let track_number = info.and_then(|info| info.track_number).unwrap_or(u32::MAX);
+            // Untitled works go last
+            (work.is_none(), work.map(sort_key), disc_of(file_path, track_info), track_number)
+        });
+    }
+    composers
+}
+
+/// Groups the tracks in `files` by their artist tag, sorted by name, with each
+/// artist's tracks in album order.
+pub fn group_artists(files: &[PathBuf], track_info: &HashMap<PathBuf, TrackInfo>) -> Vec<Person> {
+    let mut artists = group_people(files, track_info, |info| info.artist.as_deref());
+    for artist in &mut artists {
+        artist.tracks.sort_by_cached_key(|file_path| {
+            let info = track_info.get(file_path);
+            let album = info.and_then(|info| info.album.as_deref());
+            let track_number = info.and_then(|info| info.track_number).unwrap_or(u32::MAX);
+            // Loose tracks go last
+            (album.is_none(), album.map(sort_key), disc_of(file_path, track_info), track_number)
+        });
+    }
+    artists
+}
+
+/// Tracks grouped by the name `name_of` picks out of their tags, ignoring case,
+/// sorted by name. Tracks without one are left out.
+fn group_people(files: &[PathBuf], track_info: &HashMap<PathBuf, TrackInfo>, name_of: fn(&TrackInfo) -> Option<&str>) -> Vec<Person> {
+    let mut people: Vec<Person> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for file_path in files {
+        let Some(name) = track_info.get(file_path).and_then(name_of).map(str::trim).filter(|name| !name.is_empty()) else {
+            continue;
+        };
+        let position = *index.entry(name.to_lowercase()).or_insert_with(|| {
+            people.push(Person { name: name.to_string(), tracks: Vec::new() });
+            people.len() - 1
+        });
+        people[position].tracks.push(file_path.clone());
+    }
+    people.sort_by_cached_key(|person| sort_key(&person.name));
+    people
+}
+
+/// Disc numbers of the tracks in `album`, in order, if it has more than one disc.
+pub fn discs(album: &Album, track_info: &HashMap<PathBuf, TrackInfo>) -> Vec<u32> {
+    let mut discs: Vec<u32> = album.tracks.iter().map(|file_path| disc_of(file_path, track_info)).collect();
+    discs.dedup();
+    if discs.len() > 1 { discs } else { Vec::new() }
+}
+
+pub fn disc_of(file_path: &Path, track_info: &HashMap<PathBuf, TrackInfo>) -> u32 {
+    track_info.get(file_path).and_then(|info| info.disc_number).unwrap_or(1)
+}
+
+/// Tracks with an album title, along with their tags.
+fn tagged<'a>(files: &'a [PathBuf], track_info: &'a HashMap<PathBuf, TrackInfo>) -> impl Iterator<Item = (&'a PathBuf, &'a TrackInfo)> {
+    files
+        .iter()
+        .filter_map(|file_path| Some((file_path, track_info.get(file_path)?)))
+        .filter(|(_, info)| info.album.as_deref().is_some_and(|album| !album.trim().is_empty()))
+}
+
+/// The album artist, unless it's one of the usual ways of saying "compilation".
+fn album_artist(info: &TrackInfo) -> Option<&str> {
+    info.album_artist.as_deref().filter(|artist| {
+        let artist = artist.trim().to_lowercase();
+        !artist.is_empty() && !matches!(artist.as_str(), "various artists" | "various" | "va" | "v.a.")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(album: &str, artist: &str, album_artist: Option<&str>, compilation: bool) -> TrackInfo {
+        TrackInfo {
+            album: Some(album.to_string()),
+            artist: Some(artist.to_string()),
+            album_artist: album_artist.map(str::to_string),
+            compilation,
+            ..Default::default()
+        }
+    }
+
+    fn group(tracks: Vec<(&str, TrackInfo)>) -> Vec<(String, usize)> {
+        let files: Vec<PathBuf> = tracks.iter().map(|(file_path, _)| PathBuf::from(file_path)).collect();
+        let track_info = tracks.into_iter().map(|(file_path, info)| (PathBuf::from(file_path), info)).collect();
+        group_albums(&files, &track_info).into_iter().map(|album| (album.key.to_string(), album.tracks.len())).collect()
+    }
+
+    #[test]
+    fn soundtracks_stay_together() {
+        let albums = group(vec![
+            // Untagged as a compilation, but many artists in one folder
+            ("/music/Pulp Fiction/01.mp3", track("Pulp Fiction", "Dick Dale", None, false)),
+            ("/music/Pulp Fiction/02.mp3", track("Pulp Fiction", "Kool & the Gang", None, false)),
+            ("/music/Pulp Fiction/03.mp3", track("Pulp Fiction", "Al Green", None, false)),
+            // Flagged, even though it's spread over folders
+            ("/music/Now 1/a.mp3", track("Now 1", "Duran Duran", None, true)),
+            ("/music/Now 1/disc 2/b.mp3", track("Now 1", "UB40", None, true)),
+            // Said in the album artist
+            ("/music/Hits/a.mp3", track("Hits", "Madonna", Some("Various Artists"), false)),
+            ("/music/Hits/b.mp3", track("Hits", "Prince", Some("VA"), false)),
+        ]);
+
+        assert_eq!(
+            albums,
+            [
+                ("Hits — Various Artists".to_string(), 2),
+                ("Now 1 — Various Artists".to_string(), 2),
+                ("Pulp Fiction — Various Artists".to_string(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn tracks_go_in_disc_then_track_order() {
+        let numbered = |disc, number| TrackInfo { disc_number: disc, track_number: number, ..track("Set", "Band", None, false) };
+        let tracks = [
+            ("/music/Set/a.mp3", numbered(Some(2), Some(1))),
+            ("/music/Set/b.mp3", numbered(Some(1), Some(10))),
+            ("/music/Set/c.mp3", numbered(None, None)),
+            ("/music/Set/d.mp3", numbered(Some(1), Some(2))),
+            ("/music/Set/e.mp3", numbered(None, Some(1))),
+        ];
+        let files: Vec<PathBuf> = tracks.iter().map(|(file_path, _)| PathBuf::from(file_path)).collect();
+        let track_info: HashMap<PathBuf, TrackInfo> = tracks.into_iter().map(|(file_path, info)| (PathBuf::from(file_path), info)).collect();
+
+        let albums = group_albums(&files, &track_info);
+        let names: Vec<_> = albums[0].tracks.iter().map(|file_path| file_path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["e.mp3", "d.mp3", "b.mp3", "c.mp3", "a.mp3"]);
+        assert_eq!(discs(&albums[0], &track_info), [1, 2]);
+    }
+
+    #[test]
+    fn composers_keep_works_together() {
+        let movement = |composer: &str, work: Option<&str>, number| TrackInfo {
+            composer: Some(composer.to_string()),
+            work: work.map(str::to_string),
+            track_number: Some(number),
+            ..Default::default()
+        };
+        let tracks = [
+            ("/music/Mixed/1.mp3", movement("Dvořák", Some("Symphony No. 9"), 2)),
+            ("/music/Mixed/2.mp3", movement("Bach", Some("Cello Suite No. 1"), 1)),
+            ("/music/Mixed/3.mp3", movement("dvořák", None, 3)),
+            ("/music/Mixed/4.mp3", movement("Dvořák", Some("Symphony No. 9"), 1)),
+            ("/music/Mixed/5.mp3", movement("Dvořák", Some("Humoresque No. 7"), 9)),
+            ("/music/Mixed/6.mp3", TrackInfo::default()),
+        ];
+        let files: Vec<PathBuf> = tracks.iter().map(|(file_path, _)| PathBuf::from(file_path)).collect();
+        let track_info: HashMap<PathBuf, TrackInfo> = tracks.into_iter().map(|(file_path, info)| (PathBuf::from(file_path), info)).collect();
+
+        let composers = group_composers(&files, &track_info);
+        let summary: Vec<(&str, Vec<&str>)> = composers
+            .iter()
+            .map(|composer| (composer.name.as_str(), composer.tracks.iter().map(|file_path| file_path.file_name().unwrap().to_str().unwrap()).collect()))
+            .collect();
+        assert_eq!(summary, [("Bach", vec!["2.mp3"]), ("Dvořák", vec!["5.mp3", "4.mp3", "1.mp3", "3.mp3"])]);
+    }
+
+    #[test]
+    fn artists_list_their_tracks_by_album() {
+        let song = |artist: &str, album: Option<&str>, number| TrackInfo {
+            artist: Some(artist.to_string()),
+            album: album.map(str::to_string),
+            track_number: Some(number),
+            ..Default::default()
+        };
+        let tracks = [
+            ("/music/1.mp3", song("The Beatles", Some("Revolver"), 2)),
+            ("/music/2.mp3", song("the beatles", None, 1)),
+            ("/music/3.mp3", song("ABBA", Some("Arrival"), 1)),
+            ("/music/4.mp3", song("The Beatles", Some("Abbey Road"), 5)),
+            ("/music/5.mp3", song("The Beatles", Some("Revolver"), 1)),
+        ];
+        let files: Vec<PathBuf> = tracks.iter().map(|(file_path, _)| PathBuf::from(file_path)).collect();
+        let track_info: HashMap<PathBuf, TrackInfo> = tracks.into_iter().map(|(file_path, info)| (PathBuf::from(file_path), info)).collect();
+
+        let artists = group_artists(&files, &track_info);
+        let summary: Vec<(&str, Vec<&str>)> = artists
+            .iter()
+            .map(|artist| (artist.name.as_str(), artist.tracks.iter().map(|file_path| file_path.file_name().unwrap().to_str().unwrap()).collect()))
+            .collect();
+        assert_eq!(summary, [("ABBA", vec!["3.mp3"]), ("The Beatles", vec!["4.mp3", "5.mp3", "1.mp3", "2.mp3"])]);
+    }
+
+    #[test]
+    fn albums_go_by_album_artist() {
+        let albums = group(vec![
+            ("/music/Thriller/1.mp3", track("Thriller", "Michael Jackson", Some("Michael Jackson"), false)),
+            ("/music/Thriller/2.mp3", track("Thriller", "Michael Jackson feat. Paul McCartney", Some("Michael Jackson"), false)),
+            ("/music/Greatest Hits/1.mp3", track("Greatest Hits", "Queen", None, false)),
+            ("/music/Greatest Hits (ABBA)/1.mp3", track("Greatest Hits", "ABBA", None, false)),
+            ("/music/loose.mp3", TrackInfo { title: Some("No album".to_string()), ..Default::default() }),
+        ]);
+
+        assert_eq!(
+            albums,
+            [
+                ("Greatest Hits — ABBA".to_string(), 1),
+                ("Thriller — Michael Jackson".to_string(), 2),
+                ("Greatest Hits — Queen".to_string(), 1),
+            ]
+        );
+    }
+}
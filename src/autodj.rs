@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::database::LibraryDb;
+use crate::metadata::TrackInfo;
+
+// Top the queue up whenever fewer than this many tracks are waiting
+pub const QUEUE_TARGET: usize = 3;
+
+/// How Auto-DJ decides what fits after the last track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutoDjRule {
+    #[default]
+    SameArtist,
+    SameGenre,
+    SameDecade,
+    LeastRecentlyPlayed,
+}
+
+impl AutoDjRule {
+    pub const ALL: [AutoDjRule; 4] =
+        [AutoDjRule::SameArtist, AutoDjRule::SameGenre, AutoDjRule::SameDecade, AutoDjRule::LeastRecentlyPlayed];
+
+    fn fits(self, seed: Option<&TrackInfo>, candidate: Option<&TrackInfo>) -> bool {
+        let (Some(seed), Some(candidate)) = (seed, candidate) else {
+            return self == AutoDjRule::LeastRecentlyPlayed;
+        };
+        let same = |a: &Option<String>, b: &Option<String>| matches!((a, b), (Some(a), Some(b)) if a.eq_ignore_ascii_case(b));
+        match self {
+            AutoDjRule::SameArtist => same(&seed.artist, &candidate.artist),
+            AutoDjRule::SameGenre => same(&seed.genre, &candidate.genre),
+            AutoDjRule::SameDecade => matches!((seed.year, candidate.year), (Some(a), Some(b)) if a / 10 == b / 10),
+            AutoDjRule::LeastRecentlyPlayed => true,
+        }
+    }
+}
+
+impl std::fmt::Display for AutoDjRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AutoDjRule::SameArtist => "Same artist",
+            AutoDjRule::SameGenre => "Same genre",
+            AutoDjRule::SameDecade => "Same decade",
+            AutoDjRule::LeastRecentlyPlayed => "Least recently played",
+        })
+    }
+}
+
+/// Chooses up to `count` tracks from `files` to follow `seed`. Tracks that fit
+/// `rule` come first, and when there aren't enough of those the rest of the
+/// library fills in. Either way the ones not heard for longest win, so a party
+/// doesn't keep coming back to the same songs. Nothing in `skip` is picked.
+pub fn pick_tracks(
+    rule: AutoDjRule,
+    seed: Option<&Path>,
+    files: &[PathBuf],
+    track_info: &HashMap<PathBuf, TrackInfo>,
+    db: &LibraryDb,
+    skip: &HashSet<&Path>,
+    count: usize,
+) -> Vec<PathBuf> {
+    let seed_info = seed.and_then(|seed| track_info.get(seed));
+    let mut candidates: Vec<&PathBuf> = files.iter().filter(|file_path| !skip.contains(file_path.as_path())).collect();
+    // Shuffle first so the sort, which is stable, breaks ties at random
+    fastrand::shuffle(&mut candidates);
+    // Never played sorts before played, then oldest first
+    candidates.sort_by_key(|file_path| db.tracks.get(*file_path).and_then(|record| record.last_played));
+    let (mut picks, others): (Vec<_>, Vec<_>) =
+        candidates.into_iter().partition(|file_path| rule.fits(seed_info, track_info.get(*file_path)));
+    picks.extend(others);
+    picks.into_iter().take(count).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn info(artist: &str, genre: &str, year: u32) -> TrackInfo {
+        TrackInfo { artist: Some(artist.to_string()), genre: Some(genre.to_string()), year: Some(year), ..Default::default() }
+    }
+
+    fn library() -> (Vec<PathBuf>, HashMap<PathBuf, TrackInfo>) {
+        let tracks = [
+            ("/music/seed.mp3", info("Blondie", "New Wave", 1979)),
+            ("/music/heart.mp3", info("blondie", "New Wave", 1978)),
+            ("/music/call.mp3", info("Blondie", "Disco", 1980)),
+            ("/music/tainted.mp3", info("Soft Cell", "Synth-pop", 1981)),
+            ("/music/hold.mp3", info("Ramones", "Punk", 1976)),
+        ];
+        let files = tracks.iter().map(|(file_path, _)| PathBuf::from(file_path)).collect();
+        (files, tracks.into_iter().map(|(file_path, info)| (PathBuf::from(file_path), info)).collect())
+    }
+
+    fn played(db: &mut LibraryDb, file_path: &str, minutes_ago: u64) {
+        let record = db.tracks.entry(PathBuf::from(file_path)).or_default();
+        record.last_played = Some(SystemTime::now() - Duration::from_secs(minutes_ago * 60));
+    }
+
+    #[test]
+    fn prefers_tracks_that_fit_the_rule() {
+        let (files, track_info) = library();
+        let seed = Path::new("/music/seed.mp3");
+        let skip = HashSet::from([seed]);
+        let db = LibraryDb::default();
+
+        let mut artist = pick_tracks(AutoDjRule::SameArtist, Some(seed), &files, &track_info, &db, &skip, 2);
+        artist.sort();
+        assert_eq!(artist, [PathBuf::from("/music/call.mp3"), PathBuf::from("/music/heart.mp3")]);
+        let genre = pick_tracks(AutoDjRule::SameGenre, Some(seed), &files, &track_info, &db, &skip, 1);
+        assert_eq!(genre, [PathBuf::from("/music/heart.mp3")]);
+        let mut decade = pick_tracks(AutoDjRule::SameDecade, Some(seed), &files, &track_info, &db, &skip, 2);
+        decade.sort();
+        assert_eq!(decade, [PathBuf::from("/music/heart.mp3"), PathBuf::from("/music/hold.mp3")]);
+    }
+
+    #[test]
+    fn least_recently_played_comes_first() {
+        let (files, track_info) = library();
+        let mut db = LibraryDb::default();
+        for (file_path, minutes_ago) in [("/music/seed.mp3", 5), ("/music/heart.mp3", 60), ("/music/call.mp3", 600), ("/music/tainted.mp3", 1)] {
+            played(&mut db, file_path, minutes_ago);
+        }
+
+        let picks = pick_tracks(AutoDjRule::LeastRecentlyPlayed, None, &files, &track_info, &db, &HashSet::new(), 5);
+        let expected = ["/music/hold.mp3", "/music/call.mp3", "/music/heart.mp3", "/music/seed.mp3", "/music/tainted.mp3"];
+        assert_eq!(picks, expected.map(PathBuf::from));
+    }
+
+    #[test]
+    fn falls_back_when_nothing_fits() {
+        let (files, track_info) = library();
+        let seed = Path::new("/music/hold.mp3");
+        let picks = pick_tracks(AutoDjRule::SameArtist, Some(seed), &files, &track_info, &LibraryDb::default(), &HashSet::from([seed]), 10);
+        assert_eq!(picks.len(), 4);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+
+use crate::database::LibraryDb;
+use crate::settings::Settings;
+
+const SETTINGS_ENTRY: &str = "settings.toml";
+const LIBRARY_ENTRY: &str = "library.json";
+
+/// Settings and the library database, as saved in a backup archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub settings: Settings,
+    pub db: LibraryDb,
+}
+
+impl Backup {
+    /// Where the library lived on the machine the backup was made on.
+    pub fn library_root(&self) -> Option<&Path> {
+        self.settings.library_folders.first().map(PathBuf::as_path)
+    }
+
+    /// Moves every path under `from` to the same place under `to`, for restoring
+    /// onto a machine where the music lives somewhere else.
+    pub fn remap(&mut self, from: &Path, to: &Path) {
+        let remap = |file_path: &Path| match file_path.strip_prefix(from) {
+            Ok(relative) => to.join(relative),
+            Err(_) => file_path.to_path_buf(),
+        };
+        self.db.tracks = std::mem::take(&mut self.db.tracks).into_iter().map(|(file_path, record)| (remap(&file_path), record)).collect();
+        for playlist in &mut self.db.playlists {
+            playlist.tracks.iter_mut().for_each(|file_path| *file_path = remap(file_path));
+        }
+        self.db.queue.iter_mut().for_each(|file_path| *file_path = remap(file_path));
+        for snapshot in &mut self.db.queue_snapshots {
+            snapshot.tracks.iter_mut().for_each(|file_path| *file_path = remap(file_path));
+        }
+        self.db.history.iter_mut().for_each(|play| play.file_path = remap(&play.file_path));
+        self.settings.library_folders.iter_mut().for_each(|folder| *folder = remap(folder));
+        self.settings.recent_folders.iter_mut().for_each(|folder| *folder = remap(folder));
+        self.settings.audiobooks.folders.iter_mut().for_each(|folder| *folder = remap(folder));
+        if let Some(destination) = &mut self.settings.convert.destination {
+            *destination = remap(destination);
+        }
+    }
+}
+
+/// Writes `settings` and `db` into a zip archive at `path`.
+#[tracing::instrument(skip(settings, db), fields(path = %path.display()))]
+pub fn write_backup(path: &Path, settings: &Settings, db: &LibraryDb) -> Result<(), String> {
+    let settings = toml::to_string_pretty(settings).map_err(|e| format!("Failed to back up settings: {}", e))?;
+    let db = serde_json::to_vec_pretty(db).map_err(|e| format!("Failed to back up the library: {}", e))?;
+
+    let file = fs::File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut archive = zip::ZipWriter::new(file);
+    let written = archive
+        .start_file(SETTINGS_ENTRY, SimpleFileOptions::default())
+        .and_then(|_| Ok(archive.write_all(settings.as_bytes())?))
+        .and_then(|_| archive.start_file(LIBRARY_ENTRY, SimpleFileOptions::default()))
+        .and_then(|_| Ok(archive.write_all(&db)?))
+        .and_then(|_| archive.finish());
+    written.map(|_| ()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[tracing::instrument(fields(path = %path.display()))]
+pub fn read_backup(path: &Path) -> Result<Backup, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| format!("{} isn't a backup: {}", display(path), e))?;
+    let mut read_entry = |name: &str| -> Result<String, String> {
+        let mut contents = String::new();
+        archive
+            .by_name(name)
+            .map_err(|_| format!("{} isn't a backup: it has no {}", display(path), name))?
+            .read_to_string(&mut contents)
+            .map_err(|e| format!("Failed to read {} from {}: {}", name, display(path), e))?;
+        Ok(contents)
+    };
+    let settings = read_entry(SETTINGS_ENTRY)?;
+    let db = read_entry(LIBRARY_ENTRY)?;
+    Ok(Backup {
+        settings: toml::from_str(&settings).map_err(|e| format!("The settings in the backup are invalid: {}", e))?,
+        db: serde_json::from_str(&db).map_err(|e| format!("The library in the backup is invalid: {}", e))?,
+    })
+}
+
+fn display(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Bookmark, Playlist};
+    use crate::settings::ThemeChoice;
+    use std::time::Duration;
+
+    fn backup() -> Backup {
+        let settings = Settings { library_folders: vec![PathBuf::from("/Users/sam/Music")], theme: ThemeChoice::Dark, ..Default::default() };
+        let mut db = LibraryDb::default();
+        let song = PathBuf::from("/Users/sam/Music/Album/song.mp3");
+        db.add_bookmark(&song, Bookmark { name: "Solo".to_string(), position: Duration::from_secs(95) });
+        db.set_playlist(Playlist { name: "Mix".to_string(), tracks: vec![song, PathBuf::from("/Volumes/USB/other.mp3")] });
+        Backup { settings, db }
+    }
+
+    #[test]
+    fn round_trips_through_an_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.zip");
+        let backup = backup();
+
+        write_backup(&path, &backup.settings, &backup.db).unwrap();
+        assert_eq!(read_backup(&path).unwrap(), backup);
+    }
+
+    #[test]
+    fn leaves_out_the_jellyfin_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.zip");
+        let mut backup = backup();
+        backup.settings.jellyfin.user_id = "5d1c".to_string();
+        backup.settings.jellyfin.token = "b2f0".to_string();
+
+        write_backup(&path, &backup.settings, &backup.db).unwrap();
+        let mut archive = zip::ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+        let mut settings = String::new();
+        archive.by_name(SETTINGS_ENTRY).unwrap().read_to_string(&mut settings).unwrap();
+        assert!(settings.contains("5d1c") && !settings.contains("b2f0"));
+        assert_eq!(read_backup(&path).unwrap().settings.jellyfin.token, "");
+    }
+
+    #[test]
+    fn remaps_the_library_root() {
+        let mut backup = backup();
+        backup.remap(Path::new("/Users/sam/Music"), Path::new("/home/sam/music"));
+
+        let song = PathBuf::from("/home/sam/music/Album/song.mp3");
+        assert_eq!(backup.library_root(), Some(Path::new("/home/sam/music")));
+        assert_eq!(backup.db.tracks[&song].bookmarks[0].name, "Solo");
+        // Anything outside the old root is left alone
+        assert_eq!(backup.db.playlists[0].tracks, [song, PathBuf::from("/Volumes/USB/other.mp3")]);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.zip");
+        fs::write(&path, b"not a zip").unwrap();
+        assert!(read_backup(&path).is_err());
+
+        let file = fs::File::create(&path).unwrap();
+        let mut archive = zip::ZipWriter::new(file);
+        archive.start_file("readme.txt", SimpleFileOptions::default()).unwrap();
+        archive.finish().unwrap();
+        assert!(read_backup(&path).unwrap_err().contains("no settings.toml"));
+    }
+}
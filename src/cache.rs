@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A 64-bit FNV-1a hash of `bytes`. Unlike the standard library's hasher it
+/// never changes between Rust releases, so it's safe to name files on disk by.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Marks `file_path` as just used, so it's the last to go when its folder's pruned.
+pub fn touch(file_path: &Path) {
+    let _ = fs::File::options().write(true).open(file_path).and_then(|file| file.set_modified(SystemTime::now()));
+}
+
+/// Deletes files from `dir`, least recently used first, until they take up no
+/// more than `limit` bytes. `keep` stays whatever its age, as do .part files
+/// still being written.
+pub fn prune(dir: &Path, limit: u64, keep: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok().filter(|metadata| metadata.is_file())?;
+            Some((metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), metadata.len(), entry.path()))
+        })
+        .filter(|(_, _, file_path)| file_path.extension().is_none_or(|extension| extension != "part"))
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    files.sort();
+    for (_, size, file_path) in files {
+        if total <= limit {
+            break;
+        }
+        if file_path == keep {
+            continue;
+        }
+        match fs::remove_file(&file_path) {
+            Ok(()) => total -= size,
+            Err(e) => tracing::warn!(path = %file_path.display(), "Failed to delete from the cache: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn hashes_the_same_everywhere() {
+        assert_eq!(stable_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn prunes_the_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, age: u64| {
+            let file_path = dir.path().join(name);
+            fs::write(&file_path, [0; 100]).unwrap();
+            let file = fs::File::options().write(true).open(&file_path).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(age)).unwrap();
+            file_path
+        };
+        let oldest = write("a.flac", 300);
+        let kept = write("b.flac", 200);
+        let newer = write("c.transcoded.mp3", 100);
+        let partial = write("d.part", 400);
+        let used = write("e.png", 500);
+        touch(&used);
+
+        prune(dir.path(), 250, &kept);
+        assert!(!oldest.exists() && !newer.exists());
+        assert!(kept.exists() && partial.exists() && used.exists());
+    }
+}
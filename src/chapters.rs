@@ -0,0 +1,355 @@
+use lofty::{ItemKey, TaggedFileExt};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+// Cue sheet positions are in CD frames
+const CUE_FRAMES_PER_SECOND: u64 = 75;
+
+/// A titled point in a track: a chapter of an audiobook, or a song in a
+/// recording of a whole album or concert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+}
+
+impl std::fmt::Display for Chapter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.title)
+    }
+}
+
+/// The chapters of `file_path`, in order, from the first place that has any: a
+/// cue sheet next to it with the same name, a cue sheet in its tags (usual for
+/// FLAC), then ID3v2 chapter frames in MP3s or Nero chapters in MP4s. Empty if
+/// there are none.
+pub fn read_chapters(file_path: &Path) -> Result<Vec<Chapter>, String> {
+    let file_name = file_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    if let Ok(sheet) = fs::read(file_path.with_extension("cue")) {
+        let chapters = parse_cue_sheet(&String::from_utf8_lossy(&sheet), &file_name);
+        if !chapters.is_empty() {
+            return Ok(chapters);
+        }
+    }
+    let tagged = lofty::read_from_path(file_path).map_err(|e| format!("Failed to read tags: {}", e))?;
+    let embedded = tagged.tags().iter().flat_map(|tag| tag.items()).find_map(|item| match item.key() {
+        ItemKey::Unknown(key) if key.eq_ignore_ascii_case("CUESHEET") => item.value().text(),
+        _ => None,
+    });
+    if let Some(sheet) = embedded {
+        let chapters = parse_cue_sheet(sheet, &file_name);
+        if !chapters.is_empty() {
+            return Ok(chapters);
+        }
+    }
+    let mut file = fs::File::open(file_path).map_err(|e| format!("Failed to open the file: {}", e))?;
+    let extension = file_path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    let chapters = match extension.to_ascii_lowercase().as_str() {
+        "mp3" => read_id3v2(&mut file).map(|tag| id3v2_chapters(&tag)),
+        "m4a" | "m4b" => read_mp4_moov(&mut file).map(|moov| mp4_chapters(&moov)),
+        _ => return Ok(Vec::new()),
+    };
+    chapters.map_err(|e| format!("Failed to read chapters: {}", e))
+}
+
+/// Which of `chapters` `position` is in.
+pub fn chapter_at(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    chapters.iter().rposition(|chapter| chapter.start <= position)
+}
+
+/// A TRACK of a cue sheet, and the FILE it's in.
+struct CueTrack {
+    file: Option<String>,
+    number: u32,
+    title: Option<String>,
+    start: Option<Duration>,
+}
+
+/// The tracks of a cue sheet that belong to `file_name`. A sheet naming a
+/// single file is taken to be for this one even if it's since been renamed.
+fn parse_cue_sheet(sheet: &str, file_name: &str) -> Vec<Chapter> {
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    let mut file = None;
+    for line in sheet.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => file = Some(unquote(rest.rsplit_once(char::is_whitespace).map_or(rest, |(name, _)| name))),
+            "TRACK" => {
+                let number = rest.split_whitespace().next().and_then(|number| number.parse().ok()).unwrap_or(0);
+                tracks.push(CueTrack { file: file.clone(), number, title: None, start: None });
+            }
+            "TITLE" => {
+                // A title before the first track is the album's
+                if let Some(track) = tracks.last_mut() {
+                    track.title = Some(unquote(rest));
+                }
+            }
+            "INDEX" => {
+                let mut fields = rest.split_whitespace();
+                if fields.next() == Some("01")
+                    && let (Some(track), Some(start)) = (tracks.last_mut(), fields.next().and_then(parse_cue_time))
+                {
+                    track.start = Some(start);
+                }
+            }
+            _ => {}
+        }
+    }
+    let named = |file: &Option<String>| file.as_deref().is_some_and(|file| file.eq_ignore_ascii_case(file_name));
+    let ours = tracks.iter().any(|track| named(&track.file));
+    let single_file = tracks.windows(2).all(|pair| pair[0].file == pair[1].file);
+    if !ours && !single_file {
+        return Vec::new();
+    }
+    let mut chapters: Vec<Chapter> = tracks
+        .into_iter()
+        .filter(|track| !ours || named(&track.file))
+        .filter_map(|track| {
+            Some(Chapter { title: track.title.unwrap_or_else(|| format!("Track {}", track.number)), start: track.start? })
+        })
+        .collect();
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value).to_string()
+}
+
+/// Reads a cue sheet's "mm:ss:ff".
+fn parse_cue_time(time: &str) -> Option<Duration> {
+    let mut fields = time.split(':').map(|field| field.parse::<u64>().ok());
+    let (Some(Some(minutes)), Some(Some(seconds)), Some(Some(frames)), None) = (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return None;
+    };
+    let frames = (minutes * 60 + seconds) * CUE_FRAMES_PER_SECOND + frames;
+    Some(Duration::from_millis(frames * 1000 / CUE_FRAMES_PER_SECOND))
+}
+
+/// The ID3v2 tag at the start of an MP3, header included, or nothing if it hasn't one.
+fn read_id3v2(file: &mut fs::File) -> std::io::Result<Vec<u8>> {
+    let mut header = [0u8; 10];
+    if file.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        return Ok(Vec::new());
+    }
+    let mut tag = header.to_vec();
+    file.take(synchsafe(&header[6..10]) as u64).read_to_end(&mut tag)?;
+    Ok(tag)
+}
+
+/// The CHAP frames of an ID3v2.3 or 2.4 tag. Chapters without a title are numbered.
+fn id3v2_chapters(tag: &[u8]) -> Vec<Chapter> {
+    if tag.len() < 10 || &tag[..3] != b"ID3" {
+        return Vec::new();
+    }
+    let (version, flags) = (tag[3], tag[5]);
+    if version != 3 && version != 4 {
+        return Vec::new();
+    }
+    let mut body = &tag[10..];
+    if flags & 0x40 != 0 {
+        // An extended header, sized without its size field in 2.3 and with it in 2.4
+        let size = match (version, body.get(..4)) {
+            (3, Some(size)) => u32::from_be_bytes(size.try_into().unwrap()) as usize + 4,
+            (_, Some(size)) => synchsafe(size) as usize,
+            (_, None) => return Vec::new(),
+        };
+        body = body.get(size..).unwrap_or_default();
+    }
+    let mut chapters: Vec<Chapter> = id3v2_frames(body, version)
+        .filter(|(id, _)| id == b"CHAP")
+        .filter_map(|(_, frame)| {
+            let element_end = frame.iter().position(|&byte| byte == 0)?;
+            let times = frame.get(element_end + 1..element_end + 17)?;
+            let start = Duration::from_millis(u32::from_be_bytes(times[..4].try_into().unwrap()) as u64);
+            let title = id3v2_frames(&frame[element_end + 17..], version).find(|(id, _)| id == b"TIT2").and_then(|(_, text)| decode_id3v2_text(text));
+            Some(Chapter { title: title.unwrap_or_default(), start })
+        })
+        .collect();
+    chapters.sort_by_key(|chapter| chapter.start);
+    for (index, chapter) in chapters.iter_mut().enumerate() {
+        if chapter.title.is_empty() {
+            chapter.title = format!("Chapter {}", index + 1);
+        }
+    }
+    chapters
+}
+
+/// The ID and contents of each frame in `frames`, up to the padding.
+fn id3v2_frames(mut frames: &[u8], version: u8) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let header = frames.get(..10)?;
+        if header[0] == 0 {
+            return None;
+        }
+        let size = if version == 4 { synchsafe(&header[4..8]) } else { u32::from_be_bytes(header[4..8].try_into().unwrap()) } as usize;
+        let id = header[..4].try_into().unwrap();
+        let contents = frames.get(10..10 + size)?;
+        frames = &frames[10 + size..];
+        Some((id, contents))
+    })
+}
+
+/// A text frame's value, in whichever of ID3v2's encodings it uses.
+fn decode_id3v2_text(frame: &[u8]) -> Option<String> {
+    let (&encoding, text) = frame.split_first()?;
+    let utf16 = |text: &[u8], big_endian: bool| {
+        let units: Vec<u16> = text
+            .chunks_exact(2)
+            .map(|pair| if big_endian { u16::from_be_bytes([pair[0], pair[1]]) } else { u16::from_le_bytes([pair[0], pair[1]]) })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    let text = match encoding {
+        0 => text.iter().map(|&byte| byte as char).collect(),
+        1 => match text {
+            [0xFE, 0xFF, rest @ ..] => utf16(rest, true),
+            [0xFF, 0xFE, rest @ ..] => utf16(rest, false),
+            _ => utf16(text, false),
+        },
+        2 => utf16(text, true),
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+    Some(text.trim_end_matches('\0').to_string()).filter(|text| !text.is_empty())
+}
+
+fn synchsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |size, &byte| (size << 7) | (byte & 0x7F) as u32)
+}
+
+/// The contents of an MP4's moov atom, wherever in the file it is.
+fn read_mp4_moov(file: &mut fs::File) -> std::io::Result<Vec<u8>> {
+    let length = file.metadata()?.len();
+    let mut position = 0;
+    while position + 8 <= length {
+        file.seek(SeekFrom::Start(position))?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let (mut size, mut header_size) = (u32::from_be_bytes(header[..4].try_into().unwrap()) as u64, 8);
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large)?;
+            (size, header_size) = (u64::from_be_bytes(large), 16);
+        } else if size == 0 {
+            size = length - position;
+        }
+        if size < header_size {
+            break;
+        }
+        if &header[4..] == b"moov" {
+            let mut moov = Vec::new();
+            file.take(size - header_size).read_to_end(&mut moov)?;
+            return Ok(moov);
+        }
+        position += size;
+    }
+    Ok(Vec::new())
+}
+
+/// Nero-style chapters, from moov/udta/chpl, laid out the way FFmpeg and
+/// mp4v2 write them.
+fn mp4_chapters(moov: &[u8]) -> Vec<Chapter> {
+    let Some(chpl) = mp4_child(moov, b"udta").and_then(|udta| mp4_child(udta, b"chpl")) else {
+        return Vec::new();
+    };
+    let Some(&version) = chpl.first() else { return Vec::new() };
+    let mut rest = chpl.get(if version == 0 { 4 } else { 8 }..).unwrap_or_default();
+    let Some((&count, entries)) = rest.split_first() else { return Vec::new() };
+    rest = entries;
+    let mut chapters = Vec::new();
+    for _ in 0..count {
+        let Some((start, rest_after)) = rest.split_first_chunk::<8>() else { break };
+        let Some((&title_len, rest_after)) = rest_after.split_first() else { break };
+        let Some(title) = rest_after.get(..title_len as usize) else { break };
+        // In units of 100 nanoseconds
+        let start = Duration::from_nanos(u64::from_be_bytes(*start).saturating_mul(100));
+        chapters.push(Chapter { title: String::from_utf8_lossy(title).into_owned(), start });
+        rest = &rest_after[title_len as usize..];
+    }
+    chapters
+}
+
+/// The contents of the first atom of kind `kind` directly inside `atoms`.
+fn mp4_child<'a>(mut atoms: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    while let Some(header) = atoms.get(..8) {
+        let size = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        if size < 8 {
+            return None;
+        }
+        let contents = atoms.get(8..size)?;
+        if &header[4..] == kind {
+            return Some(contents);
+        }
+        atoms = &atoms[size..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(title: &str, milliseconds: u64) -> Chapter {
+        Chapter { title: title.to_string(), start: Duration::from_millis(milliseconds) }
+    }
+
+    #[test]
+    fn reads_the_tracks_of_a_cue_sheet_for_this_file() {
+        let sheet = r#"
+            PERFORMER "Pink Floyd"
+            TITLE "Live at Pompeii"
+            FILE "pompeii.flac" WAVE
+              TRACK 01 AUDIO
+                TITLE "Echoes, Part 1"
+                INDEX 01 00:00:00
+              TRACK 02 AUDIO
+                INDEX 00 12:30:00
+                INDEX 01 12:32:30
+            FILE "other.flac" WAVE
+              TRACK 03 AUDIO
+                TITLE "Elsewhere"
+                INDEX 01 00:00:00
+        "#;
+        assert_eq!(parse_cue_sheet(sheet, "pompeii.flac"), [chapter("Echoes, Part 1", 0), chapter("Track 2", 752_400)]);
+        // Two files and neither is this one
+        assert!(parse_cue_sheet(sheet, "renamed.flac").is_empty());
+        assert_eq!(chapter_at(&parse_cue_sheet(sheet, "pompeii.flac"), Duration::from_secs(800)), Some(1));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("renamed.mp3");
+        fs::write(&path, b"not really an mp3").unwrap();
+        fs::write(dir.path().join("renamed.cue"), "FILE \"album.wav\" WAVE\nTRACK 01 AUDIO\nTITLE \"Only\"\nINDEX 01 00:01:00\n").unwrap();
+        assert_eq!(read_chapters(&path).unwrap(), [chapter("Only", 1000)]);
+    }
+
+    #[test]
+    fn reads_id3v2_chapter_frames() {
+        let frame = |id: &[u8], contents: &[u8]| [id, &(contents.len() as u32).to_be_bytes(), &[0, 0], contents].concat();
+        let chap = |element: &str, start_ms: u32, title: Option<&str>| {
+            let mut contents = [element.as_bytes(), &[0], &start_ms.to_be_bytes(), &[0; 4], &[0xFF; 8]].concat();
+            if let Some(title) = title {
+                contents.extend(frame(b"TIT2", &[&[3], title.as_bytes()].concat()));
+            }
+            frame(b"CHAP", &contents)
+        };
+        let frames = [chap("ch1", 90_000, None), chap("ch0", 0, Some("Prologue")), frame(b"TIT2", b"\x00Book")].concat();
+        let tag = [b"ID3\x03\x00\x00".as_slice(), &[0, 0, 0, frames.len() as u8], &frames].concat();
+
+        assert_eq!(id3v2_chapters(&tag), [chapter("Prologue", 0), chapter("Chapter 2", 90_000)]);
+    }
+
+    #[test]
+    fn reads_nero_chapters_from_mp4() {
+        let atom = |kind: &[u8], contents: &[u8]| [&(contents.len() as u32 + 8).to_be_bytes(), kind, contents].concat();
+        let entry = |seconds: u64, title: &str| [&(seconds * 10_000_000).to_be_bytes()[..], &[title.len() as u8], title.as_bytes()].concat();
+        let chpl = [&[1, 0, 0, 0, 0, 0, 0, 0, 2][..], &entry(0, "One"), &entry(300, "Two")].concat();
+        let moov = [atom(b"mvhd", &[0; 4]), atom(b"udta", &atom(b"chpl", &chpl))].concat();
+
+        assert_eq!(mp4_chapters(&moov), [chapter("One", 0), chapter("Two", 300_000)]);
+    }
+}
@@ -0,0 +1,98 @@
+use icu_normalizer::DecomposingNormalizer;
+use std::cmp::Ordering;
+
+/// Compares names the way people read them rather than by code point: numbers
+/// by value, so "Track 2" comes before "Track 10", and case, accents and
+/// full-width forms ignored. Hiragana and katakana sort together, as a Japanese
+/// index would have them. Kanji and hanzi stay in code point order, since sorting
+/// those by reading needs dictionaries this doesn't carry. Names that only differ
+/// in the ignored ways are ordered by their text so the result is stable.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    sort_key(a).cmp(&sort_key(b)).then_with(|| a.cmp(b))
+}
+
+/// What [`natural_cmp`] compares, for sorting with a cached key.
+pub fn sort_key(name: &str) -> Vec<Token> {
+    let folded = DecomposingNormalizer::new_nfkd().normalize(name);
+    let mut tokens = Vec::new();
+    let mut chars = folded.chars().filter(|c| !is_mark(*c)).flat_map(char::to_lowercase).map(katakana_to_hiragana).peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_ascii_digit() {
+            tokens.push(Token::Char(c));
+            continue;
+        }
+        let mut digits = String::from(c);
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            digits.push(digit);
+        }
+        // Leading zeros don't change the value, so "07" and "7" sort together
+        let digits = digits.trim_start_matches('0');
+        tokens.push(Token::Number(digits.to_string()));
+    }
+    tokens
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Char(char),
+    Number(String), // Digits without leading zeros
+}
+
+impl Ord for Token {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Token::Char(a), Token::Char(b)) => a.cmp(b),
+            (Token::Number(a), Token::Number(b)) => a.len().cmp(&b.len()).then_with(|| a.cmp(b)),
+            // Numbers sit where the digits would, after spaces and punctuation and before letters
+            (Token::Number(_), Token::Char(c)) => '0'.cmp(c).then(Ordering::Less),
+            (Token::Char(c), Token::Number(_)) => c.cmp(&'0').then(Ordering::Greater),
+        }
+    }
+}
+
+impl PartialOrd for Token {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Accents and the kana voicing marks left over from decomposition.
+fn is_mark(c: char) -> bool {
+    matches!(c, '\u{0300}'..='\u{036F}' | '\u{3099}'..='\u{309A}')
+}
+
+fn katakana_to_hiragana(c: char) -> char {
+    match c {
+        '\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn numbers_sort_by_value() {
+        assert_eq!(
+            sorted(&["Track 10.mp3", "Track 2.mp3", "track 1.mp3", "Track 02b.mp3", "Track.mp3", "Track 100.mp3"]),
+            ["track 1.mp3", "Track 2.mp3", "Track 02b.mp3", "Track 10.mp3", "Track 100.mp3", "Track.mp3"]
+        );
+    }
+
+    #[test]
+    fn ignores_accents_width_and_kana_script() {
+        assert_eq!(sorted(&["Zoë", "Émilie", "Eve"]), ["Émilie", "Eve", "Zoë"]);
+        // Full-width digits are numbers too
+        assert_eq!(sorted(&["第１０話", "第２話"]), ["第２話", "第１０話"]);
+        // Katakana interleaves with hiragana, and voiced kana sit with their plain forms
+        assert_eq!(sorted(&["さくら", "カラオケ", "がっこう", "あめ"]), ["あめ", "がっこう", "カラオケ", "さくら"]);
+        assert_eq!(natural_cmp("ﾃｽﾄ", "テスト"), "ﾃｽﾄ".cmp("テスト"));
+    }
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::database::TrackRecord;
+use crate::metadata::TrackInfo;
+
+/// Something the track list can show about each track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ListColumn {
+    Title,
+    Artist,
+    Album,
+    Composer,
+    Performer,
+    Work,
+    Duration,
+    Bitrate,
+    Year,
+    Rating,
+    PlayCount,
+    SkipCount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnSlot {
+    pub column: ListColumn,
+    pub shown: bool,
+}
+
+impl ListColumn {
+    /// Every column, in the order ones missing from the settings are added.
+    const ALL: [ListColumn; 12] = [
+        ListColumn::Title,
+        ListColumn::Artist,
+        ListColumn::Album,
+        ListColumn::Composer,
+        ListColumn::Performer,
+        ListColumn::Work,
+        ListColumn::Duration,
+        ListColumn::Bitrate,
+        ListColumn::Year,
+        ListColumn::Rating,
+        ListColumn::PlayCount,
+        ListColumn::SkipCount,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ListColumn::Title => "Title",
+            ListColumn::Artist => "Artist",
+            ListColumn::Album => "Album",
+            ListColumn::Composer => "Composer",
+            ListColumn::Performer => "Performer",
+            ListColumn::Work => "Work",
+            ListColumn::Duration => "Time",
+            ListColumn::Bitrate => "Bitrate",
+            ListColumn::Year => "Year",
+            ListColumn::Rating => "Rating",
+            ListColumn::PlayCount => "Plays",
+            ListColumn::SkipCount => "Skips",
+        }
+    }
+
+    /// Text columns share out the width; the rest are just wide enough.
+    pub fn is_text(self) -> bool {
+        matches!(
+            self,
+            ListColumn::Title | ListColumn::Artist | ListColumn::Album | ListColumn::Composer | ListColumn::Performer | ListColumn::Work
+        )
+    }
+
+    /// What goes in this column for `file_path`. Without a title tag the file
+    /// name stands in, so every row says something.
+    pub fn cell(self, file_path: &Path, info: Option<&TrackInfo>, record: Option<&TrackRecord>) -> String {
+        let text = |field: fn(&TrackInfo) -> &Option<String>| info.and_then(|info| field(info).clone()).unwrap_or_default();
+        match self {
+            ListColumn::Title => info
+                .and_then(|info| info.title.clone())
+                .unwrap_or_else(|| file_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()),
+            ListColumn::Artist => text(|info| &info.artist),
+            ListColumn::Album => text(|info| &info.album),
+            ListColumn::Composer => text(|info| &info.composer),
+            ListColumn::Performer => text(|info| &info.performer),
+            ListColumn::Work => info.map(work_and_movement).unwrap_or_default(),
+            ListColumn::Duration => info.and_then(|info| info.duration).map(format_duration).unwrap_or_default(),
+            ListColumn::Bitrate => info.and_then(|info| info.bitrate).map(|kbps| format!("{} kbps", kbps)).unwrap_or_default(),
+            ListColumn::Year => info.and_then(|info| info.year).map(|year| year.to_string()).unwrap_or_default(),
+            ListColumn::Rating => record.and_then(|record| record.rating).map(|stars| "★".repeat(stars.into())).unwrap_or_default(),
+            ListColumn::PlayCount => record.map(|record| record.play_count).filter(|&plays| plays > 0).map(|plays| plays.to_string()).unwrap_or_default(),
+            ListColumn::SkipCount => record.map(|record| record.skip_count).filter(|&skips| skips > 0).map(|skips| skips.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+/// "Work: Movement", or whichever of them is tagged.
+pub fn work_and_movement(info: &TrackInfo) -> String {
+    match (&info.work, &info.movement) {
+        (Some(work), Some(movement)) => format!("{}: {}", work, movement),
+        (Some(part), None) | (None, Some(part)) => part.clone(),
+        (None, None) => String::new(),
+    }
+}
+
+/// Title, artist, rating and time, as the list looked before columns could be chosen.
+pub fn default_columns() -> Vec<ColumnSlot> {
+    let shown = [ListColumn::Title, ListColumn::Artist, ListColumn::Rating, ListColumn::Duration];
+    let mut slots: Vec<ColumnSlot> = shown.iter().map(|&column| ColumnSlot { column, shown: true }).collect();
+    complete_columns(&mut slots);
+    slots
+}
+
+/// Adds any columns missing from `slots` to the end, hidden, so ones added in
+/// newer versions show up for settings saved by older ones.
+pub fn complete_columns(slots: &mut Vec<ColumnSlot>) {
+    for column in ListColumn::ALL {
+        if !slots.iter().any(|slot| slot.column == column) {
+            slots.push(ColumnSlot { column, shown: false });
+        }
+    }
+}
+
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn cells_show_tags_and_library_data() {
+        let info = TrackInfo {
+            title: Some("Symphony No. 9".to_string()),
+            composer: Some("Dvořák".to_string()),
+            performer: Some("Berliner Philharmoniker".to_string()),
+            work: Some("Symphony No. 9".to_string()),
+            movement: Some("IV. Allegro con fuoco".to_string()),
+            duration: Some(Duration::from_secs(2534)),
+            bitrate: Some(320),
+            year: Some(1893),
+            ..Default::default()
+        };
+        let record = TrackRecord { rating: Some(4), play_count: 12, skip_count: 2, ..Default::default() };
+        let file_path = Path::new("/music/04 Allegro con fuoco.flac");
+
+        let cells: Vec<String> = ListColumn::ALL.iter().map(|column| column.cell(file_path, Some(&info), Some(&record))).collect();
+        assert_eq!(cells[..3], ["Symphony No. 9", "", ""]);
+        assert_eq!(cells[3..6], ["Dvořák", "Berliner Philharmoniker", "Symphony No. 9: IV. Allegro con fuoco"]);
+        assert_eq!(cells[6..], ["42:14", "320 kbps", "1893", "★★★★", "12", "2"]);
+        assert_eq!(ListColumn::Title.cell(file_path, None, None), "04 Allegro con fuoco.flac");
+        assert_eq!(ListColumn::PlayCount.cell(file_path, None, Some(&TrackRecord::default())), "");
+    }
+
+    #[test]
+    fn missing_columns_are_added_hidden() {
+        let mut slots = vec![ColumnSlot { column: ListColumn::Year, shown: true }];
+        complete_columns(&mut slots);
+        assert_eq!(slots.len(), ListColumn::ALL.len());
+        assert_eq!(slots[0], ColumnSlot { column: ListColumn::Year, shown: true });
+        assert!(slots[1..].iter().all(|slot| !slot.shown));
+    }
+}
@@ -0,0 +1,101 @@
+use iced::theme::{self, Palette};
+use iced::widget::text_input;
+use iced::{Color, Theme};
+
+/// How much bigger everything is drawn, so buttons and sliders are easier to hit.
+pub const SCALE: f64 = 1.25;
+
+/// White on black, with yellow for what's selected or has focus.
+pub fn high_contrast_theme() -> Theme {
+    Theme::custom(Palette {
+        background: Color::BLACK,
+        text: Color::WHITE,
+        primary: Color::from_rgb(1.0, 0.85, 0.0),
+        success: Color::from_rgb(0.0, 0.9, 0.3),
+        danger: Color::from_rgb(1.0, 0.45, 0.45),
+    })
+}
+
+/// Text fields with a solid border, and a thick one in the primary colour when
+/// focused, so it's obvious where typing will go.
+pub fn text_input_style() -> theme::TextInput {
+    theme::TextInput::Custom(Box::new(FocusOutline))
+}
+
+struct FocusOutline;
+
+impl FocusOutline {
+    fn outline(theme: &Theme, width: f32, color: Color) -> text_input::Appearance {
+        text_input::Appearance {
+            background: theme.palette().background.into(),
+            border_radius: 2.0,
+            border_width: width,
+            border_color: color,
+            icon_color: theme.palette().text,
+        }
+    }
+}
+
+impl text_input::StyleSheet for FocusOutline {
+    type Style = Theme;
+
+    fn active(&self, theme: &Theme) -> text_input::Appearance {
+        Self::outline(theme, 2.0, theme.palette().text)
+    }
+
+    fn focused(&self, theme: &Theme) -> text_input::Appearance {
+        Self::outline(theme, 4.0, theme.palette().primary)
+    }
+
+    fn hovered(&self, theme: &Theme) -> text_input::Appearance {
+        Self::outline(theme, 3.0, theme.palette().text)
+    }
+
+    fn disabled(&self, theme: &Theme) -> text_input::Appearance {
+        Self::outline(theme, 1.0, theme.extended_palette().background.strong.color)
+    }
+
+    fn placeholder_color(&self, theme: &Theme) -> Color {
+        theme.extended_palette().background.strong.text
+    }
+
+    fn value_color(&self, theme: &Theme) -> Color {
+        theme.palette().text
+    }
+
+    fn disabled_color(&self, theme: &Theme) -> Color {
+        theme.extended_palette().background.strong.color
+    }
+
+    fn selection_color(&self, theme: &Theme) -> Color {
+        // See-through, so selected text stays readable
+        Color { a: 0.5, ..theme.palette().primary }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// WCAG's relative luminance.
+    fn luminance(color: Color) -> f32 {
+        let linear = |c: f32| if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+        0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+    }
+
+    fn contrast(a: Color, b: Color) -> f32 {
+        let (a, b) = (luminance(a), luminance(b));
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    #[test]
+    fn colours_meet_the_enhanced_contrast_ratio() {
+        let palette = high_contrast_theme().palette();
+        // 7:1 is WCAG's AAA level for normal text
+        for color in [palette.text, palette.primary, palette.success, palette.danger] {
+            assert!(contrast(color, palette.background) >= 7.0, "{:?}", color);
+        }
+        // Selected buttons put the background colour on the primary one
+        assert!(contrast(palette.background, palette.primary) >= 7.0);
+    }
+}
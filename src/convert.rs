@@ -0,0 +1,274 @@
+use iced::futures::channel::mpsc as async_mpsc;
+use iced::futures::SinkExt;
+use lofty::{TagExt, TaggedFileExt};
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Format {
+    #[default]
+    Mp3,
+    Opus,
+    Flac,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Mp3, Format::Opus, Format::Flac];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::Opus => "opus",
+            Format::Flac => "flac",
+        }
+    }
+
+    pub fn is_lossless(self) -> bool {
+        self == Format::Flac
+    }
+
+    fn encoder_args(self, quality: Quality) -> Vec<&'static str> {
+        match (self, quality) {
+            // LAME's VBR presets, roughly 115, 190 and 245 kbps
+            (Format::Mp3, Quality::Small) => vec!["-c:a", "libmp3lame", "-q:a", "6"],
+            (Format::Mp3, Quality::Balanced) => vec!["-c:a", "libmp3lame", "-q:a", "2"],
+            (Format::Mp3, Quality::Best) => vec!["-c:a", "libmp3lame", "-q:a", "0"],
+            (Format::Opus, Quality::Small) => vec!["-c:a", "libopus", "-b:a", "64k"],
+            (Format::Opus, Quality::Balanced) => vec!["-c:a", "libopus", "-b:a", "96k"],
+            (Format::Opus, Quality::Best) => vec!["-c:a", "libopus", "-b:a", "160k"],
+            // Lossless either way, so just squeeze as hard as we can
+            (Format::Flac, _) => vec!["-c:a", "flac", "-compression_level", "8"],
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Format::Mp3 => "MP3",
+            Format::Opus => "Opus",
+            Format::Flac => "FLAC",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Quality {
+    Small,
+    #[default]
+    Balanced,
+    Best,
+}
+
+impl Quality {
+    pub const ALL: [Quality; 3] = [Quality::Small, Quality::Balanced, Quality::Best];
+}
+
+impl std::fmt::Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Quality::Small => "Small",
+            Quality::Balanced => "Balanced",
+            Quality::Best => "Best",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConvertJob {
+    pub id: u64,
+    pub source: PathBuf,
+    pub destination_folder: PathBuf,
+    pub format: Format,
+    pub quality: Quality,
+    pub duration: Option<Duration>, // Only used to work out progress
+}
+
+#[derive(Debug, Clone)]
+pub enum ConvertEvent {
+    Progress { id: u64, fraction: f32 },
+    Finished { id: u64, result: Result<PathBuf, String> },
+}
+
+/// Handle to the conversion thread. Jobs run one at a time in the order they
+/// were queued, since ffmpeg already keeps a core busy.
+#[derive(Debug, Clone)]
+pub struct Converter {
+    jobs: mpsc::Sender<ConvertJob>,
+}
+
+impl Converter {
+    pub fn spawn(events: async_mpsc::Sender<ConvertEvent>) -> Self {
+        let (jobs, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("convert".to_string())
+            .spawn(move || run_convert_thread(receiver, events))
+            .expect("failed to spawn the conversion thread");
+        Self { jobs }
+    }
+
+    pub fn queue(&self, job: ConvertJob) {
+        let _ = self.jobs.send(job);
+    }
+}
+
+fn run_convert_thread(jobs: mpsc::Receiver<ConvertJob>, mut events: async_mpsc::Sender<ConvertEvent>) {
+    let mut emit = |event| {
+        let _ = iced::futures::executor::block_on(events.send(event));
+    };
+    for job in jobs {
+        let id = job.id;
+        let result = convert(&job, &mut |fraction| emit(ConvertEvent::Progress { id, fraction }));
+        if let Err(e) = &result {
+            tracing::warn!(path = %job.source.display(), "Conversion failed: {}", e);
+        }
+        emit(ConvertEvent::Finished { id, result });
+    }
+}
+
+#[tracing::instrument(skip_all, fields(path = %job.source.display(), format = %job.format))]
+fn convert(job: &ConvertJob, progress: &mut dyn FnMut(f32)) -> Result<PathBuf, String> {
+    let destination = destination_path(&job.source, &job.destination_folder, job.format);
+    let mut child = Command::new("ffmpeg")
+        .args(ffmpeg_args(&job.source, &destination, job.format, job.quality))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => "Converting needs ffmpeg, which wasn't found on the PATH".to_string(),
+            _ => format!("Failed to start ffmpeg: {}", e),
+        })?;
+
+    // ffmpeg writes key=value progress lines to stdout as it goes
+    if let Some(stdout) = child.stdout.take() {
+        let mut reported = 0.0;
+        for line in std::io::BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let (Some(encoded), Some(duration)) = (parse_progress(&line), job.duration) {
+                let fraction = (encoded.as_secs_f32() / duration.as_secs_f32()).clamp(0.0, 1.0);
+                if fraction - reported >= 0.01 {
+                    reported = fraction;
+                    progress(fraction);
+                }
+            }
+        }
+    }
+
+    let output = child.wait_with_output().map_err(|e| format!("ffmpeg went missing: {}", e))?;
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&destination);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffmpeg failed: {}", stderr.lines().last().unwrap_or("no details")));
+    }
+
+    // ffmpeg's own tag mapping drops the cover for some containers, lofty doesn't
+    if let Err(e) = copy_tags(&job.source, &destination) {
+        tracing::warn!(path = %destination.display(), "Converted, but failed to copy tags: {}", e);
+    }
+    Ok(destination)
+}
+
+fn ffmpeg_args(source: &Path, destination: &Path, format: Format, quality: Quality) -> Vec<std::ffi::OsString> {
+    let mut args: Vec<std::ffi::OsString> = ["-hide_banner", "-loglevel", "error", "-nostats", "-progress", "pipe:1", "-n", "-i"]
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    args.push(source.into());
+    // Audio only; tags and cover art are copied over afterwards
+    args.extend(["-map", "0:a:0", "-map_metadata", "-1"].map(Into::into));
+    args.extend(format.encoder_args(quality).into_iter().map(Into::into));
+    args.push(destination.into());
+    args
+}
+
+fn parse_progress(line: &str) -> Option<Duration> {
+    // Despite the name, out_time_ms is in microseconds too
+    let micros = line.strip_prefix("out_time_us=").or_else(|| line.strip_prefix("out_time_ms="))?;
+    micros.trim().parse().ok().map(Duration::from_micros)
+}
+
+/// `folder/<name>.<ext>`, numbered if that's taken so nothing gets overwritten.
+fn destination_path(source: &Path, folder: &Path, format: Format) -> PathBuf {
+    let stem = source.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_else(|| "track".to_string());
+    let mut destination = folder.join(format!("{}.{}", stem, format.extension()));
+    let mut n = 1;
+    while destination.exists() {
+        destination = folder.join(format!("{} ({}).{}", stem, n, format.extension()));
+        n += 1;
+    }
+    destination
+}
+
+/// Copies the tags and pictures from `source` into whatever tag format suits `destination`.
+fn copy_tags(source: &Path, destination: &Path) -> Result<(), lofty::LoftyError> {
+    let source = lofty::read_from_path(source)?;
+    let Some(mut tag) = source.primary_tag().or_else(|| source.first_tag()).cloned() else {
+        return Ok(());
+    };
+    tag.re_map(lofty::read_from_path(destination)?.primary_tag_type());
+    tag.save_to_path(destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_audio::write_wav;
+    use lofty::{Accessor, MimeType, Picture, PictureType, Tag, TagType};
+    use std::fs;
+
+    #[test]
+    fn never_overwrites_an_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Path::new("/music/Album/01 Intro.flac");
+        assert_eq!(destination_path(source, dir.path(), Format::Opus), dir.path().join("01 Intro.opus"));
+
+        fs::write(dir.path().join("01 Intro.mp3"), b"").unwrap();
+        fs::write(dir.path().join("01 Intro (1).mp3"), b"").unwrap();
+        assert_eq!(destination_path(source, dir.path(), Format::Mp3), dir.path().join("01 Intro (2).mp3"));
+    }
+
+    #[test]
+    fn reads_ffmpeg_progress() {
+        assert_eq!(parse_progress("out_time_us=1500000"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_progress("out_time_ms=250000"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_progress("out_time_us=N/A"), None);
+        assert_eq!(parse_progress("progress=continue"), None);
+    }
+
+    #[test]
+    fn asks_ffmpeg_for_audio_only() {
+        let args = ffmpeg_args(Path::new("in.flac"), Path::new("out.opus"), Format::Opus, Quality::Small);
+        let args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+        assert_eq!(args.last().map(|arg| arg.as_ref()), Some("out.opus"));
+        assert!(args.windows(2).any(|pair| pair[0] == "-map" && pair[1] == "0:a:0"));
+        assert!(args.windows(2).any(|pair| pair[0] == "-b:a" && pair[1] == "64k"));
+        // Never clobber an existing file, even if our own check raced with someone
+        assert!(args.iter().any(|arg| arg == "-n"));
+    }
+
+    #[test]
+    fn copies_tags_and_cover() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.wav");
+        let destination = dir.path().join("destination.wav");
+        write_wav(&source, 1, 8000, &[0; 8000]);
+        write_wav(&destination, 1, 8000, &[0; 8000]);
+
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.set_title("Teardrop".to_string());
+        tag.set_artist("Massive Attack".to_string());
+        tag.push_picture(Picture::new_unchecked(PictureType::CoverFront, MimeType::Png, None, vec![1, 2, 3]));
+        tag.save_to_path(&source).unwrap();
+
+        copy_tags(&source, &destination).unwrap();
+        let copied = lofty::read_from_path(&destination).unwrap();
+        let tag = copied.primary_tag().unwrap();
+        assert_eq!(tag.title().as_deref(), Some("Teardrop"));
+        assert_eq!(tag.artist().as_deref(), Some("Massive Attack"));
+        assert_eq!(tag.pictures()[0].data(), &[1, 2, 3]);
+    }
+}
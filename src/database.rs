@@ -0,0 +1,410 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::project_dirs;
+
+// Plays older than this many back drop out of the history
+const HISTORY_LENGTH: usize = 1000;
+
+// Moving on before this share of a track has played counts as skipping it
+const SKIP_FRACTION: f64 = 0.2;
+
+// Skips before a track is suggested for removal, as long as it's skipped more often than finished
+const FREQUENT_SKIPS: u32 = 3;
+
+// While an audiobook plays, its place is saved every this many seconds
+const RESUME_SAVE_SECONDS: u64 = 30;
+
+/// What the app knows about tracks beyond their tags, kept as JSON in the data
+/// directory. Tracks are keyed by path; ones with nothing worth keeping are left out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryDb {
+    pub tracks: BTreeMap<PathBuf, TrackRecord>,
+    pub playlists: Vec<Playlist>,
+    pub queue: Vec<PathBuf>, // What was waiting to play when the app last closed
+    pub queue_snapshots: Vec<QueueSnapshot>,
+    pub history: Vec<Play>, // Oldest first
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackRecord {
+    pub bookmarks: Vec<Bookmark>, // In order of position
+    pub rating: Option<u8>,       // Stars, 1 to 5
+    pub play_count: u32, // Times played to the end
+    pub skip_count: u32,
+    pub last_played: Option<SystemTime>,
+    pub gain_db: f32, // Offset applied on playback, for tracks mastered louder or quieter than the rest
+    pub resume_at: Option<Duration>, // Where an audiobook was left off
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    pub name: String,
+    pub tracks: Vec<PathBuf>,
+}
+
+/// A track starting to play, for the history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Play {
+    pub file_path: PathBuf,
+    pub started: SystemTime,
+}
+
+/// The queue as it was at some point, saved under a name to pick up again later.
+/// Lighter than a playlist: it isn't shown in the library or exported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    pub name: String,
+    pub tracks: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub position: Duration,
+}
+
+impl LibraryDb {
+    /// Loads the database, starting empty if it's missing or unreadable.
+    pub fn load() -> Self {
+        database_path().map(|path| Self::load_from(&path)).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = database_path()
+            && let Err(e) = self.save_to(&path)
+        {
+            tracing::warn!("Failed to save the library database: {}", e);
+        }
+    }
+
+    fn load_from(path: &Path) -> Self {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                // Keep the broken file around rather than silently overwriting it on the next save
+                let backup = path.with_extension("json.broken");
+                tracing::error!("Invalid library database {}, moved to {}: {}", path.display(), backup.display(), e);
+                let _ = fs::rename(path, backup);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn save_to(&self, path: &Path) -> std::io::Result<()> {
+        let contents = serde_json::to_vec_pretty(self).map_err(std::io::Error::other)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write then rename so a crash mid-save can't lose the whole database
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(temporary, path)
+    }
+
+    pub fn add_bookmark(&mut self, file_path: &Path, bookmark: Bookmark) {
+        let bookmarks = &mut self.tracks.entry(file_path.to_path_buf()).or_default().bookmarks;
+        let index = bookmarks.partition_point(|existing| existing.position <= bookmark.position);
+        bookmarks.insert(index, bookmark);
+    }
+
+    pub fn remove_bookmark(&mut self, file_path: &Path, index: usize) {
+        if let Some(record) = self.tracks.get_mut(file_path)
+            && index < record.bookmarks.len()
+        {
+            record.bookmarks.remove(index);
+            self.forget_if_empty(file_path);
+        }
+    }
+
+    pub fn count_play(&mut self, file_path: &Path) {
+        let record = self.tracks.entry(file_path.to_path_buf()).or_default();
+        record.play_count += 1;
+        record.last_played = Some(SystemTime::now());
+    }
+
+    /// Counts a skip if the user moved on from `file_path` after `position`,
+    /// early in a track `duration` long. Returns whether it counted.
+    pub fn note_skip(&mut self, file_path: &Path, position: Duration, duration: Option<Duration>) -> bool {
+        let Some(duration) = duration.filter(|duration| !duration.is_zero()) else { return false };
+        if position.as_secs_f64() >= duration.as_secs_f64() * SKIP_FRACTION {
+            return false;
+        }
+        self.tracks.entry(file_path.to_path_buf()).or_default().skip_count += 1;
+        true
+    }
+
+    /// Tracks skipped often enough, and more than they're finished, that they
+    /// might be worth removing or rating down. Most skipped first.
+    pub fn frequently_skipped(&self) -> Vec<PathBuf> {
+        let mut skipped: Vec<(&PathBuf, &TrackRecord)> = self
+            .tracks
+            .iter()
+            .filter(|(_, record)| record.skip_count >= FREQUENT_SKIPS && record.skip_count > record.play_count)
+            .collect();
+        skipped.sort_by_key(|(_, record)| std::cmp::Reverse(record.skip_count));
+        skipped.into_iter().map(|(file_path, _)| file_path.clone()).collect()
+    }
+
+    /// Takes a star off `file_path`, counting unrated tracks as three stars. Never
+    /// goes below one.
+    pub fn rate_down(&mut self, file_path: &Path) {
+        let rating = &mut self.tracks.entry(file_path.to_path_buf()).or_default().rating;
+        *rating = Some(rating.unwrap_or(3).saturating_sub(1).max(1));
+    }
+
+    /// Adds `file_path` to the history as starting now.
+    pub fn record_play(&mut self, file_path: &Path) {
+        if self.history.len() >= HISTORY_LENGTH {
+            self.history.drain(..=self.history.len() - HISTORY_LENGTH);
+        }
+        self.history.push(Play { file_path: file_path.to_path_buf(), started: SystemTime::now() });
+    }
+
+    pub fn gain_db(&self, file_path: &Path) -> f32 {
+        self.tracks.get(file_path).map_or(0.0, |record| record.gain_db)
+    }
+
+    pub fn set_gain(&mut self, file_path: &Path, gain_db: f32) {
+        self.tracks.entry(file_path.to_path_buf()).or_default().gain_db = gain_db;
+        self.forget_if_empty(file_path);
+    }
+
+    /// Where to pick `file_path` up from, the start unless it was left part way.
+    pub fn resume_position(&self, file_path: &Path) -> Duration {
+        self.tracks.get(file_path).and_then(|record| record.resume_at).unwrap_or_default()
+    }
+
+    /// Remembers where `file_path` was left, or forgets it with `None` once it's finished.
+    pub fn set_resume_position(&mut self, file_path: &Path, position: Option<Duration>) {
+        self.tracks.entry(file_path.to_path_buf()).or_default().resume_at = position;
+        self.forget_if_empty(file_path);
+    }
+
+    pub fn playlist(&self, name: &str) -> Option<&Playlist> {
+        self.playlists.iter().find(|playlist| playlist.name == name)
+    }
+
+    /// Adds `playlist`, replacing any existing one with the same name.
+    pub fn set_playlist(&mut self, playlist: Playlist) {
+        match self.playlists.iter_mut().find(|existing| existing.name == playlist.name) {
+            Some(existing) => *existing = playlist,
+            None => self.playlists.push(playlist),
+        }
+    }
+
+    /// Adds `file_path` to the end of the playlist called `name`, starting one if
+    /// there isn't one by that name yet.
+    pub fn add_to_playlist(&mut self, name: &str, file_path: &Path) {
+        match self.playlists.iter_mut().find(|playlist| playlist.name == name) {
+            Some(playlist) => playlist.tracks.push(file_path.to_path_buf()),
+            None => self.playlists.push(Playlist { name: name.to_string(), tracks: vec![file_path.to_path_buf()] }),
+        }
+    }
+
+    pub fn queue_snapshot(&self, name: &str) -> Option<&QueueSnapshot> {
+        self.queue_snapshots.iter().find(|snapshot| snapshot.name == name)
+    }
+
+    /// Saves `snapshot`, replacing any existing one with the same name. Newest first.
+    pub fn save_queue_snapshot(&mut self, snapshot: QueueSnapshot) {
+        self.delete_queue_snapshot(&snapshot.name);
+        self.queue_snapshots.insert(0, snapshot);
+    }
+
+    pub fn delete_queue_snapshot(&mut self, name: &str) {
+        self.queue_snapshots.retain(|snapshot| snapshot.name != name);
+    }
+
+    /// Drops everything known about a track, e.g. once it's been deleted.
+    pub fn forget(&mut self, file_path: &Path) {
+        self.tracks.remove(file_path);
+        self.history.retain(|play| play.file_path != file_path);
+    }
+
+    fn forget_if_empty(&mut self, file_path: &Path) {
+        if self.tracks.get(file_path).is_some_and(|record| *record == TrackRecord::default()) {
+            self.tracks.remove(file_path);
+        }
+    }
+}
+
+/// Whether playing on from `previous` to `position` went past one of the points
+/// where an audiobook's place is saved, so it's not lost if the app is killed.
+pub fn passed_save_point(previous: Duration, position: Duration) -> bool {
+    previous.as_secs() / RESUME_SAVE_SECONDS != position.as_secs() / RESUME_SAVE_SECONDS
+}
+
+fn database_path() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.data_dir().join("library.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(name: &str, seconds: u64) -> Bookmark {
+        Bookmark { name: name.to_string(), position: Duration::from_secs(seconds) }
+    }
+
+    fn bookmarks<'a>(db: &'a LibraryDb, file_path: &Path) -> &'a [Bookmark] {
+        db.tracks.get(file_path).map(|record| &record.bookmarks[..]).unwrap_or_default()
+    }
+
+    #[test]
+    fn bookmarks_stay_in_order() {
+        let mut db = LibraryDb::default();
+        let mix = Path::new("/music/mix.mp3");
+        db.add_bookmark(mix, bookmark("Drop", 1800));
+        db.add_bookmark(mix, bookmark("Intro", 0));
+        db.add_bookmark(mix, bookmark("Breakdown", 900));
+
+        let names: Vec<_> = bookmarks(&db, mix).iter().map(|bookmark| bookmark.name.as_str()).collect();
+        assert_eq!(names, ["Intro", "Breakdown", "Drop"]);
+        assert!(bookmarks(&db, Path::new("/music/other.mp3")).is_empty());
+    }
+
+    #[test]
+    fn remembers_where_a_book_was_left() {
+        let mut db = LibraryDb::default();
+        let book = Path::new("/books/chapter2.m4a");
+        assert_eq!(db.resume_position(book), Duration::ZERO);
+
+        db.set_resume_position(book, Some(Duration::from_secs(754)));
+        assert_eq!(db.resume_position(book), Duration::from_secs(754));
+        db.set_resume_position(book, None);
+        assert_eq!(db, LibraryDb::default());
+    }
+
+    #[test]
+    fn removing_the_last_bookmark_forgets_the_track() {
+        let mut db = LibraryDb::default();
+        let book = Path::new("/books/chapter1.m4a");
+        db.add_bookmark(book, bookmark("Where I stopped", 600));
+        db.remove_bookmark(book, 5);
+        assert_eq!(bookmarks(&db, book).len(), 1);
+
+        db.remove_bookmark(book, 0);
+        assert_eq!(db, LibraryDb::default());
+    }
+
+    #[test]
+    fn gain_defaults_to_zero() {
+        let mut db = LibraryDb::default();
+        let loud = Path::new("/music/loud.mp3");
+        assert_eq!(db.gain_db(loud), 0.0);
+
+        db.set_gain(loud, -4.5);
+        assert_eq!(db.gain_db(loud), -4.5);
+        db.set_gain(loud, 0.0);
+        assert_eq!(db, LibraryDb::default());
+    }
+
+    #[test]
+    fn playlists_are_replaced_by_name() {
+        let mut db = LibraryDb::default();
+        db.set_playlist(Playlist { name: "Road trip".to_string(), tracks: vec![PathBuf::from("/music/a.mp3")] });
+        db.set_playlist(Playlist { name: "Gym".to_string(), tracks: Vec::new() });
+        db.set_playlist(Playlist { name: "Road trip".to_string(), tracks: vec![PathBuf::from("/music/b.mp3")] });
+
+        assert_eq!(db.playlists.len(), 2);
+        assert_eq!(db.playlist("Road trip").unwrap().tracks, [PathBuf::from("/music/b.mp3")]);
+
+        db.add_to_playlist("Gym", Path::new("/music/c.mp3"));
+        db.add_to_playlist("New", Path::new("/music/d.mp3"));
+        assert_eq!(db.playlist("Gym").unwrap().tracks, [PathBuf::from("/music/c.mp3")]);
+        assert_eq!(db.playlists[2], Playlist { name: "New".to_string(), tracks: vec![PathBuf::from("/music/d.mp3")] });
+    }
+
+    #[test]
+    fn early_skips_are_counted() {
+        let mut db = LibraryDb::default();
+        let (song, other) = (Path::new("/music/song.mp3"), Path::new("/music/other.mp3"));
+        let length = Some(Duration::from_secs(200));
+        for _ in 0..3 {
+            assert!(db.note_skip(song, Duration::from_secs(10), length));
+        }
+        assert!(!db.note_skip(song, Duration::from_secs(40), length));
+        assert!(!db.note_skip(song, Duration::from_secs(1), None));
+        assert_eq!(db.tracks[song].skip_count, 3);
+        assert_eq!(db.frequently_skipped(), [song]);
+
+        // Skipped as often, but finished more
+        for _ in 0..3 {
+            db.note_skip(other, Duration::ZERO, length);
+        }
+        for _ in 0..4 {
+            db.count_play(other);
+        }
+        assert_eq!(db.frequently_skipped(), [song]);
+
+        db.rate_down(song);
+        db.rate_down(other);
+        db.rate_down(other);
+        assert_eq!((db.tracks[song].rating, db.tracks[other].rating), (Some(2), Some(1)));
+        db.rate_down(other);
+        assert_eq!(db.tracks[other].rating, Some(1));
+    }
+
+    #[test]
+    fn history_keeps_the_latest_plays() {
+        let mut db = LibraryDb::default();
+        for number in 0..HISTORY_LENGTH + 5 {
+            db.record_play(&PathBuf::from(format!("/music/{}.mp3", number)));
+        }
+        assert_eq!(db.history.len(), HISTORY_LENGTH);
+        assert_eq!(db.history[0].file_path, PathBuf::from("/music/5.mp3"));
+        assert!(db.history.windows(2).all(|plays| plays[0].started <= plays[1].started));
+
+        db.forget(Path::new("/music/5.mp3"));
+        assert_eq!(db.history[0].file_path, PathBuf::from("/music/6.mp3"));
+    }
+
+    #[test]
+    fn queue_snapshots_are_replaced_by_name_newest_first() {
+        let mut db = LibraryDb::default();
+        let snapshot = |name: &str, track: &str| QueueSnapshot { name: name.to_string(), tracks: vec![PathBuf::from(track)] };
+        db.save_queue_snapshot(snapshot("Last weekend", "/music/a.mp3"));
+        db.save_queue_snapshot(snapshot("Cooking", "/music/b.mp3"));
+        db.save_queue_snapshot(snapshot("Last weekend", "/music/c.mp3"));
+
+        let names: Vec<_> = db.queue_snapshots.iter().map(|snapshot| snapshot.name.as_str()).collect();
+        assert_eq!(names, ["Last weekend", "Cooking"]);
+        assert_eq!(db.queue_snapshot("Last weekend").unwrap().tracks, [PathBuf::from("/music/c.mp3")]);
+        db.delete_queue_snapshot("Cooking");
+        assert!(db.queue_snapshot("Cooking").is_none());
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data").join("library.json");
+        let mut db = LibraryDb::default();
+        db.add_bookmark(Path::new("/music/mix.mp3"), bookmark("Drop", 1800));
+        db.count_play(Path::new("/music/mix.mp3"));
+        db.set_playlist(Playlist { name: "Mixes".to_string(), tracks: vec![PathBuf::from("/music/mix.mp3")] });
+        db.queue = vec![PathBuf::from("/music/next.mp3")];
+        db.record_play(Path::new("/music/mix.mp3"));
+        db.save_queue_snapshot(QueueSnapshot { name: "Sunday".to_string(), tracks: vec![PathBuf::from("/music/mix.mp3")] });
+
+        db.save_to(&path).unwrap();
+        assert_eq!(LibraryDb::load_from(&path), db);
+        assert_eq!(LibraryDb::load_from(&dir.path().join("missing.json")), LibraryDb::default());
+    }
+
+    #[test]
+    fn broken_file_is_set_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("library.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        assert_eq!(LibraryDb::load_from(&path), LibraryDb::default());
+        assert!(!path.exists());
+        assert!(dir.path().join("library.json.broken").exists());
+    }
+}
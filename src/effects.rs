@@ -0,0 +1,625 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::player::db_to_gain;
+
+pub type BoxedSource = Box<dyn Source<Item = f32> + Send>;
+
+// Peaks are held to full scale
+const LIMIT_CEILING: f32 = 1.0;
+// How long the limiter takes to let go once peaks drop back
+const LIMIT_RELEASE: Duration = Duration::from_millis(150);
+// Turning peaks down by less than this (under 0.1 dB) isn't worth mentioning
+const LIMIT_NOTICEABLE: f32 = 0.99;
+// Tracks without ReplayGain tags are brought to about this RMS level, which is
+// roughly where ReplayGain puts tagged ones
+const LEVEL_TARGET_DB: f32 = -18.0;
+// Only the start of a track is listened to, and the gain is held from then on
+const LEVEL_LISTEN: Duration = Duration::from_secs(10);
+// Less than this much sound to go on and the gain is left alone
+const LEVEL_SETTLE: Duration = Duration::from_millis(500);
+// Quieter samples are taken for silence and left out of the measurement
+const LEVEL_GATE_DB: f32 = -50.0;
+// Roughly how long the gain takes to move to a new setting
+const LEVEL_GLIDE: Duration = Duration::from_secs(1);
+const LEVEL_MAX_BOOST_DB: f32 = 12.0;
+// Bauer's figures: the head shadows the far ear above about 700 Hz, and sound
+// takes about 0.3 ms to get round to it
+const CROSSFEED_CUTOFF_HZ: f32 = 700.0;
+const CROSSFEED_DELAY: Duration = Duration::from_micros(300);
+
+/// A stage of the playback chain. Each one wraps whatever comes out of the stage
+/// before it, so effects compose in the order they're listed.
+pub trait AudioEffect: Send {
+    fn apply(&self, source: BoxedSource) -> BoxedSource;
+}
+
+/// An effect and its settings, as arranged in the effects panel.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+    Preamp { gain_db: f32 },
+    HighPass { cutoff_hz: u32 },
+    LowPass { cutoff_hz: u32 },
+    Mono,
+    /// Cancels what's panned dead centre, usually the lead vocal, for singing along.
+    Karaoke { strength: u32 },
+    /// Feeds each channel into the other, as speakers would, for headphones.
+    Crossfeed { level_db: f32 }, // How far below the direct sound the feed is
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EffectSlot {
+    pub effect: Effect,
+    pub enabled: bool,
+}
+
+impl Effect {
+    /// One of each effect, in the order new ones are added to the chain.
+    const DEFAULTS: [Effect; 6] = [
+        Effect::Preamp { gain_db: 0.0 },
+        Effect::HighPass { cutoff_hz: 80 },
+        Effect::LowPass { cutoff_hz: 12000 },
+        Effect::Mono,
+        Effect::Karaoke { strength: 100 },
+        Effect::Crossfeed { level_db: 6.0 },
+    ];
+
+    pub fn label(&self) -> String {
+        match self {
+            Effect::Preamp { gain_db } => format!("Preamp ({:+.1} dB)", gain_db),
+            Effect::HighPass { cutoff_hz } => format!("Bass cut (below {} Hz)", cutoff_hz),
+            Effect::LowPass { cutoff_hz } => format!("Treble cut (above {} Hz)", cutoff_hz),
+            Effect::Mono => "Mono".to_string(),
+            Effect::Karaoke { strength } => format!("Karaoke ({}% vocal cut)", strength),
+            Effect::Crossfeed { level_db } => format!("Crossfeed ({:.1} dB down)", level_db),
+        }
+    }
+
+    /// The effect's one adjustable setting, if it has one, with its range and step.
+    pub fn parameter(&self) -> Option<(f32, RangeInclusive<f32>, f32)> {
+        match *self {
+            Effect::Preamp { gain_db } => Some((gain_db, -12.0..=12.0, 0.5)),
+            Effect::HighPass { cutoff_hz } => Some((cutoff_hz as f32, 20.0..=500.0, 10.0)),
+            Effect::LowPass { cutoff_hz } => Some((cutoff_hz as f32, 2000.0..=20000.0, 500.0)),
+            Effect::Mono => None,
+            Effect::Karaoke { strength } => Some((strength as f32, 10.0..=100.0, 10.0)),
+            Effect::Crossfeed { level_db } => Some((level_db, 3.0..=12.0, 0.5)),
+        }
+    }
+
+    pub fn set_parameter(&mut self, value: f32) {
+        match self {
+            Effect::Preamp { gain_db } | Effect::Crossfeed { level_db: gain_db } => *gain_db = value,
+            Effect::HighPass { cutoff_hz } | Effect::LowPass { cutoff_hz } => *cutoff_hz = value.round() as u32,
+            Effect::Karaoke { strength } => *strength = value.round() as u32,
+            Effect::Mono => {}
+        }
+    }
+
+    fn build(&self) -> Box<dyn AudioEffect> {
+        match *self {
+            Effect::Preamp { gain_db } => Box::new(Preamp { gain: db_to_gain(gain_db) }),
+            Effect::HighPass { cutoff_hz } => Box::new(HighPass { cutoff_hz }),
+            Effect::LowPass { cutoff_hz } => Box::new(LowPass { cutoff_hz }),
+            Effect::Mono => Box::new(Downmix),
+            Effect::Karaoke { strength } => Box::new(CenterCancel { amount: strength as f32 / 100.0 }),
+            Effect::Crossfeed { level_db } => Box::new(Crossfeed { feed: db_to_gain(-level_db) }),
+        }
+    }
+
+    fn same_kind(&self, other: &Effect) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// Every effect, switched off.
+pub fn default_chain() -> Vec<EffectSlot> {
+    Effect::DEFAULTS.iter().map(|&effect| EffectSlot { effect, enabled: false }).collect()
+}
+
+/// Adds any effects missing from `slots` to the end, switched off, so ones added in
+/// newer versions show up for settings saved by older ones.
+pub fn complete_chain(slots: &mut Vec<EffectSlot>) {
+    for effect in Effect::DEFAULTS {
+        if !slots.iter().any(|slot| slot.effect.same_kind(&effect)) {
+            slots.push(EffectSlot { effect, enabled: false });
+        }
+    }
+}
+
+/// The enabled effects in `slots`, ready to run tracks through.
+pub fn build_chain(slots: &[EffectSlot]) -> Vec<Box<dyn AudioEffect>> {
+    slots.iter().filter(|slot| slot.enabled).map(|slot| slot.effect.build()).collect()
+}
+
+pub fn apply_chain(chain: &[Box<dyn AudioEffect>], source: BoxedSource) -> BoxedSource {
+    chain.iter().fold(source, |source, effect| effect.apply(source))
+}
+
+struct Preamp {
+    gain: f32,
+}
+
+impl AudioEffect for Preamp {
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        Box::new(source.amplify(self.gain))
+    }
+}
+
+struct HighPass {
+    cutoff_hz: u32,
+}
+
+impl AudioEffect for HighPass {
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        Box::new(source.high_pass(self.cutoff_hz))
+    }
+}
+
+struct LowPass {
+    cutoff_hz: u32,
+}
+
+impl AudioEffect for LowPass {
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        Box::new(source.low_pass(self.cutoff_hz))
+    }
+}
+
+struct Downmix;
+
+/// Averages the channels of each frame and plays the result on all of them, for
+/// one-speaker setups and old hard-panned stereo. The channel count is unchanged
+/// so the output device doesn't have to be reopened.
+impl AudioEffect for Downmix {
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        Box::new(PerFrame::new(source, |frame: &mut [f32]| {
+            let average = frame.iter().sum::<f32>() / frame.len() as f32;
+            frame.fill(average);
+        }))
+    }
+}
+
+struct CenterCancel {
+    amount: f32, // 0 to 1
+}
+
+/// Takes away `amount` of what the left and right channels have in common. The
+/// lead vocal is nearly always mixed there, but so are bass and kick drum, which
+/// go with it. Anything but stereo is left alone.
+impl AudioEffect for CenterCancel {
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        if source.channels() != 2 {
+            return source;
+        }
+        let amount = self.amount;
+        Box::new(PerFrame::new(source, move |frame: &mut [f32]| {
+            if let [left, right] = frame {
+                let center = (*left + *right) / 2.0 * amount;
+                *left -= center;
+                *right -= center;
+            }
+        }))
+    }
+}
+
+struct Crossfeed {
+    feed: f32, // Linear gain of the crossed-over signal
+}
+
+/// Bauer's stereophonic-to-binaural crossfeed. Each ear gets the other channel
+/// low-passed, delayed and turned down, the way it would hear the far speaker
+/// round the head, so hard-panned old stereo stops sitting inside one ear. The
+/// treble the feed leaves out is added back to the direct sound, so anything in
+/// the centre comes out as it went in. Anything but stereo is left alone.
+impl AudioEffect for Crossfeed {
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        if source.channels() != 2 {
+            return source;
+        }
+        let rate = source.sample_rate() as f32;
+        let smoothing = 1.0 - (-2.0 * std::f32::consts::PI * CROSSFEED_CUTOFF_HZ / rate).exp();
+        let delay = ((CROSSFEED_DELAY.as_secs_f32() * rate).round() as usize).max(1);
+        let feed = self.feed;
+        let mut low = [0f32; 2];
+        // The low-passed channels from `delay` frames ago, oldest first
+        let mut delayed = std::collections::VecDeque::from(vec![[0f32; 2]; delay]);
+        Box::new(PerFrame::new(source, move |frame: &mut [f32]| {
+            if let [left, right] = frame {
+                low[0] += (*left - low[0]) * smoothing;
+                low[1] += (*right - low[1]) * smoothing;
+                delayed.push_back(low);
+                let [far_left, far_right] = delayed.pop_front().unwrap_or_default();
+                *left = (*left + feed * (*left - low[0]) + feed * far_right) / (1.0 + feed);
+                *right = (*right + feed * (*right - low[1]) + feed * far_left) / (1.0 + feed);
+            }
+        }))
+    }
+}
+
+/// Runs `process` over each frame of `inner`, all channels at once.
+struct PerFrame<F> {
+    inner: BoxedSource,
+    process: F,
+    frame: Vec<f32>,
+    index: usize, // Next sample of `frame` to hand out
+}
+
+impl<F: FnMut(&mut [f32])> PerFrame<F> {
+    fn new(inner: BoxedSource, process: F) -> Self {
+        Self { inner, process, frame: Vec::new(), index: 0 }
+    }
+}
+
+impl<F: FnMut(&mut [f32])> Iterator for PerFrame<F> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.index >= self.frame.len() {
+            let channels = self.inner.channels();
+            self.frame.clear();
+            self.frame.extend(self.inner.by_ref().take(channels as usize));
+            if self.frame.is_empty() {
+                return None;
+            }
+            (self.process)(&mut self.frame);
+            self.index = 0;
+        }
+        self.index += 1;
+        Some(self.frame[self.index - 1])
+    }
+}
+
+impl<F: FnMut(&mut [f32])> Source for PerFrame<F> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len().map(|len| len + self.frame.len() - self.index)
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.frame.clear();
+        self.index = 0;
+        self.inner.try_seek(pos)
+    }
+}
+
+/// How a track is brought to the same loudness as the rest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Leveling {
+    Off,
+    /// The ReplayGain track gain from its tags, in dB.
+    Tagged(f32),
+    /// No tags to go on, so its first seconds are measured as it plays.
+    Measure,
+}
+
+impl Leveling {
+    pub fn for_track(enabled: bool, replay_gain_db: Option<f32>) -> Self {
+        match replay_gain_db {
+            _ if !enabled => Leveling::Off,
+            Some(gain_db) => Leveling::Tagged(gain_db),
+            None => Leveling::Measure,
+        }
+    }
+}
+
+/// Evens out tracks that have no ReplayGain tags, without scanning them first:
+/// listens to how loud the first seconds are and glides the gain towards the
+/// level tagged tracks play at. Clones share the switch, so it can be flipped
+/// mid-track; switched off, the gain glides back to unity.
+#[derive(Debug, Clone, Default)]
+pub struct Leveler {
+    measuring: Arc<AtomicBool>,
+}
+
+impl Leveler {
+    pub fn set_measuring(&self, measuring: bool) {
+        self.measuring.store(measuring, Ordering::Relaxed);
+    }
+
+    pub fn apply(&self, source: BoxedSource) -> BoxedSource {
+        let per_second = source.sample_rate() as f32 * source.channels() as f32;
+        Box::new(Leveled {
+            inner: source,
+            leveler: self.clone(),
+            listen_for: (LEVEL_LISTEN.as_secs_f32() * per_second) as u64,
+            settle_after: (LEVEL_SETTLE.as_secs_f32() * per_second) as u64,
+            glide: 1.0 - (-1.0 / (LEVEL_GLIDE.as_secs_f32() * per_second).max(1.0)).exp(),
+            heard: 0,
+            loud: 0,
+            sum_squares: 0.0,
+            target: 1.0,
+            gain: 1.0,
+        })
+    }
+}
+
+/// A source run through a [`Leveler`]. All channels get the same gain.
+struct Leveled {
+    inner: BoxedSource,
+    leveler: Leveler,
+    listen_for: u64,   // Samples to measure
+    settle_after: u64, // Samples above the gate needed before the gain moves
+    glide: f32,        // How far `gain` moves towards `target` per sample
+    heard: u64,
+    loud: u64, // Samples of `heard` above the gate
+    sum_squares: f64,
+    target: f32,
+    gain: f32,
+}
+
+impl Iterator for Leveled {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        if !self.leveler.measuring.load(Ordering::Relaxed) {
+            self.target = 1.0;
+        } else if self.heard < self.listen_for {
+            self.heard += 1;
+            if sample.abs() > db_to_gain(LEVEL_GATE_DB) {
+                self.loud += 1;
+                self.sum_squares += (sample * sample) as f64;
+            }
+            // Working the target out every so often is plenty, as the gain glides anyway
+            if self.loud >= self.settle_after && self.heard.is_multiple_of(1024) {
+                let rms_db = 10.0 * (self.sum_squares / self.loud as f64).log10() as f32;
+                self.target = db_to_gain((LEVEL_TARGET_DB - rms_db).min(LEVEL_MAX_BOOST_DB));
+            }
+        }
+        self.gain += (self.target - self.gain) * self.glide;
+        Some(sample * self.gain)
+    }
+}
+
+impl Source for Leveled {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    // What's been measured still holds after a seek, so the gain stays put
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+/// The last stage before the output. Applies the track gain, then turns down
+/// any peak that would go past full scale and eases back up afterwards, so
+/// boosts from the preamp or track gain never clip. Clones share the gain and
+/// the engaged flag, so the gain can change mid-track.
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    gain: Arc<AtomicU32>, // f32 bits, linear
+    engaged: Arc<AtomicBool>,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self { gain: Arc::new(AtomicU32::new(1f32.to_bits())), engaged: Arc::default() }
+    }
+}
+
+impl Limiter {
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Whether peaks have been turned down since the last call.
+    pub fn take_engaged(&self) -> bool {
+        self.engaged.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn apply(&self, source: BoxedSource) -> BoxedSource {
+        let release_samples = LIMIT_RELEASE.as_secs_f32() * source.sample_rate() as f32;
+        let release = 1.0 - (-1.0 / release_samples.max(1.0)).exp();
+        Box::new(Limited { inner: source, limiter: self.clone(), frame: Vec::new(), index: 0, reduction: 1.0, release })
+    }
+}
+
+/// A source run through a [`Limiter`]. Works a frame at a time so every channel
+/// is turned down together and the stereo image doesn't shift.
+struct Limited {
+    inner: BoxedSource,
+    limiter: Limiter,
+    frame: Vec<f32>,
+    index: usize,    // Next sample of `frame` to hand out
+    reduction: f32,  // Gain the limiter is applying, 1 when it's idle
+    release: f32,    // How far `reduction` recovers towards 1 per frame
+}
+
+impl Iterator for Limited {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.index >= self.frame.len() {
+            let channels = self.inner.channels();
+            let gain = f32::from_bits(self.limiter.gain.load(Ordering::Relaxed));
+            self.frame.clear();
+            self.frame.extend(self.inner.by_ref().take(channels as usize).map(|sample| sample * gain));
+            if self.frame.is_empty() {
+                return None;
+            }
+            let peak = self.frame.iter().fold(0f32, |peak, sample| peak.max(sample.abs()));
+            let allowed = if peak > LIMIT_CEILING { LIMIT_CEILING / peak } else { 1.0 };
+            // Straight down for a peak, then back up gently so it doesn't pump
+            self.reduction = (self.reduction + (1.0 - self.reduction) * self.release).min(allowed);
+            if self.reduction < LIMIT_NOTICEABLE {
+                self.limiter.engaged.store(true, Ordering::Relaxed);
+            }
+            let reduction = self.reduction;
+            self.frame.iter_mut().for_each(|sample| *sample *= reduction);
+            self.index = 0;
+        }
+        self.index += 1;
+        Some(self.frame[self.index - 1])
+    }
+}
+
+impl Source for Limited {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len().map(|len| len + self.frame.len() - self.index)
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.frame.clear();
+        self.index = 0;
+        self.reduction = 1.0;
+        self.inner.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn stereo(samples: &[f32]) -> BoxedSource {
+        Box::new(SamplesBuffer::new(2, 44100, samples.to_vec()))
+    }
+
+    fn slot(effect: Effect) -> EffectSlot {
+        EffectSlot { effect, enabled: true }
+    }
+
+    #[test]
+    fn effects_run_in_order() {
+        let slots = [slot(Effect::Mono), slot(Effect::Preamp { gain_db: 20.0 }), EffectSlot { effect: Effect::LowPass { cutoff_hz: 100 }, enabled: false }];
+        let output: Vec<f32> = apply_chain(&build_chain(&slots), stereo(&[0.1, 0.0, 0.0, -0.02])).collect();
+
+        let expected = [0.5, 0.5, -0.1, -0.1];
+        assert_eq!(output.len(), expected.len());
+        for (sample, expected) in output.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-5, "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn limiter_keeps_boosted_peaks_under_full_scale() {
+        let limiter = Limiter::default();
+        let quiet: Vec<f32> = limiter.apply(stereo(&[0.25, -0.5])).collect();
+        assert_eq!(quiet, [0.25, -0.5]);
+        assert!(!limiter.take_engaged());
+
+        limiter.set_gain(db_to_gain(12.0));
+        let loud: Vec<f32> = (0..4000).map(|i| (i as f32 * 0.05).sin() * 0.9).collect();
+        let output: Vec<f32> = limiter.apply(stereo(&loud)).collect();
+        assert_eq!(output.len(), loud.len());
+        assert!(output.iter().all(|sample| sample.abs() <= 1.0));
+        assert!(output.iter().any(|sample| sample.abs() > 0.9));
+        assert!(limiter.take_engaged());
+        assert!(!limiter.take_engaged());
+    }
+
+    #[test]
+    fn leveler_brings_untagged_tracks_up_to_the_target() {
+        let rms_db = |samples: &[f32]| 10.0 * (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).log10();
+        // 20 seconds of a tone at -30 dB RMS
+        let quiet: Vec<f32> = (0..44100 * 20).map(|i| (i as f32 * 0.05).sin() * db_to_gain(-30.0) * 2f32.sqrt()).collect();
+        let leveler = Leveler::default();
+        let untouched: Vec<f32> = leveler.apply(Box::new(SamplesBuffer::new(1, 44100, quiet.clone()))).collect();
+        assert_eq!(untouched, quiet);
+
+        leveler.set_measuring(true);
+        let output: Vec<f32> = leveler.apply(Box::new(SamplesBuffer::new(1, 44100, quiet.clone()))).collect();
+        assert_eq!(output.len(), quiet.len());
+        // Still quiet at the very start, at the target by the end
+        assert!(rms_db(&output[..4410]) < -29.0);
+        assert!((rms_db(&output[output.len() - 44100..]) - LEVEL_TARGET_DB).abs() < 0.5, "{}", rms_db(&output[output.len() - 44100..]));
+
+        assert_eq!(Leveling::for_track(true, Some(-7.5)), Leveling::Tagged(-7.5));
+        assert_eq!(Leveling::for_track(true, None), Leveling::Measure);
+        assert_eq!(Leveling::for_track(false, None), Leveling::Off);
+    }
+
+    #[test]
+    fn karaoke_cancels_the_centre_of_stereo_only() {
+        let slots = [slot(Effect::Karaoke { strength: 100 })];
+        // A centred vocal over something panned hard left
+        let output: Vec<f32> = apply_chain(&build_chain(&slots), stereo(&[0.5, 0.5, 0.6, 0.2])).collect();
+        let expected = [0.0, 0.0, 0.2, -0.2];
+        assert_eq!(output.len(), expected.len());
+        for (sample, expected) in output.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-5, "{:?}", output);
+        }
+
+        let mono: BoxedSource = Box::new(SamplesBuffer::new(1, 44100, vec![0.5, 0.25]));
+        let output: Vec<f32> = apply_chain(&build_chain(&slots), mono).collect();
+        assert_eq!(output, [0.5, 0.25]);
+    }
+
+    #[test]
+    fn crossfeed_reaches_the_far_ear_late_and_leaves_the_centre_alone() {
+        let slots = [slot(Effect::Crossfeed { level_db: 6.0 })];
+        let feed = db_to_gain(-6.0);
+        // A steady tone panned hard left
+        let panned: Vec<f32> = (0..2000).flat_map(|_| [1.0, 0.0]).collect();
+        let output: Vec<f32> = apply_chain(&build_chain(&slots), stereo(&panned)).collect();
+        assert_eq!(output.len(), panned.len());
+        let delay = (CROSSFEED_DELAY.as_secs_f32() * 44100.0).round() as usize;
+        assert!(output[1..2 * delay].iter().step_by(2).all(|&right| right == 0.0));
+        let (left, right) = (output[output.len() - 2], output[output.len() - 1]);
+        assert!((right / left - feed).abs() < 1e-3, "{} {}", left, right);
+
+        let centred: Vec<f32> = (0..2000).flat_map(|_| [0.5, 0.5]).collect();
+        let output: Vec<f32> = apply_chain(&build_chain(&slots), stereo(&centred)).collect();
+        assert!(output[output.len() - 2..].iter().all(|sample| (sample - 0.5).abs() < 1e-4), "{:?}", &output[output.len() - 2..]);
+    }
+
+    #[test]
+    fn missing_effects_are_added_switched_off() {
+        let mut slots = vec![slot(Effect::Mono), slot(Effect::Preamp { gain_db: -3.0 })];
+        complete_chain(&mut slots);
+
+        assert_eq!(slots.len(), Effect::DEFAULTS.len());
+        assert_eq!(slots[..2], [slot(Effect::Mono), slot(Effect::Preamp { gain_db: -3.0 })]);
+        assert!(slots[2..].iter().all(|slot| !slot.enabled));
+    }
+
+    #[test]
+    fn parameters_round_trip() {
+        let mut effect = Effect::HighPass { cutoff_hz: 80 };
+        effect.set_parameter(119.6);
+        assert_eq!(effect, Effect::HighPass { cutoff_hz: 120 });
+        assert_eq!(effect.parameter().map(|(value, _, _)| value), Some(120.0));
+        assert_eq!(Effect::Mono.parameter(), None);
+    }
+}
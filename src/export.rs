@@ -0,0 +1,176 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::LibraryDb;
+use crate::metadata::TrackInfo;
+
+#[derive(Debug, Serialize)]
+struct ExportedLibrary<'a> {
+    tracks: Vec<ExportedTrack<'a>>,
+    playlists: Vec<ExportedPlaylist<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportedTrack<'a> {
+    path: &'a Path,
+    title: Option<&'a str>,
+    artist: Option<&'a str>,
+    duration_seconds: Option<f64>,
+    rating: Option<u8>,
+    play_count: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportedPlaylist<'a> {
+    name: &'a str,
+    tracks: &'a [PathBuf],
+}
+
+/// Writes the library and playlists to `path`, as CSV if it ends in `.csv` and JSON
+/// otherwise. CSV gets a second file, `<name>-playlists.csv`, since playlists don't
+/// fit in the same table. Returns the files written.
+#[tracing::instrument(skip(files, track_info, db), fields(path = %path.display(), files = files.len()))]
+pub fn export_library(
+    path: &Path,
+    files: &[PathBuf],
+    track_info: &HashMap<PathBuf, TrackInfo>,
+    db: &LibraryDb,
+) -> Result<Vec<PathBuf>, String> {
+    let tracks = files
+        .iter()
+        .map(|file_path| {
+            let info = track_info.get(file_path);
+            let record = db.tracks.get(file_path);
+            ExportedTrack {
+                path: file_path,
+                title: info.and_then(|info| info.title.as_deref()),
+                artist: info.and_then(|info| info.artist.as_deref()),
+                duration_seconds: info.and_then(|info| info.duration).map(|duration| duration.as_secs_f64()),
+                rating: record.and_then(|record| record.rating),
+                play_count: record.map_or(0, |record| record.play_count),
+            }
+        })
+        .collect();
+    let playlists = db.playlists.iter().map(|playlist| ExportedPlaylist { name: &playlist.name, tracks: &playlist.tracks }).collect();
+    let library = ExportedLibrary { tracks, playlists };
+
+    let is_csv = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    let written = if is_csv {
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let playlists_path = path.with_file_name(format!("{}-playlists.csv", stem));
+        write(path, tracks_csv(&library.tracks))?;
+        write(&playlists_path, playlists_csv(&library.playlists))?;
+        vec![path.to_path_buf(), playlists_path]
+    } else {
+        let json = serde_json::to_string_pretty(&library).map_err(|e| format!("Failed to export: {}", e))?;
+        write(path, json)?;
+        vec![path.to_path_buf()]
+    };
+    tracing::info!(tracks = library.tracks.len(), playlists = library.playlists.len(), "Exported");
+    Ok(written)
+}
+
+fn write(path: &Path, contents: String) -> Result<(), String> {
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn tracks_csv(tracks: &[ExportedTrack]) -> String {
+    let mut csv = String::from("path,title,artist,duration_seconds,rating,play_count\n");
+    for track in tracks {
+        let row = [
+            csv_field(&track.path.to_string_lossy()),
+            csv_field(track.title.unwrap_or_default()),
+            csv_field(track.artist.unwrap_or_default()),
+            track.duration_seconds.map(|seconds| format!("{:.3}", seconds)).unwrap_or_default(),
+            track.rating.map(|stars| stars.to_string()).unwrap_or_default(),
+            track.play_count.to_string(),
+        ];
+        csv += &row.join(",");
+        csv.push('\n');
+    }
+    csv
+}
+
+fn playlists_csv(playlists: &[ExportedPlaylist]) -> String {
+    let mut csv = String::from("playlist,position,path\n");
+    for playlist in playlists {
+        for (position, file_path) in playlist.tracks.iter().enumerate() {
+            let row = [csv_field(playlist.name), (position + 1).to_string(), csv_field(&file_path.to_string_lossy())];
+            csv += &row.join(",");
+            csv.push('\n');
+        }
+    }
+    csv
+}
+
+/// Quotes a field if it needs it, doubling any quotes inside.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Playlist;
+    use std::time::Duration;
+
+    fn library() -> (Vec<PathBuf>, HashMap<PathBuf, TrackInfo>, LibraryDb) {
+        let files = vec![PathBuf::from("/music/Hello, \"World\".mp3"), PathBuf::from("/music/untagged.wav")];
+        let mut track_info = HashMap::new();
+        track_info.insert(
+            files[0].clone(),
+            TrackInfo { title: Some("Hello".to_string()), artist: Some("Band".to_string()), duration: Some(Duration::from_millis(61500)), ..Default::default() },
+        );
+        let mut db = LibraryDb::default();
+        let record = db.tracks.entry(files[0].clone()).or_default();
+        record.rating = Some(4);
+        record.play_count = 12;
+        db.set_playlist(Playlist { name: "Faves".to_string(), tracks: vec![files[1].clone(), files[0].clone()] });
+        (files, track_info, db)
+    }
+
+    #[test]
+    fn exports_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("library.json");
+        let (files, track_info, db) = library();
+
+        assert_eq!(export_library(&path, &files, &track_info, &db).unwrap(), vec![path.clone()]);
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(json["tracks"][0]["title"], "Hello");
+        assert_eq!(json["tracks"][0]["duration_seconds"], 61.5);
+        assert_eq!(json["tracks"][0]["rating"], 4);
+        assert_eq!(json["tracks"][0]["play_count"], 12);
+        assert_eq!(json["tracks"][1]["title"], serde_json::Value::Null);
+        assert_eq!(json["playlists"][0]["name"], "Faves");
+        assert_eq!(json["playlists"][0]["tracks"][0], "/music/untagged.wav");
+    }
+
+    #[test]
+    fn exports_csv_with_a_playlists_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("library.CSV");
+        let (files, track_info, db) = library();
+
+        let written = export_library(&path, &files, &track_info, &db).unwrap();
+        assert_eq!(written, [path.clone(), dir.path().join("library-playlists.csv")]);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "path,title,artist,duration_seconds,rating,play_count\n\
+             \"/music/Hello, \"\"World\"\".mp3\",Hello,Band,61.500,4,12\n\
+             /music/untagged.wav,,,,,0\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&written[1]).unwrap(),
+            "playlist,position,path\n\
+             Faves,1,/music/untagged.wav\n\
+             Faves,2,\"/music/Hello, \"\"World\"\".mp3\"\n"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use iced::futures::channel::mpsc as async_mpsc;
+use iced::futures::executor::block_on;
+use iced::futures::StreamExt;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::database::{passed_save_point, LibraryDb};
+use crate::effects::Leveling;
+use crate::hooks::{run_hook, HookEvent};
+use crate::library::{is_supported_audio_file, scan_folders};
+use crate::metadata::extract_track_info;
+use crate::player::{AudioCommand, AudioEngine, AudioEvent};
+use crate::plugins::{plugins_dir, Plugins};
+use crate::queue::PlayQueue;
+use crate::remote::{self, RemoteCommand, Request, TrackRef};
+use crate::settings::Settings;
+
+enum Input {
+    Remote(Request),
+    Audio(AudioEvent),
+}
+
+impl From<Request> for Input {
+    fn from(request: Request) -> Self {
+        Self::Remote(request)
+    }
+}
+
+/// Runs the library and audio engine without a window, taking commands from
+/// the remote control socket on `address` until the process is killed.
+pub fn run(address: SocketAddr) -> Result<(), String> {
+    let listener = TcpListener::bind(address).map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+    tracing::info!(%address, "Running headless, listening for remote commands");
+
+    let (inputs, received) = mpsc::channel();
+    let (audio_events, mut audio_received) = async_mpsc::channel(100);
+    let engine = AudioEngine::spawn(audio_events);
+    // Only plugin outputs matter without a window to show metadata or lyrics in
+    if let Some(dir) = plugins_dir() {
+        engine.tap.set_sinks(Plugins::load(&dir).sinks());
+    }
+    let forward = inputs.clone();
+    thread::Builder::new()
+        .name("audio-events".to_string())
+        .spawn(move || {
+            while let Some(event) = block_on(audio_received.next()) {
+                if forward.send(Input::Audio(event)).is_err() {
+                    return;
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start: {}", e))?;
+
+    let mut daemon = Daemon::new(Settings::load(), LibraryDb::load(), engine);
+    daemon.rescan();
+    remote::serve(listener, inputs);
+
+    for input in received {
+        match input {
+            Input::Remote(request) => {
+                let reply = match daemon.handle(request.command) {
+                    Ok(reply) => reply,
+                    Err(e) => format!("error: {}", e),
+                };
+                let _ = request.reply.send(reply);
+            }
+            Input::Audio(event) => daemon.on_audio_event(event),
+        }
+    }
+    Ok(())
+}
+
+/// Playback state for headless mode; the counterpart of the bits of `MusicJester`
+/// that don't involve drawing anything.
+struct Daemon {
+    settings: Settings,
+    db: LibraryDb,
+    engine: AudioEngine,
+    files: Vec<PathBuf>,
+    queue: PlayQueue,
+    now_playing: Option<PathBuf>,
+    duration: Option<Duration>,
+    position: Duration,
+    paused: bool,
+    unavailable: Option<PathBuf>, // Couldn't be reached, so it'll start again where it stopped
+}
+
+impl Daemon {
+    fn new(settings: Settings, db: LibraryDb, engine: AudioEngine) -> Self {
+        engine.send(AudioCommand::SetVolume(settings.volume));
+        engine.send(AudioCommand::SetOutputDevice(settings.output_device.clone()));
+        engine.send(AudioCommand::SetSampleRate(settings.sample_rate));
+        engine.send(AudioCommand::SetResampleQuality(settings.resample_quality));
+        engine.send(AudioCommand::SetEffects(settings.effects.clone()));
+        engine.send(AudioCommand::SetSilenceTrimming(settings.silence.clone()));
+        engine.send(AudioCommand::SetBitPerfect(settings.bit_perfect));
+        Self {
+            settings,
+            db,
+            engine,
+            files: Vec::new(),
+            queue: PlayQueue::default(),
+            now_playing: None,
+            duration: None,
+            position: Duration::ZERO,
+            paused: false,
+            unavailable: None,
+        }
+    }
+
+    /// Has the audio thread get the next track in the queue ready to start.
+    fn preload_next(&self) {
+        self.engine.send(AudioCommand::Preload(self.queue.iter().next().cloned()));
+    }
+
+    fn rescan(&mut self) {
+        self.files = scan_folders(&self.settings.library_folders, &self.settings.scan);
+    }
+
+    fn handle(&mut self, command: RemoteCommand) -> Result<String, String> {
+        match command {
+            RemoteCommand::Status => {
+                let state = match (&self.now_playing, self.paused) {
+                    (None, _) => "stopped",
+                    (Some(_), true) => "paused",
+                    (Some(_), false) => "playing",
+                };
+                let status = serde_json::json!({
+                    "state": state,
+                    "track": self.now_playing,
+                    "position": self.position.as_secs_f64(),
+                    "duration": self.duration.map(|duration| duration.as_secs_f64()),
+                    "volume": self.settings.volume,
+                    "queued": self.queue.len(),
+                    "library": self.files.len(),
+                });
+                return Ok(status.to_string());
+            }
+            RemoteCommand::List => return Ok(serde_json::json!(self.files).to_string()),
+            RemoteCommand::Queue => return Ok(serde_json::json!(self.queue.iter().collect::<Vec<_>>()).to_string()),
+            RemoteCommand::Play(track) => {
+                let file_path = self.resolve(track)?;
+                self.play(file_path);
+            }
+            RemoteCommand::Enqueue(track) => {
+                let file_path = self.resolve(track)?;
+                self.queue.push(file_path);
+                self.preload_next();
+            }
+            RemoteCommand::Next => {
+                if let Some(file_path) = &self.now_playing
+                    && !self.settings.audiobooks.contains(file_path)
+                    && self.db.note_skip(file_path, self.position, self.duration)
+                {
+                    self.db.save();
+                }
+                if !self.play_next() {
+                    return Err("The queue is empty".to_string());
+                }
+            }
+            RemoteCommand::Pause => {
+                self.engine.send(AudioCommand::Pause);
+                self.paused = self.now_playing.is_some();
+                if let Some(file_path) = &self.now_playing {
+                    self.hook(HookEvent::Paused, file_path);
+                }
+            }
+            RemoteCommand::Resume => {
+                self.engine.send(AudioCommand::Resume);
+                self.paused = false;
+            }
+            RemoteCommand::Stop => self.stop(),
+            RemoteCommand::Seek(position) => {
+                if self.now_playing.is_none() {
+                    return Err("Nothing is playing".to_string());
+                }
+                self.engine.send(AudioCommand::Seek(position));
+            }
+            RemoteCommand::Volume(volume) => {
+                self.settings.volume = volume;
+                self.settings.save();
+                self.engine.send(AudioCommand::SetVolume(volume));
+            }
+            RemoteCommand::ClearQueue => {
+                self.queue.clear();
+                self.preload_next();
+            }
+            RemoteCommand::Rescan => self.rescan(),
+        }
+        Ok("ok".to_string())
+    }
+
+    fn on_audio_event(&mut self, event: AudioEvent) {
+        match event {
+            AudioEvent::Started { file_path, duration } => {
+                tracing::info!(path = %file_path.display(), "Playing");
+                // Picking up again after the file came back isn't another play
+                let resumed = self.unavailable.take().as_ref() == Some(&file_path);
+                if !resumed {
+                    self.hook(HookEvent::TrackStarted, &file_path);
+                    if !self.settings.audiobooks.contains(&file_path) {
+                        self.db.record_play(&file_path);
+                    }
+                    self.db.save();
+                    self.position = Duration::ZERO;
+                }
+                self.now_playing = Some(file_path);
+                self.duration = duration;
+                self.paused = false;
+                self.preload_next();
+            }
+            AudioEvent::Restarted { position, paused } => {
+                self.position = position;
+                self.paused = paused;
+            }
+            AudioEvent::PositionChanged(position) => {
+                if let Some(file_path) = &self.now_playing
+                    && self.settings.audiobooks.contains(file_path)
+                {
+                    self.db.set_resume_position(file_path, Some(position));
+                    if passed_save_point(self.position, position) {
+                        self.db.save();
+                    }
+                }
+                self.position = position;
+            }
+            AudioEvent::Limiting(_) => {}
+            // The audio thread keeps trying and picks up where it stopped
+            AudioEvent::SourceUnavailable(file_path) => {
+                tracing::warn!(path = %file_path.display(), "Waiting for the file to come back");
+                self.unavailable = Some(file_path);
+            }
+            AudioEvent::TrackEnded => {
+                if let Some(file_path) = &self.now_playing {
+                    self.hook(HookEvent::TrackFinished, file_path);
+                    if self.settings.audiobooks.contains(file_path) {
+                        self.db.set_resume_position(file_path, None);
+                    } else {
+                        self.db.count_play(file_path);
+                    }
+                    self.db.save();
+                }
+                if !self.play_next() {
+                    self.stop();
+                }
+            }
+            // Already logged by the audio thread. Skip past a broken file rather than going quiet.
+            AudioEvent::Error { file_path: Some(_), .. } => {
+                if !self.play_next() {
+                    self.stop();
+                }
+            }
+            AudioEvent::Error { file_path: None, .. } => self.stop(),
+        }
+    }
+
+    fn resolve(&self, track: TrackRef) -> Result<PathBuf, String> {
+        match track {
+            TrackRef::Index(index) => {
+                self.files.get(index).cloned().ok_or_else(|| format!("No track {}, the library has {}", index, self.files.len()))
+            }
+            TrackRef::Path(file_path) if file_path.is_file() && is_supported_audio_file(&file_path) => Ok(file_path),
+            TrackRef::Path(file_path) => Err(format!("{} isn't a playable file", file_path.display())),
+        }
+    }
+
+    /// Plays `file_path` from the top, or from where it was left if it's an audiobook.
+    fn play(&mut self, file_path: PathBuf) {
+        self.engine.send(AudioCommand::SetTrackGain(self.db.gain_db(&file_path)));
+        // There's no track info kept here, so the tags are read as each track starts
+        let replay_gain_db =
+            self.settings.normalize.then(|| extract_track_info(&file_path).ok()).flatten().and_then(|info| info.replay_gain_db);
+        self.engine.send(AudioCommand::SetLeveling(Leveling::for_track(self.settings.normalize, replay_gain_db)));
+        self.engine.send(AudioCommand::SetSpeed(self.settings.audiobooks.speed_for(&file_path)));
+        if self.settings.audiobooks.contains(&file_path) {
+            let position = self.db.resume_position(&file_path);
+            self.engine.send(AudioCommand::PlayFrom(file_path, position));
+        } else {
+            self.engine.send(AudioCommand::Play(file_path));
+        }
+    }
+
+    fn play_next(&mut self) -> bool {
+        match self.queue.pop_next() {
+            Some(file_path) => {
+                self.play(file_path);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn hook(&self, event: HookEvent, file_path: &Path) {
+        if !self.settings.hooks.command(event).trim().is_empty() {
+            run_hook(&self.settings.hooks, event, file_path, extract_track_info(file_path).ok().as_ref());
+        }
+    }
+
+    fn stop(&mut self) {
+        self.engine.send(AudioCommand::Stop);
+        self.now_playing = None;
+        self.duration = None;
+        self.position = Duration::ZERO;
+        self.paused = false;
+        self.unavailable = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn daemon(files: Vec<PathBuf>) -> Daemon {
+        let (events, _) = async_mpsc::channel(10);
+        let mut daemon = Daemon::new(Settings::default(), LibraryDb::default(), AudioEngine::spawn(events));
+        daemon.files = files;
+        daemon
+    }
+
+    #[test]
+    fn enqueues_by_index_or_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let song = dir.path().join("song.mp3");
+        fs::write(&song, b"").unwrap();
+        let mut daemon = daemon(vec![PathBuf::from("/music/first.mp3")]);
+
+        assert_eq!(daemon.handle(RemoteCommand::Enqueue(TrackRef::Index(0))), Ok("ok".to_string()));
+        assert_eq!(daemon.handle(RemoteCommand::Enqueue(TrackRef::Path(song.clone()))), Ok("ok".to_string()));
+        assert!(daemon.handle(RemoteCommand::Enqueue(TrackRef::Index(1))).is_err());
+        assert!(daemon.handle(RemoteCommand::Enqueue(TrackRef::Path(dir.path().join("missing.mp3")))).is_err());
+
+        let queue: Vec<PathBuf> = serde_json::from_str(&daemon.handle(RemoteCommand::Queue).unwrap()).unwrap();
+        assert_eq!(queue, [PathBuf::from("/music/first.mp3"), song]);
+    }
+
+    #[test]
+    fn reports_status_as_json() {
+        let mut daemon = daemon(vec![PathBuf::from("/music/first.mp3")]);
+        daemon.on_audio_event(AudioEvent::Started { file_path: PathBuf::from("/music/first.mp3"), duration: Some(Duration::from_secs(200)) });
+        daemon.on_audio_event(AudioEvent::PositionChanged(Duration::from_secs(30)));
+        daemon.handle(RemoteCommand::Pause).unwrap();
+
+        let status: serde_json::Value = serde_json::from_str(&daemon.handle(RemoteCommand::Status).unwrap()).unwrap();
+        assert_eq!(status["state"], "paused");
+        assert_eq!(status["track"], "/music/first.mp3");
+        assert_eq!(status["position"], 30.0);
+        assert_eq!(status["duration"], 200.0);
+        assert_eq!(status["library"], 1);
+
+        daemon.handle(RemoteCommand::Stop).unwrap();
+        let status: serde_json::Value = serde_json::from_str(&daemon.handle(RemoteCommand::Status).unwrap()).unwrap();
+        assert_eq!(status["state"], "stopped");
+    }
+
+    #[test]
+    fn a_restart_isnt_another_play() {
+        let mut daemon = daemon(vec![PathBuf::from("/music/first.mp3")]);
+        daemon.on_audio_event(AudioEvent::Started { file_path: PathBuf::from("/music/first.mp3"), duration: Some(Duration::from_secs(200)) });
+        daemon.on_audio_event(AudioEvent::PositionChanged(Duration::from_secs(30)));
+        daemon.on_audio_event(AudioEvent::Restarted { position: Duration::from_secs(30), paused: true });
+
+        assert_eq!(daemon.db.history.len(), 1);
+        assert_eq!((daemon.position, daemon.paused), (Duration::from_secs(30), true));
+    }
+
+    #[test]
+    fn coming_back_after_the_file_was_unavailable_isnt_another_play() {
+        let song = PathBuf::from("/music/first.mp3");
+        let mut daemon = daemon(vec![song.clone()]);
+        daemon.on_audio_event(AudioEvent::Started { file_path: song.clone(), duration: Some(Duration::from_secs(200)) });
+        daemon.on_audio_event(AudioEvent::PositionChanged(Duration::from_secs(30)));
+        daemon.on_audio_event(AudioEvent::SourceUnavailable(song.clone()));
+        daemon.on_audio_event(AudioEvent::Started { file_path: song.clone(), duration: Some(Duration::from_secs(200)) });
+
+        assert_eq!(daemon.db.history.len(), 1);
+        assert_eq!(daemon.position, Duration::from_secs(30));
+        assert_eq!(daemon.unavailable, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_restart_doesnt_run_the_start_hook_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("started.log");
+        let mut daemon = daemon(vec![PathBuf::from("/music/first.mp3")]);
+        daemon.settings.hooks.track_started = format!("echo started >> '{}'", log.display());
+
+        daemon.on_audio_event(AudioEvent::Started { file_path: PathBuf::from("/music/first.mp3"), duration: None });
+        daemon.on_audio_event(AudioEvent::Restarted { position: Duration::from_secs(30), paused: false });
+        // Hooks run in the background, so give any second one time to land too
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !log.exists() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(fs::read_to_string(&log).unwrap(), "started\n");
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::metadata::TrackInfo;
+use crate::settings::HookSettings;
+
+/// Something that happened to playback, which a hook can be run for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    TrackStarted,
+    TrackFinished, // Played to the end
+    Paused,
+}
+
+impl HookEvent {
+    pub const ALL: [HookEvent; 3] = [HookEvent::TrackStarted, HookEvent::TrackFinished, HookEvent::Paused];
+
+    /// What it's called in the `MUSIC_JESTER_EVENT` variable.
+    fn name(self) -> &'static str {
+        match self {
+            HookEvent::TrackStarted => "started",
+            HookEvent::TrackFinished => "finished",
+            HookEvent::Paused => "paused",
+        }
+    }
+}
+
+impl std::fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HookEvent::TrackStarted => "Track started",
+            HookEvent::TrackFinished => "Track finished",
+            HookEvent::Paused => "Paused",
+        })
+    }
+}
+
+/// Runs the command set for `event`, if there is one, about `file_path`. It
+/// goes to the shell with `{title}`, `{artist}`, `{album}` and `{path}` filled
+/// in, quoted so odd characters in tags can't run anything, and the same values
+/// in `MUSIC_JESTER_*` environment variables. The app doesn't wait for it; what
+/// goes wrong is logged.
+pub fn run_hook(hooks: &HookSettings, event: HookEvent, file_path: &Path, info: Option<&TrackInfo>) {
+    let template = hooks.command(event).trim();
+    if template.is_empty() {
+        return;
+    }
+    let field = |value: Option<&Option<String>>| value.and_then(|value| value.clone()).unwrap_or_default();
+    let values = [
+        ("title", field(info.map(|info| &info.title))),
+        ("artist", field(info.map(|info| &info.artist))),
+        ("album", field(info.map(|info| &info.album))),
+        ("path", file_path.display().to_string()),
+    ];
+    let command_line = expand(template, &values);
+    let mut command = shell(&command_line);
+    command.env("MUSIC_JESTER_EVENT", event.name());
+    for (name, value) in &values {
+        command.env(format!("MUSIC_JESTER_{}", name.to_uppercase()), value);
+    }
+    tracing::debug!(%event, command = %command_line, "Running hook");
+    let spawned = command.spawn();
+    // Waited for off the UI thread, so it's reaped and a failure gets logged
+    let waited = std::thread::Builder::new().name("hook".to_string()).spawn(move || match spawned.and_then(|mut child| child.wait()) {
+        Ok(status) if !status.success() => tracing::warn!(%event, command = %command_line, "Hook failed: {}", status),
+        Ok(_) => {}
+        Err(e) => tracing::warn!(%event, command = %command_line, "Failed to run hook: {}", e),
+    });
+    if let Err(e) = waited {
+        tracing::warn!("Failed to start waiting for a hook: {}", e);
+    }
+}
+
+/// Fills `{name}` placeholders in `template` with their values, quoted for the shell.
+/// Anything else in braces is left as it is.
+fn expand(template: &str, values: &[(&str, String)]) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        let placeholder = &rest[open + 1..];
+        match values.iter().find(|(name, _)| placeholder.strip_prefix(name).is_some_and(|after| after.starts_with('}'))) {
+            Some((name, value)) => {
+                expanded.push_str(&quote(value));
+                rest = &placeholder[name.len() + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = placeholder;
+            }
+        }
+    }
+    expanded + rest
+}
+
+#[cfg(windows)]
+fn shell(command_line: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(command_line);
+    command
+}
+
+#[cfg(not(windows))]
+fn shell(command_line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(command_line);
+    command
+}
+
+/// `value` as a single argument. cmd has no way of escaping a double quote
+/// inside quotes, so they're dropped there.
+#[cfg(windows)]
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', ""))
+}
+
+#[cfg(not(windows))]
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_quoted_for_the_shell() {
+        let values = [("title", "Don't Stop Me Now {artist}".to_string()), ("artist", "Queen; rm -rf ~".to_string()), ("path", "/music/a b.mp3".to_string())];
+        let command = expand("notify-send {title} {artist} # {path} {unknown}", &values);
+        assert_eq!(command, r"notify-send 'Don'\''t Stop Me Now {artist}' 'Queen; rm -rf ~' # '/music/a b.mp3' {unknown}");
+
+        let output = shell(&expand("printf '%s|' {title} {artist}", &values)).output().unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "Don't Stop Me Now {artist}|Queen; rm -rf ~|");
+    }
+}
@@ -0,0 +1,213 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+// A server that stops answering for this long is given up on
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a server is: enough of an `http://` address to connect and ask it for
+/// things. Plain HTTP only, as media servers on a home network usually are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub host: String,
+    pub port: u16,
+    pub base_path: String, // Anything after the host, without a trailing slash, e.g. "/jellyfin"
+}
+
+impl Address {
+    /// Takes `http://host:port/path`, or just `host:port`.
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let url = url.trim();
+        if url.starts_with("https://") {
+            return Err("Only http:// addresses are supported; use the server's local address, e.g. http://192.168.1.10:8096".to_string());
+        }
+        let rest = url.strip_prefix("http://").unwrap_or(url);
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| format!("\"{}\" isn't a port number", port))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err("There's no server address".to_string());
+        }
+        Ok(Self { host: host.to_string(), port, base_path: path.trim_end_matches('/').to_string() })
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.base_path)
+    }
+}
+
+/// A response with a 2xx status, read as it arrives.
+pub struct Response {
+    body: Body,
+}
+
+enum Body {
+    Length(io::Take<BufReader<TcpStream>>),
+    Chunked { reader: BufReader<TcpStream>, remaining: usize, done: bool },
+    UntilClosed(BufReader<TcpStream>),
+}
+
+impl Response {
+    pub fn text(mut self) -> Result<String, String> {
+        let mut text = String::new();
+        self.read_to_string(&mut text).map_err(|e| format!("Failed to read the server's answer: {}", e))?;
+        Ok(text)
+    }
+
+    pub fn json<T: serde::de::DeserializeOwned>(self) -> Result<T, String> {
+        serde_json::from_str(&self.text()?).map_err(|e| format!("The server's answer didn't make sense: {}", e))
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        match &mut self.body {
+            Body::Length(reader) => reader.read(out),
+            Body::UntilClosed(reader) => reader.read(out),
+            Body::Chunked { reader, remaining, done } => {
+                if *done || out.is_empty() {
+                    return Ok(0);
+                }
+                if *remaining == 0 {
+                    let line = read_line(reader)?;
+                    let size = line.split(';').next().unwrap_or_default().trim();
+                    *remaining = usize::from_str_radix(size, 16).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bad chunk size"))?;
+                    if *remaining == 0 {
+                        *done = true;
+                        return Ok(0);
+                    }
+                }
+                let wanted = out.len().min(*remaining);
+                let count = reader.read(&mut out[..wanted])?;
+                if count == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                *remaining -= count;
+                if *remaining == 0 {
+                    read_line(reader)?; // The CRLF after each chunk
+                }
+                Ok(count)
+            }
+        }
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end().to_string())
+}
+
+/// Sends one request to `address` for `target` (a path under its base path,
+/// with any query), one connection per request. Anything but a 2xx answer is
+/// an error.
+pub fn request(method: &str, address: &Address, target: &str, headers: &[(&str, String)], body: Option<&[u8]>) -> Result<Response, String> {
+    let failed = |e: io::Error| format!("Failed to reach {}: {}", address, e);
+    let mut stream = TcpStream::connect((address.host.as_str(), address.port)).map_err(failed)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(failed)?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(failed)?;
+
+    let mut head = format!(
+        "{} {}{} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\nUser-Agent: music-jester/{}\r\n",
+        method,
+        address.base_path,
+        target,
+        address.host,
+        address.port,
+        env!("CARGO_PKG_VERSION")
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if let Some(body) = body {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).map_err(failed)?;
+    if let Some(body) = body {
+        stream.write_all(body).map_err(failed)?;
+    }
+
+    let mut reader = BufReader::new(stream);
+    let status_line = read_line(&mut reader).map_err(failed)?;
+    let status = status_line.split_whitespace().nth(1).and_then(|status| status.parse::<u16>().ok());
+    let Some(status) = status else {
+        return Err(format!("{} doesn't look like a web server", address));
+    };
+    let (mut length, mut chunked) = (None, false);
+    loop {
+        let line = read_line(&mut reader).map_err(failed)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.parse::<u64>().ok();
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+    if !(200..300).contains(&status) {
+        let reason = status_line.splitn(3, ' ').nth(2).unwrap_or_default();
+        return Err(format!("{} answered {} {}", address, status, reason));
+    }
+    let body = match (chunked, length) {
+        (true, _) => Body::Chunked { reader, remaining: 0, done: false },
+        (false, Some(length)) => Body::Length(reader.take(length)),
+        (false, None) => Body::UntilClosed(reader),
+    };
+    Ok(Response { body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn reads_chunked_and_sized_answers() {
+        assert_eq!(
+            Address::parse("http://media.local:8096/jellyfin/").unwrap(),
+            Address { host: "media.local".to_string(), port: 8096, base_path: "/jellyfin".to_string() }
+        );
+        assert_eq!(Address::parse("192.168.1.10").unwrap().port, 80);
+        assert!(Address::parse("https://media.local").is_err());
+        assert!(Address::parse("http://media.local:port").is_err());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let answers: [&[u8]; 3] = [
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n{\"a\"",
+                b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n",
+            ];
+            let mut targets = Vec::new();
+            for answer in answers {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                targets.push(read_line(&mut reader).unwrap());
+                while !read_line(&mut reader).unwrap().is_empty() {}
+                reader.get_mut().write_all(answer).unwrap();
+            }
+            targets
+        });
+
+        let address = Address::parse(&format!("127.0.0.1:{}/base", port)).unwrap();
+        let chunked = request("GET", &address, "/one?x=1", &[], None).unwrap().text().unwrap();
+        assert_eq!(chunked, "hello, world");
+        let sized = request("GET", &address, "/two", &[("Accept", "text/plain".to_string())], None).unwrap().text().unwrap();
+        assert_eq!(sized, "{\"a\"");
+        let refused = request("GET", &address, "/three", &[], None).err().unwrap();
+        assert!(refused.ends_with("401 Unauthorized"), "{}", refused);
+        assert_eq!(server.join().unwrap(), ["GET /base/one?x=1 HTTP/1.1", "GET /base/two HTTP/1.1", "GET /base/three HTTP/1.1"]);
+    }
+}
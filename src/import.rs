@@ -0,0 +1,416 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::{LibraryDb, Playlist};
+
+/// Ratings, play counts and playlists read from another player's library,
+/// with its paths already mapped onto files in ours.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Import {
+    pub tracks: Vec<ImportedTrack>,
+    pub playlists: Vec<Playlist>,
+    pub unmatched: usize, // Tracks we couldn't find locally
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedTrack {
+    pub file_path: PathBuf,
+    pub rating: Option<u8>, // Stars, 1 to 5
+    pub play_count: u32,
+}
+
+/// Reads an iTunes `iTunes Library.xml` or a Rhythmbox `rhythmdb.xml` (picking up
+/// `playlists.xml` next to it), mapping its tracks onto `library`.
+#[tracing::instrument(skip(library), fields(path = %path.display(), library = library.len()))]
+pub fn import_library(path: &Path, library: &[PathBuf]) -> Result<Import, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let (tracks, playlists) = if contents.contains("<plist") {
+        read_itunes(&contents)?
+    } else {
+        let playlists = fs::read_to_string(path.with_file_name("playlists.xml")).ok();
+        read_rhythmbox(&contents, playlists.as_deref())?
+    };
+
+    let matcher = PathMatcher::new(library);
+    let mut import = Import::default();
+    for track in tracks {
+        match matcher.find(&track.file_path) {
+            Some(file_path) => import.tracks.push(ImportedTrack { file_path: file_path.clone(), ..track }),
+            None => import.unmatched += 1,
+        }
+    }
+    import.playlists = playlists
+        .into_iter()
+        .map(|playlist| Playlist {
+            tracks: playlist.tracks.iter().filter_map(|file_path| matcher.find(file_path)).cloned().collect(),
+            ..playlist
+        })
+        .collect();
+    tracing::info!(tracks = import.tracks.len(), unmatched = import.unmatched, playlists = import.playlists.len(), "Imported");
+    Ok(import)
+}
+
+impl Import {
+    /// Merges into `db`. Imported ratings win, play counts keep whichever is higher
+    /// so importing twice doesn't double them, and playlists replace ones with the same name.
+    pub fn apply_to(self, db: &mut LibraryDb) {
+        for track in self.tracks {
+            let record = db.tracks.entry(track.file_path).or_default();
+            record.rating = track.rating.or(record.rating);
+            record.play_count = record.play_count.max(track.play_count);
+        }
+        for playlist in self.playlists {
+            db.set_playlist(playlist);
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Imported {} tracks and {} playlists", self.tracks.len(), self.playlists.len());
+        if self.unmatched > 0 {
+            summary += &format!(", {} tracks weren't found in your library", self.unmatched);
+        }
+        summary
+    }
+}
+
+#[derive(Deserialize)]
+struct ITunesLibrary {
+    #[serde(rename = "Tracks", default)]
+    tracks: HashMap<String, ITunesTrack>,
+    #[serde(rename = "Playlists", default)]
+    playlists: Vec<ITunesPlaylist>,
+}
+
+#[derive(Deserialize)]
+struct ITunesTrack {
+    #[serde(rename = "Track ID")]
+    id: u64,
+    #[serde(rename = "Location")]
+    location: Option<String>,
+    #[serde(rename = "Play Count", default)]
+    play_count: u32,
+    #[serde(rename = "Rating")]
+    rating: Option<u8>, // 0 to 100, 20 per star
+    #[serde(rename = "Rating Computed", default)]
+    rating_computed: bool, // Inherited from the album rather than set on the track
+}
+
+#[derive(Deserialize)]
+struct ITunesPlaylist {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Master", default)]
+    master: bool,
+    #[serde(rename = "Distinguished Kind")]
+    distinguished_kind: Option<u32>,
+    #[serde(rename = "Folder", default)]
+    folder: bool,
+    #[serde(rename = "Playlist Items", default)]
+    items: Vec<ITunesPlaylistItem>,
+}
+
+#[derive(Deserialize)]
+struct ITunesPlaylistItem {
+    #[serde(rename = "Track ID")]
+    id: u64,
+}
+
+fn read_itunes(contents: &str) -> Result<(Vec<ImportedTrack>, Vec<Playlist>), String> {
+    let library: ITunesLibrary =
+        plist::from_bytes(contents.as_bytes()).map_err(|e| format!("Not an iTunes library: {}", e))?;
+
+    let locations: HashMap<u64, PathBuf> = library
+        .tracks
+        .values()
+        .filter_map(|track| Some((track.id, path_from_file_url(track.location.as_deref()?)?)))
+        .collect();
+    let tracks = library
+        .tracks
+        .into_values()
+        .filter_map(|track| {
+            Some(ImportedTrack {
+                file_path: locations.get(&track.id)?.clone(),
+                rating: track.rating.filter(|_| !track.rating_computed).map(|rating| rating / 20).filter(|&stars| stars > 0),
+                play_count: track.play_count,
+            })
+        })
+        .collect();
+
+    // Skip the built-in ones (Library, Music, Podcasts...) and folders
+    let playlists = library
+        .playlists
+        .into_iter()
+        .filter(|playlist| !playlist.master && playlist.distinguished_kind.is_none() && !playlist.folder)
+        .map(|playlist| Playlist {
+            name: playlist.name,
+            tracks: playlist.items.iter().filter_map(|item| locations.get(&item.id).cloned()).collect(),
+        })
+        .collect();
+    Ok((tracks, playlists))
+}
+
+fn read_rhythmbox(contents: &str, playlists: Option<&str>) -> Result<(Vec<ImportedTrack>, Vec<Playlist>), String> {
+    let doc = roxmltree::Document::parse(contents).map_err(|e| format!("Not a Rhythmbox database: {}", e))?;
+    if !doc.root_element().has_tag_name("rhythmdb") {
+        return Err("Not an iTunes library or Rhythmbox database".to_string());
+    }
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children().find(|child| child.has_tag_name(name)).and_then(|child| child.text()).map(str::trim).map(str::to_string)
+    };
+
+    let tracks = doc
+        .root_element()
+        .children()
+        .filter(|entry| entry.has_tag_name("entry") && entry.attribute("type") == Some("song"))
+        .filter_map(|entry| {
+            Some(ImportedTrack {
+                file_path: path_from_file_url(&child_text(entry, "location")?)?,
+                // Older versions write the rating as a float
+                rating: child_text(entry, "rating")
+                    .and_then(|rating| rating.parse::<f32>().ok())
+                    .map(|stars| stars.round() as u8)
+                    .filter(|&stars| stars > 0),
+                play_count: child_text(entry, "play-count").and_then(|count| count.parse().ok()).unwrap_or(0),
+            })
+        })
+        .collect();
+
+    let mut imported_playlists = Vec::new();
+    if let Some(playlists) = playlists {
+        let doc = roxmltree::Document::parse(playlists).map_err(|e| format!("Failed to read playlists.xml: {}", e))?;
+        // Automatic playlists are saved searches with no tracks of their own
+        for playlist in doc.root_element().children().filter(|node| node.has_tag_name("playlist")) {
+            if playlist.attribute("type") != Some("static") {
+                continue;
+            }
+            let Some(name) = playlist.attribute("name") else { continue };
+            let tracks = playlist
+                .children()
+                .filter(|node| node.has_tag_name("location"))
+                .filter_map(|node| path_from_file_url(node.text()?.trim()))
+                .collect();
+            imported_playlists.push(Playlist { name: name.to_string(), tracks });
+        }
+    }
+    Ok((tracks, imported_playlists))
+}
+
+/// `file://localhost/Users/me/My%20Song.mp3` to `/Users/me/My Song.mp3`, and
+/// `file:///C:/Music/x.mp3` to `C:/Music/x.mp3`.
+fn path_from_file_url(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    let path = percent_decode(path)?;
+    let is_windows_drive = path.len() > 2 && path.as_bytes()[2] == b':' && path.starts_with('/');
+    Some(PathBuf::from(if is_windows_drive { &path[1..] } else { &path[..] }))
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Finds our copy of a file from another machine: the library file with the same
+/// name whose parent folders match the most, as long as there's a clear winner.
+struct PathMatcher<'a> {
+    exact: HashMap<&'a Path, &'a PathBuf>,
+    by_name: HashMap<String, Vec<&'a PathBuf>>,
+}
+
+impl<'a> PathMatcher<'a> {
+    fn new(library: &'a [PathBuf]) -> Self {
+        let mut by_name: HashMap<String, Vec<&PathBuf>> = HashMap::new();
+        for file_path in library {
+            if let Some(name) = file_path.file_name() {
+                by_name.entry(name.to_string_lossy().to_lowercase()).or_default().push(file_path);
+            }
+        }
+        Self { exact: library.iter().map(|file_path| (file_path.as_path(), file_path)).collect(), by_name }
+    }
+
+    fn find(&self, file_path: &Path) -> Option<&'a PathBuf> {
+        if let Some(&exact) = self.exact.get(file_path) {
+            return Some(exact);
+        }
+        let candidates = self.by_name.get(&file_path.file_name()?.to_string_lossy().to_lowercase())?;
+        let mut scored: Vec<_> = candidates.iter().map(|candidate| (matching_tail(candidate, file_path), *candidate)).collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        match scored.as_slice() {
+            [(best, file_path), (second, _), ..] if best > second => Some(file_path),
+            [(_, file_path)] => Some(file_path),
+            _ => None,
+        }
+    }
+}
+
+/// How many trailing path components two paths share, ignoring case.
+fn matching_tail(a: &Path, b: &Path) -> usize {
+    a.components()
+        .rev()
+        .zip(b.components().rev())
+        .take_while(|(a, b)| a.as_os_str().to_string_lossy().to_lowercase() == b.as_os_str().to_string_lossy().to_lowercase())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITUNES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Major Version</key><integer>1</integer>
+    <key>Tracks</key>
+    <dict>
+        <key>101</key>
+        <dict>
+            <key>Track ID</key><integer>101</integer>
+            <key>Name</key><string>Windowlicker</string>
+            <key>Play Count</key><integer>42</integer>
+            <key>Rating</key><integer>100</integer>
+            <key>Location</key><string>file://localhost/Users/sam/Music/iTunes/iTunes%20Media/Music/Aphex%20Twin/Windowlicker/01%20Windowlicker.mp3</string>
+        </dict>
+        <key>102</key>
+        <dict>
+            <key>Track ID</key><integer>102</integer>
+            <key>Rating</key><integer>60</integer>
+            <key>Rating Computed</key><true/>
+            <key>Location</key><string>file://localhost/Users/sam/Music/iTunes/iTunes%20Media/Music/Gone/Gone.m4a</string>
+        </dict>
+    </dict>
+    <key>Playlists</key>
+    <array>
+        <dict>
+            <key>Name</key><string>Library</string>
+            <key>Master</key><true/>
+            <key>Playlist Items</key><array><dict><key>Track ID</key><integer>101</integer></dict></array>
+        </dict>
+        <dict>
+            <key>Name</key><string>Late nights</string>
+            <key>Playlist Items</key>
+            <array>
+                <dict><key>Track ID</key><integer>101</integer></dict>
+                <dict><key>Track ID</key><integer>102</integer></dict>
+            </array>
+        </dict>
+    </array>
+</dict>
+</plist>"#;
+
+    const RHYTHMDB: &str = r#"<?xml version="1.0" standalone="yes"?>
+<rhythmdb version="2.0">
+  <entry type="song">
+    <title>Hoppípolla</title>
+    <location>file:///home/sam/Music/Sigur%20R%C3%B3s/Takk/03%20Hopp%C3%ADpolla.flac</location>
+    <play-count>7</play-count>
+    <rating>4.000000</rating>
+  </entry>
+  <entry type="podcast-post">
+    <location>https://example.com/episode.mp3</location>
+  </entry>
+</rhythmdb>"#;
+
+    const RHYTHMBOX_PLAYLISTS: &str = r#"<?xml version="1.0"?>
+<rhythmdb-playlists>
+  <playlist name="Recently Added" type="automatic"><conjunction/></playlist>
+  <playlist name="Icelandic" type="static">
+    <location>file:///home/sam/Music/Sigur%20R%C3%B3s/Takk/03%20Hopp%C3%ADpolla.flac</location>
+  </playlist>
+</rhythmdb-playlists>"#;
+
+    #[test]
+    fn reads_itunes_ratings_counts_and_playlists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("iTunes Library.xml");
+        fs::write(&path, ITUNES).unwrap();
+        let library = vec![
+            PathBuf::from("/home/sam/music/Aphex Twin/Windowlicker/01 Windowlicker.mp3"),
+            PathBuf::from("/home/sam/music/Other/Thing.mp3"),
+        ];
+
+        let import = import_library(&path, &library).unwrap();
+        assert_eq!(import.tracks, [ImportedTrack { file_path: library[0].clone(), rating: Some(5), play_count: 42 }]);
+        assert_eq!(import.unmatched, 1);
+        assert_eq!(import.playlists, [Playlist { name: "Late nights".to_string(), tracks: vec![library[0].clone()] }]);
+    }
+
+    #[test]
+    fn reads_rhythmbox_with_its_playlists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rhythmdb.xml");
+        fs::write(&path, RHYTHMDB).unwrap();
+        fs::write(dir.path().join("playlists.xml"), RHYTHMBOX_PLAYLISTS).unwrap();
+        let library = vec![PathBuf::from("/home/sam/Music/Sigur Rós/Takk/03 Hoppípolla.flac")];
+
+        let import = import_library(&path, &library).unwrap();
+        assert_eq!(import.tracks, [ImportedTrack { file_path: library[0].clone(), rating: Some(4), play_count: 7 }]);
+        assert_eq!(import.unmatched, 0);
+        assert_eq!(import.playlists, [Playlist { name: "Icelandic".to_string(), tracks: library.clone() }]);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.xml");
+        fs::write(&path, "<notes/>").unwrap();
+        assert!(import_library(&path, &[]).is_err());
+        assert!(import_library(&dir.path().join("missing.xml"), &[]).is_err());
+    }
+
+    #[test]
+    fn decodes_file_urls() {
+        assert_eq!(path_from_file_url("file://localhost/Users/sam/My%20Song.mp3"), Some(PathBuf::from("/Users/sam/My Song.mp3")));
+        assert_eq!(path_from_file_url("file:///C:/Music/%C3%A9t%C3%A9.mp3"), Some(PathBuf::from("C:/Music/été.mp3")));
+        assert_eq!(path_from_file_url("https://example.com/a.mp3"), None);
+        assert_eq!(path_from_file_url("file:///bad%2"), None);
+    }
+
+    #[test]
+    fn matches_the_closest_folder() {
+        let library = vec![
+            PathBuf::from("/music/Björk/Post/Intro.mp3"),
+            PathBuf::from("/music/Massive Attack/Mezzanine/Intro.mp3"),
+            PathBuf::from("/music/A/Live/Intro.mp3"),
+            PathBuf::from("/music/B/Live/Intro.mp3"),
+        ];
+        let matcher = PathMatcher::new(&library);
+        assert_eq!(matcher.find(Path::new("/Users/sam/iTunes/Massive Attack/Mezzanine/intro.MP3")), Some(&library[1]));
+        assert_eq!(matcher.find(&library[0]), Some(&library[0]));
+        // Two equally good guesses is no answer at all
+        assert_eq!(matcher.find(Path::new("/Users/sam/iTunes/C/Live/Intro.mp3")), None);
+        assert_eq!(matcher.find(Path::new("/Users/sam/iTunes/Outro.mp3")), None);
+    }
+
+    #[test]
+    fn import_merges_into_the_database() {
+        let mut db = LibraryDb::default();
+        let song = PathBuf::from("/music/song.mp3");
+        db.tracks.entry(song.clone()).or_default().play_count = 10;
+        db.set_playlist(Playlist { name: "Mine".to_string(), tracks: Vec::new() });
+
+        let import = Import {
+            tracks: vec![ImportedTrack { file_path: song.clone(), rating: Some(3), play_count: 4 }],
+            playlists: vec![Playlist { name: "Theirs".to_string(), tracks: vec![song.clone()] }],
+            unmatched: 0,
+        };
+        import.apply_to(&mut db);
+
+        assert_eq!(db.tracks[&song].rating, Some(3));
+        assert_eq!(db.tracks[&song].play_count, 10);
+        assert_eq!(db.playlists.len(), 2);
+    }
+}
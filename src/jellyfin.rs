@@ -0,0 +1,327 @@
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cache;
+use crate::http::{request, Address};
+use crate::metadata::TrackInfo;
+use crate::project_dirs;
+use crate::settings::JellyfinSettings;
+
+const CLIENT: &str = "Music Jester";
+// Jellyfin counts time in ticks of 100 ns
+const TICKS_PER_SECOND: f64 = 10_000_000.0;
+const TRANSCODE_BITRATE: u32 = 320_000;
+// Downloads past this are dropped, those played longest ago first
+const DOWNLOADS_LIMIT: u64 = 2 * 1024 * 1024 * 1024;
+
+/// What to browse on the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Collection {
+    #[default]
+    Albums,
+    Playlists,
+}
+
+impl Collection {
+    pub const ALL: [Collection; 2] = [Collection::Albums, Collection::Playlists];
+}
+
+impl std::fmt::Display for Collection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Collection::Albums => "Albums",
+            Collection::Playlists => "Playlists",
+        })
+    }
+}
+
+/// An album or playlist on the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteItem {
+    pub id: String,
+    pub name: String,
+    pub artist: Option<String>,
+    pub year: Option<u32>,
+    pub collection: Collection,
+}
+
+impl std::fmt::Display for RemoteItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(artist) = &self.artist {
+            write!(f, " – {}", artist)?;
+        }
+        if let Some(year) = self.year {
+            write!(f, " ({})", year)?;
+        }
+        Ok(())
+    }
+}
+
+/// A track on the server, with the details it has for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteTrack {
+    pub id: String,
+    pub info: TrackInfo,
+    container: Option<String>, // The original file's format, e.g. "flac"
+}
+
+/// How far through a server track playback is, to report back to the server
+/// so it shows up in the dashboard and marks what's been played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Playback {
+    Started,
+    Progress { position: Duration, paused: bool },
+    Stopped { position: Duration },
+}
+
+/// Jellyfin's JSON for an item, the parts used here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+struct Item {
+    id: String,
+    name: Option<String>,
+    album: Option<String>,
+    album_artist: Option<String>,
+    artists: Vec<String>,
+    genres: Vec<String>,
+    production_year: Option<u32>,
+    index_number: Option<u32>,
+    parent_index_number: Option<u32>,
+    run_time_ticks: Option<u64>,
+    container: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Items {
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Authentication {
+    access_token: String,
+    user: User,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct User {
+    id: String,
+}
+
+impl Item {
+    fn into_remote_item(self, collection: Collection) -> RemoteItem {
+        RemoteItem { name: self.name.unwrap_or_else(|| "Untitled".to_string()), artist: self.album_artist, year: self.production_year, id: self.id, collection }
+    }
+
+    fn into_track(self) -> RemoteTrack {
+        let info = TrackInfo {
+            title: self.name,
+            artist: self.artists.into_iter().next().or_else(|| self.album_artist.clone()),
+            album: self.album,
+            album_artist: self.album_artist,
+            genre: self.genres.into_iter().next(),
+            year: self.production_year,
+            track_number: self.index_number,
+            disc_number: self.parent_index_number,
+            duration: self.run_time_ticks.map(|ticks| Duration::from_secs_f64(ticks as f64 / TICKS_PER_SECOND)),
+            ..Default::default()
+        };
+        RemoteTrack { id: self.id, info, container: self.container }
+    }
+}
+
+/// Signs in to the server in `settings` as its user, returning the settings
+/// with the access token filled in.
+pub fn sign_in(settings: &JellyfinSettings, password: &str) -> Result<JellyfinSettings, String> {
+    let mut signed_in = JellyfinSettings { user_id: String::new(), token: String::new(), ..settings.clone() };
+    if signed_in.device_id.is_empty() {
+        signed_in.device_id = format!("{:016x}", fastrand::u64(..));
+    }
+    let server = Address::parse(&signed_in.server)?;
+    let body = serde_json::json!({ "Username": signed_in.username, "Pw": password }).to_string();
+    let headers = [("Authorization", authorization(&signed_in.device_id, None)), ("Content-Type", "application/json".to_string())];
+    let authentication: Authentication = request("POST", &server, "/Users/AuthenticateByName", &headers, Some(body.as_bytes()))
+        .map_err(|e| if e.ends_with("401 Unauthorized") { "Wrong username or password".to_string() } else { e })?
+        .json()?;
+    signed_in.user_id = authentication.user.id;
+    signed_in.token = authentication.access_token;
+    Ok(signed_in)
+}
+
+/// The header every request identifies the app (and once signed in, the user) with.
+fn authorization(device_id: &str, token: Option<&str>) -> String {
+    let mut header = format!(
+        "MediaBrowser Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
+        CLIENT,
+        std::env::consts::OS,
+        device_id,
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(token) = token {
+        header.push_str(&format!(", Token=\"{}\"", token));
+    }
+    header
+}
+
+/// A signed in connection to a Jellyfin server.
+#[derive(Debug, Clone)]
+pub struct Session {
+    server: Address,
+    user_id: String,
+    token: String,
+    device_id: String,
+}
+
+impl Session {
+    /// `None` until signed in.
+    pub fn new(settings: &JellyfinSettings) -> Option<Self> {
+        if !settings.signed_in() {
+            return None;
+        }
+        let server = Address::parse(&settings.server).ok()?;
+        Some(Self { server, user_id: settings.user_id.clone(), token: settings.token.clone(), device_id: settings.device_id.clone() })
+    }
+
+    fn headers(&self) -> Vec<(&'static str, String)> {
+        vec![("Authorization", authorization(&self.device_id, Some(&self.token))), ("Accept", "application/json".to_string())]
+    }
+
+    fn items(&self, target: &str) -> Result<Vec<Item>, String> {
+        Ok(request("GET", &self.server, target, &self.headers(), None)?.json::<Items>()?.items)
+    }
+
+    /// Every album or every playlist, sorted by name.
+    pub fn list(&self, collection: Collection) -> Result<Vec<RemoteItem>, String> {
+        let kind = match collection {
+            Collection::Albums => "MusicAlbum&SortBy=AlbumArtist,SortName",
+            Collection::Playlists => "Playlist&SortBy=SortName",
+        };
+        let items = self.items(&format!("/Users/{}/Items?Recursive=true&IncludeItemTypes={}", self.user_id, kind))?;
+        Ok(items.into_iter().map(|item| item.into_remote_item(collection)).collect())
+    }
+
+    /// An album's tracks by disc and track number, or a playlist's in its order.
+    pub fn tracks(&self, item: &RemoteItem) -> Result<Vec<RemoteTrack>, String> {
+        let target = match item.collection {
+            Collection::Albums => format!(
+                "/Users/{}/Items?ParentId={}&IncludeItemTypes=Audio&Recursive=true&SortBy=ParentIndexNumber,IndexNumber,SortName",
+                self.user_id, item.id
+            ),
+            Collection::Playlists => format!("/Playlists/{}/Items?UserId={}&IncludeItemTypes=Audio", item.id, self.user_id),
+        };
+        Ok(self.items(&target)?.into_iter().map(Item::into_track).collect())
+    }
+
+    /// Downloads `track` into the downloads folder, all of it before it's played,
+    /// so it can be played like any other file: the original, or an MP3 the
+    /// server converts it to on the way if `transcode` is set. A track that's
+    /// already there isn't fetched again.
+    pub fn fetch(&self, track: &RemoteTrack, transcode: bool) -> Result<PathBuf, String> {
+        let dir = downloads_dir().ok_or("There's no cache folder to download to")?;
+        let (name, target) = if transcode {
+            (format!("{}.transcoded.mp3", track.id), format!("/Audio/{}/stream.mp3?AudioCodec=mp3&AudioBitRate={}", track.id, TRANSCODE_BITRATE))
+        } else {
+            // Containers can be a list, e.g. "mp4,m4a"; the first names the file well enough
+            let container = track.container.as_deref().and_then(|container| container.split(',').next()).filter(|container| !container.is_empty());
+            (format!("{}.{}", track.id, container.unwrap_or("audio")), format!("/Audio/{}/stream?Static=true", track.id))
+        };
+        let file_path = dir.join(name);
+        if file_path.exists() {
+            cache::touch(&file_path);
+            return Ok(file_path);
+        }
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let mut response = request("GET", &self.server, &target, &self.headers(), None)?;
+        // Downloaded beside it first, so a dropped connection doesn't leave half a track to play next time
+        let partial = file_path.with_extension("part");
+        let copied = fs::File::create(&partial).and_then(|mut file| io::copy(&mut response, &mut file)).and_then(|_| fs::rename(&partial, &file_path));
+        if let Err(e) = copied {
+            let _ = fs::remove_file(&partial);
+            return Err(format!("Failed to download {}: {}", track.info.title.as_deref().unwrap_or(&track.id), e));
+        }
+        cache::prune(&dir, DOWNLOADS_LIMIT, &file_path);
+        Ok(file_path)
+    }
+
+    /// Tells the server how playback of `item_id` is going. Sent in the
+    /// background; a failure is only logged, as playback carries on regardless.
+    pub fn report(&self, item_id: &str, playback: Playback) {
+        let (target, position, paused) = match playback {
+            Playback::Started => ("/Sessions/Playing", Duration::ZERO, false),
+            Playback::Progress { position, paused } => ("/Sessions/Playing/Progress", position, paused),
+            Playback::Stopped { position } => ("/Sessions/Playing/Stopped", position, false),
+        };
+        let body = serde_json::json!({
+            "ItemId": item_id,
+            "PositionTicks": (position.as_secs_f64() * TICKS_PER_SECOND) as u64,
+            "IsPaused": paused,
+            "CanSeek": true,
+        })
+        .to_string();
+        let session = self.clone();
+        let sent = std::thread::Builder::new().name("jellyfin".to_string()).spawn(move || {
+            let mut headers = session.headers();
+            headers.push(("Content-Type", "application/json".to_string()));
+            if let Err(e) = request("POST", &session.server, target, &headers, Some(body.as_bytes())) {
+                tracing::warn!(?playback, "Failed to report playback to Jellyfin: {}", e);
+            }
+        });
+        if let Err(e) = sent {
+            tracing::warn!("Failed to start reporting playback: {}", e);
+        }
+    }
+}
+
+/// Where tracks from the server are kept once downloaded.
+pub fn downloads_dir() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.cache_dir().join("jellyfin"))
+}
+
+/// The server's id for a track, if `file_path` was downloaded from it.
+pub fn item_id(file_path: &Path) -> Option<String> {
+    item_id_in(file_path, &downloads_dir()?)
+}
+
+fn item_id_in(file_path: &Path, dir: &Path) -> Option<String> {
+    if file_path.parent() != Some(dir) {
+        return None;
+    }
+    // Everything up to the first dot, which also drops ".transcoded"
+    let name = file_path.file_name()?.to_string_lossy();
+    name.split('.').next().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_items_from_the_server() {
+        let json = r#"{"Items": [{
+            "Id": "a1b2", "Name": "Teardrop", "Album": "Mezzanine", "AlbumArtist": "Massive Attack",
+            "Artists": ["Massive Attack", "Elizabeth Fraser"], "Genres": ["Trip Hop"], "ProductionYear": 1998,
+            "IndexNumber": 3, "ParentIndexNumber": 1, "RunTimeTicks": 3305000000, "Container": "flac", "Type": "Audio"
+        }], "TotalRecordCount": 1}"#;
+        let track = serde_json::from_str::<Items>(json).unwrap().items.remove(0).into_track();
+        assert_eq!(track.id, "a1b2");
+        assert_eq!(track.info.title.as_deref(), Some("Teardrop"));
+        assert_eq!(track.info.artist.as_deref(), Some("Massive Attack"));
+        assert_eq!((track.info.track_number, track.info.disc_number), (Some(3), Some(1)));
+        assert_eq!(track.info.duration, Some(Duration::from_millis(330_500)));
+
+        let album = Item { id: "c3".to_string(), name: Some("Mezzanine".to_string()), album_artist: Some("Massive Attack".to_string()), production_year: Some(1998), ..Default::default() };
+        assert_eq!(album.into_remote_item(Collection::Albums).to_string(), "Mezzanine – Massive Attack (1998)");
+
+        let dir = Path::new("/cache/jellyfin");
+        assert_eq!(item_id_in(&dir.join("a1b2.flac"), dir).as_deref(), Some("a1b2"));
+        assert_eq!(item_id_in(&dir.join("a1b2.transcoded.mp3"), dir).as_deref(), Some("a1b2"));
+        assert_eq!(item_id_in(Path::new("/music/a1b2.flac"), dir), None);
+        assert!(authorization("d", Some("t")).ends_with(", Token=\"t\""));
+    }
+}
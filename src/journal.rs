@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+
+use crate::database::{Bookmark, TrackRecord};
+
+// Older changes are forgotten past this many
+const MAX_CHANGES: usize = 50;
+
+/// Destructive changes the user made, with what's needed to put things back,
+/// newest last. Only kept for the session.
+#[derive(Debug, Default)]
+pub struct Journal {
+    changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Tracks taken out of the library list, files left alone.
+    Removed(Vec<Removal>),
+    /// Tracks whose files went to the trash.
+    Trashed(Vec<Removal>),
+    DeletedBookmark { file_path: PathBuf, bookmark: Bookmark },
+}
+
+/// A track that left the library, where it was in the list and what the
+/// database knew about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Removal {
+    pub file_path: PathBuf,
+    pub position: usize,
+    pub record: Option<TrackRecord>,
+}
+
+impl Journal {
+    pub fn record(&mut self, change: Change) {
+        if self.changes.len() == MAX_CHANGES {
+            self.changes.remove(0);
+        }
+        self.changes.push(change);
+    }
+
+    /// Takes the latest change off the journal, to be undone.
+    pub fn undo(&mut self) -> Option<Change> {
+        self.changes.pop()
+    }
+
+    pub fn last(&self) -> Option<&Change> {
+        self.changes.last()
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tracks = |removals: &[Removal]| match removals {
+            [removal] => display_name(&removal.file_path),
+            _ => format!("{} tracks", removals.len()),
+        };
+        match self {
+            Change::Removed(removals) => write!(f, "removing {} from the library", tracks(removals)),
+            Change::Trashed(removals) => write!(f, "moving {} to the trash", tracks(removals)),
+            Change::DeletedBookmark { bookmark, .. } => write!(f, "deleting bookmark \"{}\"", bookmark.name),
+        }
+    }
+}
+
+/// Puts `file_path` back from the trash, taking the most recently deleted file
+/// if it was trashed more than once.
+#[cfg(any(target_os = "windows", all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))))]
+pub fn restore_from_trash(file_path: &Path) -> Result<(), String> {
+    use trash::os_limited;
+
+    let items = os_limited::list().map_err(|e| e.to_string())?;
+    let item = items
+        .into_iter()
+        .filter(|item| item.original_path() == file_path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| format!("{} is no longer in the trash", display_name(file_path)))?;
+    os_limited::restore_all([item]).map_err(|e| e.to_string())
+}
+
+#[cfg(not(any(target_os = "windows", all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android")))))]
+pub fn restore_from_trash(file_path: &Path) -> Result<(), String> {
+    Err(format!("Restore {} from the trash by hand; this system doesn't let apps do it", display_name(file_path)))
+}
+
+fn display_name(file_path: &Path) -> String {
+    file_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn removal(name: &str) -> Removal {
+        Removal { file_path: PathBuf::from("/music").join(name), position: 0, record: None }
+    }
+
+    #[test]
+    fn undoes_newest_first_and_forgets_the_oldest() {
+        let mut journal = Journal::default();
+        for number in 0..=MAX_CHANGES {
+            journal.record(Change::Removed(vec![removal(&format!("{}.mp3", number))]));
+        }
+        journal.record(Change::DeletedBookmark {
+            file_path: PathBuf::from("/music/book.m4b"),
+            bookmark: Bookmark { name: "Chapter 3".to_string(), position: Duration::from_secs(600) },
+        });
+
+        assert_eq!(journal.last().unwrap().to_string(), "deleting bookmark \"Chapter 3\"");
+        journal.undo();
+        assert_eq!(journal.undo().unwrap().to_string(), format!("removing {}.mp3 from the library", MAX_CHANGES));
+        let mut undone = 1;
+        while journal.undo().is_some() {
+            undone += 1;
+        }
+        // The two oldest removals fell off the end
+        assert_eq!(undone, MAX_CHANGES - 1);
+        assert_eq!(Change::Trashed(vec![removal("a.mp3"), removal("b.mp3")]).to_string(), "moving 2 tracks to the trash");
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::collate::natural_cmp;
+use crate::metadata::TrackInfo;
+use crate::settings::{AudiobookSettings, ScanSettings};
+
+/// File extensions the library picks up.
+pub const AUDIO_EXTENSIONS: [&str; 5] = ["mp3", "m4a", "flac", "wav", "ogg"];
+
+/// Audio files under `dir`, each folder's contents in natural order, at most `settings.max_depth` folders down. Anything
+/// whose real path is already in `visited` is skipped and everything scanned is
+/// added, so symlink loops end and a folder reached two ways is only listed once.
+pub fn find_audio_files(dir: &Path, settings: &ScanSettings, visited: &mut HashSet<PathBuf>) -> Vec<PathBuf> {
+    let mut audio_files = Vec::new();
+    let walker = WalkDir::new(dir)
+        .follow_links(settings.follow_symlinks)
+        .max_depth(settings.max_depth)
+        .sort_by(|a, b| natural_cmp(&a.file_name().to_string_lossy(), &b.file_name().to_string_lossy()))
+        .into_iter();
+    let walker = walker.filter_entry(|entry| {
+        // Only folders can lead somewhere already seen, unless links are followed
+        if !entry.file_type().is_dir() && !settings.follow_symlinks {
+            return true;
+        }
+        match fs::canonicalize(entry.path()) {
+            Ok(real_path) => visited.insert(real_path),
+            Err(_) => true,
+        }
+    });
+    for entry in walker {
+        match entry {
+            Ok(entry) if entry.file_type().is_file() && is_supported_audio_file(entry.path()) => audio_files.push(entry.into_path()),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Skipped while scanning: {}", e),
+        }
+    }
+    audio_files
+}
+
+/// Audio files in all of `folders`, in folder order.
+#[tracing::instrument(skip(settings))]
+pub fn scan_folders(folders: &[PathBuf], settings: &ScanSettings) -> Vec<PathBuf> {
+    let mut visited = HashSet::new();
+    let files: Vec<PathBuf> = folders.iter().flat_map(|folder| find_audio_files(folder, settings, &mut visited)).collect();
+    tracing::info!(files = files.len(), "Scan complete");
+    files
+}
+
+/// Puts the audiobook files in `files` in reading order: folder by folder, by
+/// disc and track number where they're tagged, otherwise by name as scanned.
+/// Everything else stays where it is.
+pub fn order_audiobooks(files: &mut [PathBuf], audiobooks: &AudiobookSettings, track_info: &HashMap<PathBuf, TrackInfo>) {
+    let slots: Vec<usize> = (0..files.len()).filter(|&index| audiobooks.contains(&files[index])).collect();
+    let mut folders: Vec<&Path> = Vec::new(); // In the order they were scanned
+    let mut books: Vec<((usize, u32, u32), PathBuf)> = Vec::with_capacity(slots.len());
+    for &index in &slots {
+        let file_path = &files[index];
+        let folder = file_path.parent().unwrap_or(Path::new(""));
+        let folder_index = folders.iter().position(|seen| *seen == folder).unwrap_or_else(|| {
+            folders.push(folder);
+            folders.len() - 1
+        });
+        let info = track_info.get(file_path);
+        let disc = info.and_then(|info| info.disc_number).unwrap_or(1);
+        let track = info.and_then(|info| info.track_number).unwrap_or(u32::MAX);
+        books.push(((folder_index, disc, track), file_path.clone()));
+    }
+    // Stable, so untagged files keep their natural name order
+    books.sort_by_key(|(key, _)| *key);
+    for (index, (_, file_path)) in slots.into_iter().zip(books) {
+        files[index] = file_path;
+    }
+}
+
+pub fn is_supported_audio_file(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension))
+}
+
+/// Whether every word of `query` turns up in the track's title, artist or file name,
+/// ignoring case.
+pub fn matches_search(file_path: &Path, info: Option<&TrackInfo>, query: &str) -> bool {
+    let mut haystack = file_path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    for field in info.into_iter().flat_map(|info| [&info.title, &info.artist]).flatten() {
+        haystack.push('\n');
+        haystack.push_str(&field.to_lowercase());
+    }
+    query.to_lowercase().split_whitespace().all(|word| haystack.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_audio_files_in_subfolders() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("album").join("disc 2")).unwrap();
+        for file in ["a.mp3", "notes.txt", "album/b.flac", "album/cover.jpg", "album/disc 2/c.ogg"] {
+            fs::write(dir.path().join(file), b"").unwrap();
+        }
+
+        let mut files = find_audio_files(dir.path(), &ScanSettings::default(), &mut HashSet::new());
+        files.sort();
+        let expected: Vec<PathBuf> = ["a.mp3", "album/b.flac", "album/disc 2/c.ogg"].iter().map(|file| dir.path().join(file)).collect();
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    fn missing_folder_has_no_audio_files() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(find_audio_files(&dir.path().join("nope"), &ScanSettings::default(), &mut HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn lists_tracks_in_natural_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["10 ten.mp3", "2 two.mp3", "1 one.mp3"] {
+            fs::write(dir.path().join(file), b"").unwrap();
+        }
+
+        let files = find_audio_files(dir.path(), &ScanSettings::default(), &mut HashSet::new());
+        let names: Vec<_> = files.iter().map(|file| file.file_name().unwrap().to_string_lossy()).collect();
+        assert_eq!(names, ["1 one.mp3", "2 two.mp3", "10 ten.mp3"]);
+    }
+
+    #[test]
+    fn puts_audiobooks_in_reading_order() {
+        let mut files: Vec<PathBuf> = ["/music/b.mp3", "/books/Dune/Part 2.mp3", "/music/a.mp3", "/books/Dune/Part 1.mp3", "/books/Dune/Epilogue.mp3"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let audiobooks = AudiobookSettings { folders: vec![PathBuf::from("/books")], ..Default::default() };
+        let tagged = |track_number| TrackInfo { track_number: Some(track_number), ..Default::default() };
+        let track_info = HashMap::from([(PathBuf::from("/books/Dune/Part 1.mp3"), tagged(1)), (PathBuf::from("/books/Dune/Part 2.mp3"), tagged(2))]);
+
+        order_audiobooks(&mut files, &audiobooks, &track_info);
+        let expected: Vec<PathBuf> = ["/music/b.mp3", "/books/Dune/Part 1.mp3", "/music/a.mp3", "/books/Dune/Part 2.mp3", "/books/Dune/Epilogue.mp3"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    fn stops_at_the_depth_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a").join("b")).unwrap();
+        for file in ["top.mp3", "a/middle.mp3", "a/b/deep.mp3"] {
+            fs::write(dir.path().join(file), b"").unwrap();
+        }
+
+        let settings = ScanSettings { max_depth: 2, ..Default::default() };
+        let mut files = find_audio_files(dir.path(), &settings, &mut HashSet::new());
+        files.sort();
+        assert_eq!(files, [dir.path().join("a/middle.mp3"), dir.path().join("top.mp3")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lists_each_folder_once_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let album = dir.path().join("album");
+        fs::create_dir_all(&album).unwrap();
+        fs::write(album.join("a.mp3"), b"").unwrap();
+        // A loop back up to the top, and a second way into the album
+        std::os::unix::fs::symlink(dir.path(), album.join("loop")).unwrap();
+        std::os::unix::fs::symlink(&album, dir.path().join("shortcut")).unwrap();
+
+        let folders = [dir.path().to_path_buf(), album.clone()];
+        assert_eq!(scan_folders(&folders, &ScanSettings::default()), [album.join("a.mp3")]);
+        let following = ScanSettings { follow_symlinks: true, ..Default::default() };
+        assert_eq!(scan_folders(&folders, &following).len(), 1);
+    }
+
+    #[test]
+    fn searches_tags_and_file_names() {
+        let info = TrackInfo { title: Some("Dancing Queen".to_string()), artist: Some("ABBA".to_string()), ..Default::default() };
+        let file_path = Path::new("/music/Gold/01 track.mp3");
+
+        assert!(matches_search(file_path, Some(&info), "abba queen"));
+        assert!(matches_search(file_path, Some(&info), "  TRACK "));
+        assert!(matches_search(file_path, None, ""));
+        assert!(!matches_search(file_path, Some(&info), "abba waterloo"));
+        // Folder names don't count, or everything in a big folder would match
+        assert!(!matches_search(file_path, Some(&info), "gold"));
+    }
+
+    #[test]
+    fn recognises_supported_extensions() {
+        for file in ["a.mp3", "a.m4a", "a.flac", "a.wav", "a.ogg"] {
+            assert!(is_supported_audio_file(Path::new(file)), "{}", file);
+        }
+        for file in ["a.txt", "a.jpg", "mp3", "a"] {
+            assert!(!is_supported_audio_file(Path::new(file)), "{}", file);
+        }
+    }
+}
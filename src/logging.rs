@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+use crate::project_dirs;
+
+// One file per day, and a week of them is plenty for a bug report
+const MAX_LOG_FILES: usize = 7;
+const LOG_FILE_PREFIX: &str = "music-jester";
+
+/// Logs to stderr and to a daily log file in the data directory. `RUST_LOG`
+/// overrides the default level. Keep the returned guard alive until exit so
+/// buffered lines get flushed.
+pub fn init() -> Option<WorkerGuard> {
+    let filter = || EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let stderr = fmt::layer().with_writer(std::io::stderr).with_filter(filter());
+
+    let file_appender = log_dir().and_then(|dir| {
+        RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(LOG_FILE_PREFIX)
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(dir)
+            .map_err(|e| eprintln!("Failed to open the log file: {}", e))
+            .ok()
+    });
+    let (file, guard) = match file_appender {
+        Some(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer().with_writer(writer).with_ansi(false).with_filter(filter());
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry().with(stderr).with(file).init();
+    guard
+}
+
+pub fn log_dir() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.data_dir().join("logs"))
+}
+
+/// The log file currently being written to, if there is one.
+pub fn latest_log_file() -> Option<PathBuf> {
+    fs::read_dir(log_dir()?)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(LOG_FILE_PREFIX))
+        .max_by_key(|entry| entry.metadata().and_then(|info| info.modified()).ok())
+        .map(|entry| entry.path())
+}
@@ -0,0 +1,335 @@
+use lofty::{Accessor, AudioFile, ItemKey, TaggedFileExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::cache;
+use crate::project_dirs;
+
+// Bumped whenever TrackInfo gains a field, so older entries get read again
+const METADATA_CACHE_FILE: &str = "metadata-v7.json";
+
+// Album art is shown at 270x270, keep thumbnails at twice that for HiDPI screens
+const THUMBNAIL_SIZE: u32 = 540;
+// Thumbnails past this are dropped, those shown longest ago first
+const THUMBNAILS_LIMIT: u64 = 200 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackInfo {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<Duration>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub compilation: bool, // Flagged as a various artists release
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub composer: Option<String>,
+    pub performer: Option<String>,
+    pub work: Option<String>,     // The piece a classical track is part of...
+    pub movement: Option<String>, // ...and which part of it this is
+    pub bitrate: Option<u32>, // kbps
+    pub replay_gain_db: Option<f32>, // ReplayGain track gain
+}
+
+/// A cache entry is only trusted while the file's size and mtime still match.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedTrackInfo {
+    size: u64,
+    modified: SystemTime,
+    info: TrackInfo,
+}
+
+pub fn extract_album_art(file_path: &PathBuf) -> Option<Vec<u8>> {
+    lofty::read_from_path(file_path).ok()?.primary_tag()?.pictures().first().map(|p| p.data().to_vec())
+}
+
+/// Embedded album art at full size, for viewing and saving.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cover {
+    pub bytes: Vec<u8>, // Exactly as embedded
+    pub width: u32,
+    pub height: u32,
+    pub extension: &'static str, // For saving it, going by what the bytes turn out to be
+}
+
+/// The album art embedded in `file_path`, untouched.
+pub fn load_cover(file_path: &PathBuf) -> Result<Cover, String> {
+    let bytes = extract_album_art(file_path).ok_or_else(|| "This track has no album art".to_string())?;
+    let format = image_rs::guess_format(&bytes).map_err(|e| format!("Can't read the album art: {}", e))?;
+    let (width, height) = image_rs::io::Reader::with_format(std::io::Cursor::new(&bytes), format)
+        .into_dimensions()
+        .map_err(|e| format!("Can't read the album art: {}", e))?;
+    let extension = format.extensions_str().first().copied().unwrap_or("img");
+    Ok(Cover { bytes, width, height, extension })
+}
+
+/// Album art scaled down for display, read from the thumbnail cache when possible.
+#[tracing::instrument(level = "debug", fields(path = %file_path.display()))]
+pub fn album_art_thumbnail(file_path: &PathBuf, use_cache: bool) -> Option<Vec<u8>> {
+    let cache_path = thumbnail_cache_path(file_path).filter(|_| use_cache);
+    if let Some((path, bytes)) = cache_path.as_ref().and_then(|path| Some((path, fs::read(path).ok()?))) {
+        cache::touch(path);
+        return Some(bytes);
+    }
+
+    let album_art = extract_album_art(file_path)?;
+    let Ok(cover) = image_rs::load_from_memory(&album_art) else {
+        // Let the image widget have a go at whatever this is
+        return Some(album_art);
+    };
+    let mut thumbnail = Vec::new();
+    let encoded = cover
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .write_to(&mut std::io::Cursor::new(&mut thumbnail), image_rs::ImageOutputFormat::Png);
+    if encoded.is_err() {
+        return Some(album_art);
+    }
+
+    if let Some(path) = cache_path {
+        let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, &thumbnail));
+        if let Err(e) = written {
+            tracing::warn!("Failed to cache album art thumbnail: {}", e);
+        }
+        if let Some(dir) = path.parent() {
+            cache::prune(dir, THUMBNAILS_LIMIT, &path);
+        }
+    }
+    Some(thumbnail)
+}
+
+/// Thumbnails live in the user's cache directory, keyed by a hash of the file's
+/// path, size and modification time so edited files get a fresh thumbnail.
+fn thumbnail_cache_path(file_path: &Path) -> Option<PathBuf> {
+    let dirs = project_dirs()?;
+    let file_info = fs::metadata(file_path).ok()?;
+    let modified = file_info.modified().ok()?.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let mut key = file_path.as_os_str().as_encoded_bytes().to_vec();
+    key.extend_from_slice(&file_info.len().to_le_bytes());
+    key.extend_from_slice(&modified.as_nanos().to_le_bytes());
+    Some(dirs.cache_dir().join("thumbnails").join(format!("{:016x}.png", cache::stable_hash(&key))))
+}
+
+/// Tags and durations for `files`, only asking lofty about files that changed since
+/// the last time they were cached. Files lofty can't read map to an error message.
+pub fn load_track_info(files: &[PathBuf], use_cache: bool) -> HashMap<PathBuf, Result<TrackInfo, String>> {
+    let cache_path = project_dirs().map(|dirs| dirs.cache_dir().join(METADATA_CACHE_FILE)).filter(|_| use_cache);
+    load_track_info_cached(files, cache_path.as_deref())
+}
+
+#[tracing::instrument(skip_all, fields(files = files.len()))]
+fn load_track_info_cached(files: &[PathBuf], cache_path: Option<&Path>) -> HashMap<PathBuf, Result<TrackInfo, String>> {
+    let mut cache: HashMap<PathBuf, CachedTrackInfo> = cache_path
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+
+    let mut cache_hits = 0;
+    let mut cache_changed = false;
+    let mut track_info = HashMap::with_capacity(files.len());
+    for file_path in files {
+        let Ok(file_info) = fs::metadata(file_path) else { continue };
+        let size = file_info.len();
+        let modified = file_info.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        let info = match cache.get(file_path) {
+            Some(cached) if cached.size == size && cached.modified == modified => {
+                cache_hits += 1;
+                Ok(cached.info.clone())
+            }
+            _ => {
+                // Failures aren't cached, the file may well be fixed by next time
+                let info = extract_track_info(file_path);
+                if let Err(e) = &info {
+                    tracing::warn!(path = %file_path.display(), "{}", e);
+                }
+                if let Ok(info) = &info {
+                    cache.insert(file_path.clone(), CachedTrackInfo { size, modified, info: info.clone() });
+                    cache_changed = true;
+                }
+                info
+            }
+        };
+        track_info.insert(file_path.clone(), info);
+    }
+
+    if cache_changed && let Some(path) = cache_path {
+        let written = serde_json::to_vec(&cache)
+            .map_err(std::io::Error::other)
+            .and_then(|bytes| path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(path, bytes)));
+        if let Err(e) = written {
+            tracing::warn!("Failed to write the metadata cache: {}", e);
+        }
+    }
+    tracing::info!(cache_hits, "Loaded track info");
+    track_info
+}
+
+#[tracing::instrument(level = "debug", fields(path = %file_path.display()))]
+pub fn extract_track_info(file_path: &Path) -> Result<TrackInfo, String> {
+    let file = lofty::read_from_path(file_path).map_err(|e| format!("Failed to read tags: {}", e))?;
+    let tag = file.primary_tag().or_else(|| file.first_tag());
+    Ok(TrackInfo {
+        title: tag.and_then(|tag| tag.title()).map(|s| s.to_string()),
+        artist: tag.and_then(|tag| tag.artist()).map(|s| s.to_string()),
+        duration: Some(file.properties().duration()),
+        genre: tag.and_then(|tag| tag.genre()).map(|s| s.to_string()),
+        year: tag.and_then(|tag| tag.year()),
+        album: tag.and_then(|tag| tag.album()).map(|s| s.to_string()),
+        album_artist: tag.and_then(|tag| tag.get_string(&ItemKey::AlbumArtist)).map(|s| s.to_string()),
+        compilation: tag.and_then(|tag| tag.get_string(&ItemKey::FlagCompilation)).is_some_and(|flag| flag == "1"),
+        track_number: tag.and_then(|tag| tag.track()),
+        disc_number: tag.and_then(|tag| tag.disk()),
+        composer: tag.and_then(|tag| tag.get_string(&ItemKey::Composer)).map(|s| s.to_string()),
+        // ID3v2 has nowhere for performers as such, but the conductor is usually who's meant
+        performer: tag
+            .and_then(|tag| tag.get_string(&ItemKey::Performer).or_else(|| tag.get_string(&ItemKey::Conductor)))
+            .map(|s| s.to_string()),
+        work: tag.and_then(|tag| tag.get_string(&ItemKey::Work)).map(|s| s.to_string()),
+        movement: tag.and_then(|tag| tag.get_string(&ItemKey::Movement)).map(|s| s.to_string()),
+        bitrate: file.properties().audio_bitrate(),
+        replay_gain_db: tag.and_then(|tag| tag.get_string(&ItemKey::ReplayGainTrackGain)).and_then(parse_replay_gain),
+    })
+}
+
+/// Reads a ReplayGain value such as "-6.48 dB".
+fn parse_replay_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value.strip_suffix("dB").or_else(|| value.strip_suffix("db")).unwrap_or(value);
+    number.trim().parse().ok().filter(|gain_db: &f32| gain_db.is_finite())
+}
+
+pub fn extract_metadata(file_path: &PathBuf) -> (Option<String>, Option<String>) {
+    if let Ok(file) = lofty::read_from_path(file_path) && let Some(tag) = file.primary_tag() {
+        let title = tag.title().map(|s| s.to_string());
+        let artist = tag.artist().map(|s| s.to_string());
+        return (title, artist);
+    }
+    (None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_audio::write_wav;
+    use lofty::{MimeType, Picture, PictureType, Tag, TagExt, TagType};
+
+    fn write_tags(path: &Path, title: &str, artist: &str) {
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.set_title(title.to_string());
+        tag.set_artist(artist.to_string());
+        tag.set_genre("J-Pop".to_string());
+        tag.set_year(2019);
+        tag.set_album("THE BOOK".to_string());
+        tag.insert_text(ItemKey::AlbumArtist, "YOASOBI".to_string());
+        tag.set_track(3);
+        tag.insert_text(ItemKey::Composer, "Ayase".to_string());
+        tag.insert_text(ItemKey::Conductor, "Ikura".to_string());
+        tag.insert_text(ItemKey::Movement, "Reprise".to_string());
+        tag.insert_text(ItemKey::ReplayGainTrackGain, "-6.48 dB".to_string());
+        tag.set_disk(2);
+        tag.save_to_path(path).unwrap();
+    }
+
+    #[test]
+    fn reads_tags_and_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.wav");
+        write_wav(&path, 1, 8000, &[0; 16000]);
+        write_tags(&path, "夜に駆ける", "YOASOBI");
+
+        let info = extract_track_info(&path).unwrap();
+        assert_eq!(info.title.as_deref(), Some("夜に駆ける"));
+        assert_eq!(info.artist.as_deref(), Some("YOASOBI"));
+        assert_eq!(info.duration.map(|d| d.as_secs()), Some(2));
+        assert_eq!(info.genre.as_deref(), Some("J-Pop"));
+        assert_eq!(info.year, Some(2019));
+        assert_eq!(info.album.as_deref(), Some("THE BOOK"));
+        assert_eq!(info.album_artist.as_deref(), Some("YOASOBI"));
+        assert!(!info.compilation);
+        assert_eq!((info.disc_number, info.track_number), (Some(2), Some(3)));
+        assert_eq!(info.composer.as_deref(), Some("Ayase"));
+        assert_eq!(info.performer.as_deref(), Some("Ikura"));
+        assert_eq!(info.replay_gain_db, Some(-6.48));
+        assert_eq!(parse_replay_gain("+2.1 db"), Some(2.1));
+        assert_eq!(parse_replay_gain("loud"), None);
+        assert_eq!(info.movement.as_deref(), Some("Reprise"));
+        assert_eq!(info.bitrate, Some(128)); // 8 kHz, 16-bit mono
+        assert_eq!(extract_metadata(&path), (Some("夜に駆ける".to_string()), Some("YOASOBI".to_string())));
+    }
+
+    #[test]
+    fn loads_the_cover_at_full_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.wav");
+        write_wav(&path, 1, 8000, &[0; 8000]);
+        assert!(load_cover(&path).is_err());
+
+        let mut png = Vec::new();
+        image_rs::RgbImage::new(1200, 900).write_to(&mut std::io::Cursor::new(&mut png), image_rs::ImageOutputFormat::Png).unwrap();
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.push_picture(Picture::new_unchecked(PictureType::CoverFront, MimeType::Png, None, png.clone()));
+        tag.save_to_path(&path).unwrap();
+
+        let cover = load_cover(&path).unwrap();
+        assert_eq!((cover.width, cover.height, cover.extension), (1200, 900, "png"));
+        assert_eq!(cover.bytes, png);
+    }
+
+    #[test]
+    fn untagged_file_still_has_a_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("untagged.wav");
+        write_wav(&path, 1, 8000, &[0; 8000]);
+
+        let info = extract_track_info(&path).unwrap();
+        assert_eq!(info.title, None);
+        assert_eq!(info.artist, None);
+        assert_eq!(info.duration.map(|d| d.as_secs()), Some(1));
+    }
+
+    #[test]
+    fn unreadable_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.mp3");
+        fs::write(&path, b"not really an mp3").unwrap();
+
+        assert!(extract_track_info(&path).is_err());
+        assert_eq!(extract_album_art(&path), None);
+
+        let cache_path = dir.path().join("metadata.json");
+        let info = load_track_info_cached(std::slice::from_ref(&path), Some(&cache_path));
+        assert!(info[&path].is_err());
+        assert!(!cache_path.exists());
+    }
+
+    #[test]
+    fn cache_is_reused_until_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("cache").join("metadata.json");
+        let path = dir.path().join("song.wav");
+        write_wav(&path, 1, 8000, &[0; 8000]);
+        write_tags(&path, "First", "Artist");
+        let files = vec![path.clone()];
+
+        let info = load_track_info_cached(&files, Some(&cache_path));
+        assert_eq!(info[&path].as_ref().unwrap().title.as_deref(), Some("First"));
+        assert!(cache_path.exists());
+
+        // Doctor the cache so we can tell whether lofty was asked again
+        let mut cache: HashMap<PathBuf, CachedTrackInfo> = serde_json::from_slice(&fs::read(&cache_path).unwrap()).unwrap();
+        cache.get_mut(&path).unwrap().info.title = Some("From cache".to_string());
+        fs::write(&cache_path, serde_json::to_vec(&cache).unwrap()).unwrap();
+        let info = load_track_info_cached(&files, Some(&cache_path));
+        assert_eq!(info[&path].as_ref().unwrap().title.as_deref(), Some("From cache"));
+
+        // A different size invalidates the entry
+        write_wav(&path, 1, 8000, &[0; 16000]);
+        write_tags(&path, "Second", "Artist");
+        let info = load_track_info_cached(&files, Some(&cache_path));
+        assert_eq!(info[&path].as_ref().unwrap().title.as_deref(), Some("Second"));
+    }
+}
@@ -0,0 +1,132 @@
+use iced::widget::canvas::{self, Cursor, Frame, Geometry};
+use iced::{Color, Point, Rectangle, Size, Theme};
+
+// The meter covers this much range, quieter than this reads as silence
+const FLOOR_DB: f32 = -48.0;
+// How far the bar falls per frame
+const FALL: f32 = 0.03;
+// About a second and a half at the UI frame rate
+const HOLD_FRAMES: u32 = 45;
+// Decoded samples at or beyond full scale mean the file itself clips
+const CLIP_LEVEL: f32 = 0.999;
+const CLIP_COLOR: Color = Color::from_rgb(0.85, 0.2, 0.2);
+
+/// Level meters with peak hold, one per channel.
+#[derive(Debug, Default)]
+pub struct Meters {
+    channels: Vec<Channel>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Channel {
+    level: f32, // 0 to 1 on the meter's dB scale
+    peak: f32,
+    hold: u32, // Frames left before the peak marker starts to fall
+    clipped: bool,
+}
+
+impl Meters {
+    /// Feeds in the per-channel peaks played since the last frame.
+    pub fn update(&mut self, peaks: &[f32]) {
+        if self.channels.len() != peaks.len() {
+            self.channels = vec![Channel::default(); peaks.len()];
+        }
+        for (channel, &peak) in self.channels.iter_mut().zip(peaks) {
+            let level = meter_level(peak);
+            channel.level = level.max(channel.level - FALL);
+            if level >= channel.peak {
+                channel.peak = level;
+                channel.hold = HOLD_FRAMES;
+            } else if channel.hold > 0 {
+                channel.hold -= 1;
+            } else {
+                channel.peak = (channel.peak - FALL).max(channel.level);
+            }
+            channel.clipped |= peak >= CLIP_LEVEL;
+        }
+    }
+
+    /// Drops the levels and clip indicators, e.g. when the track changes.
+    pub fn reset(&mut self) {
+        self.channels.clear();
+    }
+
+    pub fn clipped(&self) -> bool {
+        self.channels.iter().any(|channel| channel.clipped)
+    }
+}
+
+fn meter_level(peak: f32) -> f32 {
+    let db = 20.0 * peak.max(f32::EPSILON).log10();
+    ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+}
+
+impl<Message> canvas::Program<Message> for Meters {
+    type State = ();
+
+    fn draw(&self, _state: &(), theme: &Theme, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let palette = theme.extended_palette();
+        // Stereo when nothing is playing, so the meters don't jump around
+        let count = self.channels.len().max(2);
+        let lane = bounds.height / count as f32;
+        for i in 0..count {
+            let channel = self.channels.get(i).copied().unwrap_or_default();
+            let top = i as f32 * lane + 1.0;
+            let height = (lane - 2.0).max(1.0);
+            frame.fill_rectangle(Point::new(0.0, top), Size::new(bounds.width, height), palette.background.weak.color);
+            frame.fill_rectangle(Point::new(0.0, top), Size::new(channel.level * bounds.width, height), palette.primary.base.color);
+
+            let marker = if channel.clipped { CLIP_COLOR } else { palette.primary.strong.color };
+            let x = (channel.peak * bounds.width - 2.0).max(0.0);
+            frame.fill_rectangle(Point::new(x, top), Size::new(2.0, height), marker);
+        }
+        vec![frame.into_geometry()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peak_holds_then_falls() {
+        let mut meters = Meters::default();
+        meters.update(&[0.5, 0.25]);
+        let peak = meters.channels[0].peak;
+        assert_eq!(meters.channels[0].level, peak);
+        assert!(meters.channels[1].peak < peak);
+
+        for _ in 0..HOLD_FRAMES {
+            meters.update(&[0.0, 0.0]);
+        }
+        assert_eq!(meters.channels[0].peak, peak);
+        assert!(meters.channels[0].level < peak);
+
+        meters.update(&[0.0, 0.0]);
+        assert!(meters.channels[0].peak < peak);
+        assert!(meters.channels[0].peak >= meters.channels[0].level);
+    }
+
+    #[test]
+    fn clipping_latches_until_reset() {
+        let mut meters = Meters::default();
+        meters.update(&[0.9, 0.9]);
+        assert!(!meters.clipped());
+
+        meters.update(&[1.0, 0.5]);
+        meters.update(&[0.0, 0.0]);
+        assert!(meters.clipped());
+        assert!(meters.channels[0].clipped && !meters.channels[1].clipped);
+
+        meters.reset();
+        assert!(!meters.clipped());
+    }
+
+    #[test]
+    fn silence_is_an_empty_meter() {
+        assert_eq!(meter_level(0.0), 0.0);
+        assert_eq!(meter_level(1.0), 1.0);
+        assert!((meter_level(0.5) - (1.0 - 6.0206 / 48.0)).abs() < 0.001);
+    }
+}
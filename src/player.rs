@@ -0,0 +1,936 @@
+use iced::futures::channel::mpsc as async_mpsc;
+use iced::futures::SinkExt;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::{cpal, OutputStream, Sample, Sink, Source};
+use std::collections::VecDeque;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::effects::{apply_chain, build_chain, AudioEffect, EffectSlot, Leveler, Leveling, Limiter};
+use crate::plugins::OutputSink;
+use crate::preload::Primed;
+use crate::readahead::{location_unreachable, ReadAhead, SourceHealth, RETRY_INTERVAL};
+use crate::resample::{resample, ResampleQuality};
+use crate::settings::SilenceSettings;
+use crate::silence::find_silences;
+
+// How often the audio thread reports the playback position
+const POSITION_INTERVAL: Duration = Duration::from_millis(250);
+// Enough recent audio for a 4096 point FFT
+const TAP_FRAMES: usize = 4096;
+// Samples are handed to the tap in batches to keep locking off the hot path
+const TAP_BATCH: usize = 512;
+// How much of the next track to decode before it's played, enough to cover
+// decoding the rest getting going
+const PRELOAD_LENGTH: Duration = Duration::from_secs(5);
+// Sample formats to open the device in, best first. The decoder only produces
+// 16-bit samples, so bit-perfect output is 16-bit too, and higher bit depths are
+// cut down to it; anything resampled or run through effects is floating point
+// by then.
+const NATIVE_FORMATS: [cpal::SampleFormat; 3] = [cpal::SampleFormat::I16, cpal::SampleFormat::I32, cpal::SampleFormat::F32];
+const PROCESSED_FORMATS: [cpal::SampleFormat; 3] = [cpal::SampleFormat::F32, cpal::SampleFormat::I32, cpal::SampleFormat::I16];
+
+#[derive(Debug, Clone)]
+pub enum AudioCommand {
+    Play(PathBuf),
+    PlayFrom(PathBuf, Duration),
+    Pause,
+    Resume,
+    Stop,
+    Seek(Duration),
+    SetVolume(f32),
+    /// Offset in dB for the current track and any that follow, until it's set again.
+    SetTrackGain(f32),
+    /// How to level the current track and any that follow, until it's set again.
+    SetLeveling(Leveling),
+    /// Playback speed for the current track and any that follow, 1 being normal.
+    /// Pitch goes up and down with it.
+    SetSpeed(f32),
+    SetOutputDevice(Option<String>),
+    /// Runs the device at this rate instead of its default.
+    SetSampleRate(Option<u32>),
+    SetResampleQuality(ResampleQuality),
+    /// Replaces the effect chain. The playing track restarts where it was to pick it up.
+    SetEffects(Vec<EffectSlot>),
+    SetSilenceTrimming(SilenceSettings),
+    /// Opens the device at each track's own sample rate and channel count and
+    /// leaves the samples alone: no effects, gain or volume.
+    SetBitPerfect(bool),
+    /// Tries the file that couldn't be reached again straight away.
+    Retry,
+    /// Gets the start of the track likely to play next decoded, so it starts
+    /// without a pause when it does. Replaces whatever was preloaded before.
+    Preload(Option<PathBuf>),
+    /// Sent by the audio thread to itself once the silences in a track have been found.
+    SkipSilences(PathBuf, Vec<Range<Duration>>),
+}
+
+#[derive(Debug, Clone)]
+pub enum AudioEvent {
+    Started { file_path: PathBuf, duration: Option<Duration> },
+    /// The playing track was started again where it was, e.g. for new effects or
+    /// another output device. It's the same play carrying on, not a new one.
+    Restarted { position: Duration, paused: bool },
+    PositionChanged(Duration),
+    TrackEnded,
+    /// The limiter started or stopped turning peaks down to keep them from clipping.
+    Limiting(bool),
+    /// `file_path` can't be reached, e.g. because the network share it's on has
+    /// dropped. The audio thread keeps trying and carries on from where it
+    /// stopped, with `Started`, once it's back.
+    SourceUnavailable(PathBuf),
+    /// `file_path` is set when the problem is with that file rather than the device.
+    Error { file_path: Option<PathBuf>, message: String },
+}
+
+/// Handle to the audio thread. The thread owns the output device for the whole
+/// session and does all decoding, so a slow file never holds up the UI; tracks
+/// are swapped in and out of one long-lived sink rather than reopening the
+/// device for every song, which clicks and can fail on Windows.
+#[derive(Debug, Clone)]
+pub struct AudioEngine {
+    commands: mpsc::Sender<AudioCommand>,
+    pub tap: SampleTap,
+}
+
+impl AudioEngine {
+    pub fn spawn(events: async_mpsc::Sender<AudioEvent>) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let tap = SampleTap::default();
+        let thread_tap = tap.clone();
+        std::thread::Builder::new()
+            .name("audio".to_string())
+            .spawn({
+                let commands = commands.clone();
+                move || run_audio_thread(commands, receiver, events, thread_tap)
+            })
+            .expect("failed to spawn the audio thread");
+        Self { commands, tap }
+    }
+
+    pub fn send(&self, command: AudioCommand) {
+        // Only fails if the audio thread has died, and then there's nobody to tell
+        let _ = self.commands.send(command);
+    }
+}
+
+fn run_audio_thread(
+    sender: mpsc::Sender<AudioCommand>,
+    commands: mpsc::Receiver<AudioCommand>,
+    events: async_mpsc::Sender<AudioEvent>,
+    tap: SampleTap,
+) {
+    let mut audio = AudioThread {
+        commands: sender,
+        events,
+        tap,
+        output: None,
+        output_format: None,
+        output_rate: None,
+        bit_perfect: false,
+        native: false,
+        device: None,
+        sample_rate: None,
+        resample_quality: ResampleQuality::default(),
+        volume: 1.0,
+        track_gain_db: 0.0,
+        leveling: Leveling::Off,
+        leveler: Leveler::default(),
+        limiter: Limiter::default(),
+        limiting: false,
+        speed: 1.0,
+        effects: Vec::new(),
+        silence: SilenceSettings::default(),
+        skips: Vec::new(),
+        health: SourceHealth::default(),
+        waiting: None,
+        retry_at: Instant::now(),
+        current: None,
+        preloaded: None,
+        playing: false,
+        last_position: Duration::ZERO,
+    };
+
+    loop {
+        match commands.recv_timeout(POSITION_INTERVAL) {
+            Ok(command) => audio.handle(command),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+        audio.report_progress();
+    }
+}
+
+/// State owned by the audio thread.
+struct AudioThread {
+    commands: mpsc::Sender<AudioCommand>, // For finding silences in the background
+    events: async_mpsc::Sender<AudioEvent>,
+    tap: SampleTap,
+    // The device is opened on first play, and again later if that failed
+    output: Option<(OutputStream, Sink)>,
+    output_format: Option<(u16, u32)>, // Channels and sample rate asked for when bit-perfect
+    output_rate: Option<u32>,          // What the open device runs at, if known
+    bit_perfect: bool,
+    native: bool, // The device took the track's own format, so its samples go straight to it
+    device: Option<String>,
+    sample_rate: Option<u32>, // None for the device's default
+    resample_quality: ResampleQuality,
+    volume: f32,
+    track_gain_db: f32,
+    leveling: Leveling,
+    leveler: Leveler, // Measures tracks with no ReplayGain tags
+    limiter: Limiter, // Also applies the track and ReplayGain gains, so boosts can't clip
+    limiting: bool,   // As last reported
+    speed: f32,
+    effects: Vec<Box<dyn AudioEffect>>,
+    silence: SilenceSettings,
+    skips: Vec<Range<Duration>>, // Silences in the current track to jump over
+    health: SourceHealth,        // Of the file playing
+    waiting: Option<(PathBuf, Duration)>, // A file that couldn't be reached, and where to pick it up
+    retry_at: Instant,
+    current: Option<PathBuf>,
+    preloaded: Option<Preload>, // The next track, being or already decoded
+    playing: bool,
+    last_position: Duration,
+}
+
+/// Whether `play` is starting a track or picking up the playing one again.
+#[derive(Debug, Clone, Copy)]
+enum Start {
+    Track,
+    Restart { paused: bool },
+}
+
+/// A track being opened and primed on a thread of its own, ahead of being played.
+struct Preload {
+    file_path: PathBuf,
+    ready: std::thread::JoinHandle<Result<Decoded, PlaybackError>>,
+}
+
+impl AudioThread {
+    fn emit(&mut self, event: AudioEvent) {
+        let _ = iced::futures::executor::block_on(self.events.send(event));
+    }
+
+    fn emit_error(&mut self, file_path: &Path, error: PlaybackError) {
+        tracing::error!(path = %file_path.display(), "{}", error);
+        let file_path = error.is_about_the_file().then(|| file_path.to_path_buf());
+        self.emit(AudioEvent::Error { file_path, message: error.to_string() });
+    }
+
+    fn sink(&self) -> Option<&Sink> {
+        self.output.as_ref().map(|(_, sink)| sink)
+    }
+
+    fn handle(&mut self, command: AudioCommand) {
+        match command {
+            AudioCommand::Play(file_path) => {
+                self.waiting = None;
+                if let Err(e) = self.play(&file_path, Duration::ZERO, Start::Track) {
+                    self.emit_error(&file_path, e);
+                }
+            }
+            AudioCommand::PlayFrom(file_path, position) => {
+                self.waiting = None;
+                if let Err(e) = self.play(&file_path, position, Start::Track) {
+                    self.emit_error(&file_path, e);
+                }
+            }
+            AudioCommand::Pause => {
+                if let Some(sink) = self.sink() {
+                    sink.pause();
+                }
+            }
+            AudioCommand::Resume => {
+                if let Some(sink) = self.sink() {
+                    sink.play();
+                }
+            }
+            AudioCommand::Stop => {
+                if let Some(sink) = self.sink() {
+                    sink.clear();
+                }
+                self.playing = false;
+                self.current = None;
+                self.waiting = None;
+                self.preloaded = None;
+            }
+            AudioCommand::Retry => self.retry_at = Instant::now(),
+            AudioCommand::Preload(file_path) => self.preload(file_path),
+            AudioCommand::Seek(position) => {
+                if let Some(Err(e)) = self.sink().map(|sink| sink.try_seek(position)) {
+                    tracing::warn!(?position, "Failed to seek: {}", e);
+                    self.emit(AudioEvent::Error { file_path: None, message: format!("Failed to seek: {}", e) });
+                }
+            }
+            AudioCommand::SetVolume(volume) => {
+                self.volume = volume;
+                self.apply_volume();
+            }
+            AudioCommand::SetTrackGain(gain_db) => {
+                self.track_gain_db = gain_db;
+                self.apply_gain();
+            }
+            AudioCommand::SetLeveling(leveling) => {
+                self.leveling = leveling;
+                self.apply_gain();
+            }
+            AudioCommand::SetSpeed(speed) => {
+                self.speed = speed;
+                self.apply_volume();
+            }
+            AudioCommand::SetOutputDevice(device) => {
+                if device == self.device {
+                    return;
+                }
+                self.device = device;
+                // Close the old device before opening the new one
+                self.restart(|audio| audio.output = None);
+            }
+            AudioCommand::SetSampleRate(sample_rate) => {
+                if sample_rate == self.sample_rate {
+                    return;
+                }
+                self.sample_rate = sample_rate;
+                self.restart(|audio| audio.output = None);
+            }
+            AudioCommand::SetResampleQuality(quality) => {
+                if quality != self.resample_quality {
+                    self.restart(|audio| audio.resample_quality = quality);
+                }
+            }
+            AudioCommand::SetEffects(slots) => {
+                let effects = build_chain(&slots);
+                self.restart(|audio| audio.effects = effects);
+            }
+            AudioCommand::SetSilenceTrimming(silence) => {
+                self.silence = silence;
+                self.skips.clear();
+                if let Some(file_path) = self.current.clone() {
+                    self.look_for_silences(&file_path);
+                }
+            }
+            AudioCommand::SetBitPerfect(bit_perfect) => {
+                if bit_perfect != self.bit_perfect {
+                    self.restart(|audio| audio.bit_perfect = bit_perfect);
+                    self.apply_volume();
+                }
+            }
+            AudioCommand::SkipSilences(file_path, skips) => {
+                if self.current.as_ref() == Some(&file_path) && self.silence.enabled {
+                    tracing::debug!(path = %file_path.display(), ?skips, "Found silences");
+                    self.skips = skips;
+                }
+            }
+        }
+    }
+
+    /// Makes `change`, then starts the playing track again where it was, paused if
+    /// it was paused.
+    fn restart(&mut self, change: impl FnOnce(&mut Self)) {
+        let resume = self.sink().filter(|_| self.playing).map(|sink| (sink.get_pos(), sink.is_paused()));
+        change(self);
+        if let (Some((position, paused)), Some(file_path)) = (resume, self.current.clone())
+            && let Err(e) = self.play(&file_path, position, Start::Restart { paused })
+        {
+            self.emit_error(&file_path, e);
+        }
+    }
+
+    /// Starts `file_path` from `position`, opening the output device if needed.
+    #[tracing::instrument(skip(self), fields(path = %file_path.display()))]
+    fn play(&mut self, file_path: &Path, position: Duration, start: Start) -> Result<(), PlaybackError> {
+        let decoded = match self.take_preloaded(file_path) {
+            Some(decoded) => Ok(decoded),
+            None => decode(file_path, Duration::ZERO),
+        };
+        let (decoder, health) = match decoded {
+            Err(PlaybackError::Open(e)) if location_unreachable(file_path) => {
+                tracing::warn!("Can't reach the file: {}", e);
+                self.wait_for(file_path, position);
+                return Ok(());
+            }
+            result => result?,
+        };
+        let format = self.bit_perfect.then(|| (decoder.channels(), decoder.sample_rate()));
+        if self.output.is_some() && self.output_format != format {
+            // Close the old stream before opening one in the new format
+            self.output = None;
+        }
+        if self.output.is_none() {
+            self.output = Some(self.open(format)?);
+            self.output_format = format;
+            self.apply_volume();
+        }
+        // Restarting the same track, e.g. for new effects, keeps what's known about it
+        if self.current.as_deref() != Some(file_path) {
+            self.skips.clear();
+            self.look_for_silences(file_path);
+        }
+        let duration = decoder.total_duration();
+        tracing::info!(?duration, sample_rate = decoder.sample_rate(), channels = decoder.channels(), "Decoded");
+        // The UI forgets the indicator when a track starts, so start over here too
+        self.limiter.take_engaged();
+        self.limiting = false;
+        let Some(sink) = self.sink() else { return Ok(()) };
+        sink.clear();
+        self.tap.clear();
+        if self.native {
+            sink.append(Tapped::new(decoder, self.tap.clone()));
+        } else {
+            let source = apply_chain(&self.effects, Box::new(decoder.convert_samples()));
+            let source = resample(source, self.output_rate, self.resample_quality);
+            let source = self.limiter.apply(self.leveler.apply(source));
+            sink.append(Tapped::new(source, self.tap.clone()));
+        }
+        if !position.is_zero() {
+            let _ = sink.try_seek(position);
+        }
+        match start {
+            Start::Restart { paused: true } => sink.pause(),
+            _ => sink.play(),
+        }
+        self.health = health;
+        self.playing = true;
+        self.current = Some(file_path.to_path_buf());
+        self.last_position = position;
+        self.emit(match start {
+            Start::Track => AudioEvent::Started { file_path: file_path.to_path_buf(), duration },
+            Start::Restart { paused } => AudioEvent::Restarted { position, paused },
+        });
+        Ok(())
+    }
+
+    /// Starts opening and decoding the beginning of `file_path` in the background,
+    /// unless it already has been.
+    fn preload(&mut self, file_path: Option<PathBuf>) {
+        if self.preloaded.as_ref().map(|preload| &preload.file_path) == file_path.as_ref() {
+            return;
+        }
+        // Anything preloaded before is dropped once its thread's done
+        self.preloaded = file_path.and_then(|file_path| {
+            let path = file_path.clone();
+            let spawned = std::thread::Builder::new().name("preload".to_string()).spawn(move || decode(&path, PRELOAD_LENGTH));
+            match spawned {
+                Ok(ready) => Some(Preload { file_path, ready }),
+                Err(e) => {
+                    tracing::warn!("Failed to start preloading: {}", e);
+                    None
+                }
+            }
+        });
+    }
+
+    /// What was preloaded for `file_path`, if it's the track that was preloaded
+    /// and that went well, waiting for it to be ready if need be. Anything
+    /// preloaded for another track is kept for when that one's played.
+    fn take_preloaded(&mut self, file_path: &Path) -> Option<Decoded> {
+        match self.preloaded.take() {
+            Some(preload) if preload.file_path == file_path => match preload.ready.join() {
+                Ok(Ok(decoded)) => {
+                    tracing::debug!(primed = ?decoded.0.primed(), "Using the preloaded track");
+                    Some(decoded)
+                }
+                // Opened again the usual way, to deal with whatever went wrong there
+                Ok(Err(_)) | Err(_) => None,
+            },
+            other => {
+                self.preloaded = other;
+                None
+            }
+        }
+    }
+
+    /// Opens the output device, in `format` if that's given and the device takes
+    /// it, otherwise at the chosen sample rate. Any trouble is reported, then the
+    /// default device or format is used, and tracks are played converted.
+    fn open(&mut self, format: Option<(u16, u32)>) -> Result<(OutputStream, Sink), PlaybackError> {
+        self.native = false;
+        let exact = match (format, self.sample_rate) {
+            (Some((channels, sample_rate)), _) => Some((Some(channels), sample_rate, &NATIVE_FORMATS)),
+            (None, Some(sample_rate)) => Some((None, sample_rate, &PROCESSED_FORMATS)),
+            (None, None) => None,
+        };
+        if let Some((channels, sample_rate, formats)) = exact {
+            match open_output_as(self.device.as_deref(), channels, sample_rate, formats) {
+                Ok(output) => {
+                    self.output_rate = Some(sample_rate);
+                    self.native = format.is_some();
+                    return Ok(output);
+                }
+                Err(e) => {
+                    tracing::warn!("{}", e);
+                    self.emit(AudioEvent::Error { file_path: None, message: e.to_string() });
+                }
+            }
+        }
+        let (stream, sink, sample_rate) = match open_output(self.device.as_deref()) {
+            Err(e @ PlaybackError::DeviceNotFound(_)) => {
+                tracing::warn!("{}", e);
+                self.emit(AudioEvent::Error { file_path: None, message: e.to_string() });
+                open_output(None)?
+            }
+            result => result?,
+        };
+        self.output_rate = sample_rate;
+        Ok((stream, sink))
+    }
+
+    /// Works out where `file_path` is silent in the background; the answer comes
+    /// back as `SkipSilences`.
+    fn look_for_silences(&self, file_path: &Path) {
+        if !self.silence.enabled {
+            return;
+        }
+        let (commands, file_path, settings) = (self.commands.clone(), file_path.to_path_buf(), self.silence.clone());
+        let spawned = std::thread::Builder::new().name("silence".to_string()).spawn(move || {
+            match find_silences(&file_path, &settings) {
+                Ok(skips) => {
+                    let _ = commands.send(AudioCommand::SkipSilences(file_path, skips));
+                }
+                Err(e) => tracing::warn!(path = %file_path.display(), "Failed to look for silence: {}", e),
+            }
+        });
+        if let Err(e) = spawned {
+            tracing::warn!("Failed to start looking for silence: {}", e);
+        }
+    }
+
+    /// Hands the track gain, plus the ReplayGain gain if there is one, to the
+    /// limiter, and sets the leveler measuring if there isn't.
+    fn apply_gain(&self) {
+        let replay_gain_db = match self.leveling {
+            Leveling::Tagged(gain_db) => gain_db,
+            Leveling::Off | Leveling::Measure => 0.0,
+        };
+        self.limiter.set_gain(db_to_gain(self.track_gain_db + replay_gain_db));
+        self.leveler.set_measuring(self.leveling == Leveling::Measure);
+    }
+
+    /// Sets the sink's volume and speed, both left alone when playing bit-perfect.
+    fn apply_volume(&self) {
+        if let Some(sink) = self.sink() {
+            sink.set_volume(if self.native { 1.0 } else { self.volume });
+            sink.set_speed(if self.native { 1.0 } else { self.speed });
+        }
+    }
+
+    /// Holds on to `file_path` to try again every so often, as it can't be reached.
+    fn wait_for(&mut self, file_path: &Path, position: Duration) {
+        self.playing = false;
+        self.current = None;
+        self.waiting = Some((file_path.to_path_buf(), position));
+        self.retry_at = Instant::now() + RETRY_INTERVAL;
+        self.emit(AudioEvent::SourceUnavailable(file_path.to_path_buf()));
+    }
+
+    fn report_progress(&mut self) {
+        if let Some((file_path, position)) = self.waiting.clone()
+            && Instant::now() >= self.retry_at
+        {
+            if fs::File::open(&file_path).is_ok() {
+                tracing::info!(path = %file_path.display(), ?position, "Back again, resuming");
+                self.waiting = None;
+                if let Err(e) = self.play(&file_path, position, Start::Track) {
+                    self.emit_error(&file_path, e);
+                }
+            } else {
+                self.retry_at = Instant::now() + RETRY_INTERVAL;
+            }
+        }
+        if !self.playing {
+            return;
+        }
+        let Some(sink) = self.sink() else { return };
+        let position = sink.get_pos();
+        if let Some(skip) = self.skips.iter().find(|skip| skip.contains(&position)) {
+            if skip.end == Duration::MAX {
+                // Nothing but silence from here on, so the track is as good as over
+                sink.clear();
+            } else if let Err(e) = sink.try_seek(skip.end) {
+                tracing::warn!(?skip, "Failed to skip silence: {}", e);
+                self.skips.clear();
+            }
+        }
+        let limiting = self.limiter.take_engaged();
+        if limiting != self.limiting {
+            self.limiting = limiting;
+            self.emit(AudioEvent::Limiting(limiting));
+        }
+        let Some(sink) = self.sink() else { return };
+        if sink.empty() && self.health.gave_out() {
+            // The buffer ran dry with the file out of reach, rather than the track ending
+            if let Some(file_path) = self.current.clone() {
+                self.wait_for(&file_path, self.last_position);
+            }
+        } else if sink.empty() {
+            self.playing = false;
+            self.current = None;
+            self.emit(AudioEvent::TrackEnded);
+        } else if sink.get_pos() != self.last_position {
+            self.last_position = sink.get_pos();
+            self.emit(AudioEvent::PositionChanged(self.last_position));
+        }
+    }
+}
+
+/// Converts a level change in decibels to the factor samples are multiplied by.
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// The most recent samples handed to the output device, for visualisations,
+/// and a copy of them for plugin output sinks. Nothing is copied while it's
+/// disabled and there are no sinks.
+#[derive(Debug, Clone, Default)]
+pub struct SampleTap {
+    enabled: Arc<AtomicBool>,
+    buffer: Arc<Mutex<TapBuffer>>,
+    sinks: Arc<Mutex<Sinks>>,
+    has_sinks: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct Sinks(Vec<Arc<dyn OutputSink>>);
+
+impl std::fmt::Debug for Sinks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.0.iter().map(|sink| sink.name())).finish()
+    }
+}
+
+/// Interleaved samples, oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TapBuffer {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: VecDeque<f32>,
+    pub peaks: Vec<f32>, // Loudest sample per channel since the last `take_peaks`
+}
+
+impl SampleTap {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Plugin outputs that get everything played from now on.
+    pub fn set_sinks(&self, sinks: Vec<Arc<dyn OutputSink>>) {
+        self.has_sinks.store(!sinks.is_empty(), Ordering::Relaxed);
+        self.sinks.lock().unwrap().0 = sinks;
+    }
+
+    fn is_wanted(&self) -> bool {
+        self.is_enabled() || self.has_sinks.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> TapBuffer {
+        self.buffer.lock().unwrap().clone()
+    }
+
+    /// Per-channel peak levels since the last call, for level meters.
+    pub fn take_peaks(&self) -> Vec<f32> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let silence = vec![0.0; buffer.peaks.len()];
+        std::mem::replace(&mut buffer.peaks, silence)
+    }
+
+    fn clear(&self) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.samples.clear();
+        buffer.peaks.iter_mut().for_each(|peak| *peak = 0.0);
+    }
+
+    fn push(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        if self.has_sinks.load(Ordering::Relaxed) {
+            for sink in &self.sinks.lock().unwrap().0 {
+                sink.write(samples, channels, sample_rate);
+            }
+        }
+        if !self.is_enabled() {
+            return;
+        }
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.channels != channels || buffer.sample_rate != sample_rate {
+            buffer.samples.clear();
+            buffer.peaks = vec![0.0; channels as usize];
+            buffer.channels = channels;
+            buffer.sample_rate = sample_rate;
+        }
+        for (sample, channel) in samples.iter().zip((0..channels as usize).cycle()) {
+            buffer.peaks[channel] = buffer.peaks[channel].max(sample.abs());
+        }
+        buffer.samples.extend(samples);
+        let capacity = TAP_FRAMES * channels as usize;
+        if buffer.samples.len() > capacity {
+            let excess = buffer.samples.len() - capacity;
+            buffer.samples.drain(..excess);
+        }
+    }
+}
+
+/// Passes a source through unchanged while copying its samples into a [`SampleTap`].
+struct Tapped<S> {
+    inner: S,
+    tap: SampleTap,
+    pending: Vec<f32>,
+}
+
+impl<S> Tapped<S> {
+    fn new(inner: S, tap: SampleTap) -> Self {
+        Self { inner, tap, pending: Vec::with_capacity(TAP_BATCH) }
+    }
+}
+
+impl<S> Iterator for Tapped<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let sample = self.inner.next()?;
+        if self.tap.is_wanted() {
+            self.pending.push(sample.to_f32());
+            // Only whole frames, so every batch starts on the first channel
+            let channels = self.inner.channels() as usize;
+            if self.pending.len() >= TAP_BATCH && self.pending.len().is_multiple_of(channels) {
+                self.tap.push(&self.pending, self.inner.channels(), self.inner.sample_rate());
+                self.pending.clear();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for Tapped<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.pending.clear();
+        self.tap.clear();
+        self.inner.try_seek(pos)
+    }
+}
+
+/// Names of the output devices on the default host.
+pub fn output_devices() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            tracing::warn!("Failed to list output devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn find_device(name: &str) -> Result<cpal::Device, PlaybackError> {
+    cpal::default_host()
+        .output_devices()
+        .ok()
+        .and_then(|mut devices| devices.find(|device| device.name().ok().as_deref() == Some(name)))
+        .ok_or_else(|| PlaybackError::DeviceNotFound(name.to_string()))
+}
+
+fn output_device(device_name: Option<&str>) -> Result<cpal::Device, PlaybackError> {
+    match device_name {
+        Some(name) => find_device(name),
+        None => cpal::default_host().default_output_device().ok_or(PlaybackError::NoDevice),
+    }
+}
+
+/// Opens the device in its default format, returning the sample rate that is.
+fn open_output(device_name: Option<&str>) -> Result<(OutputStream, Sink, Option<u32>), PlaybackError> {
+    let device = output_device(device_name)?;
+    let sample_rate = device.default_output_config().ok().map(|config| config.sample_rate().0);
+    tracing::info!(device = device_name.unwrap_or("default"), ?sample_rate, "Opening output device");
+    let (stream, stream_handle) = OutputStream::try_from_device(&device).map_err(PlaybackError::Device)?;
+    let sink = Sink::try_new(&stream_handle).map_err(PlaybackError::Sink)?;
+    Ok((stream, sink, sample_rate))
+}
+
+/// Opens the device at exactly `sample_rate` and `channels`, or its usual
+/// channel count if that's not given, in the first of `formats` it supports.
+fn open_output_as(
+    device_name: Option<&str>,
+    channels: Option<u16>,
+    sample_rate: u32,
+    formats: &[cpal::SampleFormat],
+) -> Result<(OutputStream, Sink), PlaybackError> {
+    let device = output_device(device_name)?;
+    let channels = channels.unwrap_or_else(|| device.default_output_config().map(|config| config.channels()).unwrap_or(2));
+    let ranges = device.supported_output_configs().map(|ranges| ranges.collect()).unwrap_or_default();
+    let config = matching_config(ranges, channels, sample_rate, formats)
+        .ok_or(PlaybackError::UnsupportedFormat { channels, sample_rate })?;
+    tracing::info!(device = device_name.unwrap_or("default"), ?config, "Opening output device");
+    let (stream, stream_handle) = OutputStream::try_from_device_config(&device, config).map_err(PlaybackError::Device)?;
+    let sink = Sink::try_new(&stream_handle).map_err(PlaybackError::Sink)?;
+    Ok((stream, sink))
+}
+
+/// A config with exactly `channels` and `sample_rate`, in whichever of `formats`
+/// comes first.
+fn matching_config(
+    ranges: Vec<cpal::SupportedStreamConfigRange>,
+    channels: u16,
+    sample_rate: u32,
+    formats: &[cpal::SampleFormat],
+) -> Option<cpal::SupportedStreamConfig> {
+    ranges
+        .into_iter()
+        .filter(|range| range.channels() == channels && (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&sample_rate))
+        .filter_map(|range| Some((formats.iter().position(|&format| format == range.sample_format())?, range)))
+        .min_by_key(|(preference, _)| *preference)
+        .map(|(_, range)| range.with_sample_rate(cpal::SampleRate(sample_rate)))
+}
+
+/// A decoder for a file read ahead of playback, and a way of telling whether
+/// the file went away.
+type Decoded = (Primed<rodio::Decoder<ReadAhead>>, SourceHealth);
+
+/// Opens `file_path` for playing, decoding `prime` of it straight away.
+fn decode(file_path: &Path, prime: Duration) -> Result<Decoded, PlaybackError> {
+    let reader = ReadAhead::open(file_path).map_err(PlaybackError::Open)?;
+    let health = reader.health();
+    let decoder = rodio::Decoder::new(reader).map_err(PlaybackError::Decode)?;
+    Ok((Primed::new(decoder, prime), health))
+}
+
+#[derive(Debug)]
+enum PlaybackError {
+    DeviceNotFound(String),
+    NoDevice,
+    UnsupportedFormat { channels: u16, sample_rate: u32 },
+    Device(rodio::StreamError),
+    Sink(rodio::PlayError),
+    Open(std::io::Error),
+    Decode(rodio::decoder::DecoderError),
+}
+
+impl std::fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlaybackError::DeviceNotFound(name) => write!(f, "Output device \"{}\" not found, using the default", name),
+            PlaybackError::NoDevice => f.write_str("No audio output device found"),
+            PlaybackError::UnsupportedFormat { channels, sample_rate } => write!(
+                f,
+                "The output device can't play {} Hz, {} channel audio, so its default format is used instead",
+                sample_rate, channels
+            ),
+            PlaybackError::Device(e) => write!(f, "Failed to open the audio device: {}", e),
+            PlaybackError::Sink(e) => write!(f, "Failed to start audio output: {}", e),
+            PlaybackError::Open(e) => write!(f, "Failed to open the audio file: {}", e),
+            PlaybackError::Decode(e) => write!(f, "Failed to decode the audio file: {}", e),
+        }
+    }
+}
+
+impl PlaybackError {
+    fn is_about_the_file(&self) -> bool {
+        matches!(self, PlaybackError::Open(_) | PlaybackError::Decode(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn picks_a_config_matching_the_track() {
+        let range = |channels, min, max, format| {
+            cpal::SupportedStreamConfigRange::new(channels, cpal::SampleRate(min), cpal::SampleRate(max), cpal::SupportedBufferSize::Unknown, format)
+        };
+        let ranges = vec![
+            range(2, 44100, 48000, cpal::SampleFormat::F32),
+            range(2, 44100, 192000, cpal::SampleFormat::I32),
+            range(2, 44100, 48000, cpal::SampleFormat::I16),
+            range(6, 48000, 48000, cpal::SampleFormat::I16),
+        ];
+
+        let config = matching_config(ranges.clone(), 2, 44100, &NATIVE_FORMATS).unwrap();
+        assert_eq!((config.sample_format(), config.sample_rate().0), (cpal::SampleFormat::I16, 44100));
+        let config = matching_config(ranges.clone(), 2, 44100, &PROCESSED_FORMATS).unwrap();
+        assert_eq!((config.sample_format(), config.sample_rate().0), (cpal::SampleFormat::F32, 44100));
+        let config = matching_config(ranges.clone(), 2, 96000, &NATIVE_FORMATS).unwrap();
+        assert_eq!((config.sample_format(), config.sample_rate().0), (cpal::SampleFormat::I32, 96000));
+        assert!(matching_config(ranges.clone(), 1, 44100, &NATIVE_FORMATS).is_none());
+        assert!(matching_config(ranges, 6, 44100, &NATIVE_FORMATS).is_none());
+    }
+
+    #[test]
+    fn converts_decibels_to_gain() {
+        assert_eq!(db_to_gain(0.0), 1.0);
+        assert!((db_to_gain(-6.0) - 0.501).abs() < 0.001);
+        assert!((db_to_gain(20.0) - 10.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn tap_keeps_the_most_recent_samples() {
+        let tap = SampleTap::default();
+        tap.set_enabled(true);
+        let samples: Vec<f32> = (0..TAP_FRAMES * 2 + TAP_BATCH).map(|i| i as f32).collect();
+        let source = Tapped::new(SamplesBuffer::new(1, 44100, samples.clone()), tap.clone());
+
+        // Audio passes through untouched
+        assert_eq!(source.collect::<Vec<f32>>(), samples);
+
+        let snapshot = tap.snapshot();
+        assert_eq!(snapshot.channels, 1);
+        assert_eq!(snapshot.sample_rate, 44100);
+        assert_eq!(snapshot.samples.len(), TAP_FRAMES);
+        assert_eq!(snapshot.samples.back(), samples.last());
+    }
+
+    #[test]
+    fn disabled_tap_copies_nothing() {
+        let tap = SampleTap::default();
+        let source = Tapped::new(SamplesBuffer::new(2, 48000, vec![0.5; TAP_BATCH * 4]), tap.clone());
+        assert_eq!(source.count(), TAP_BATCH * 4);
+        assert!(tap.snapshot().samples.is_empty());
+        assert!(tap.take_peaks().is_empty());
+    }
+
+    #[test]
+    fn tap_tracks_peaks_per_channel() {
+        let tap = SampleTap::default();
+        tap.set_enabled(true);
+        // Six channels don't divide the batch size evenly
+        let samples: Vec<f32> = (0..TAP_BATCH * 6).map(|i| [0.1, -0.2, 0.3, -0.4, 0.5, -1.0][i % 6]).collect();
+        let source = Tapped::new(SamplesBuffer::new(6, 48000, samples), tap.clone());
+        assert_eq!(source.count(), TAP_BATCH * 6);
+
+        assert_eq!(tap.take_peaks(), vec![0.1, 0.2, 0.3, 0.4, 0.5, 1.0]);
+        // Nothing new has played since
+        assert_eq!(tap.take_peaks(), vec![0.0; 6]);
+    }
+}
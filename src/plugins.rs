@@ -0,0 +1,417 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, CStr, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::metadata::TrackInfo;
+use crate::project_dirs;
+
+/// Plugins built against a different version of the API below are turned away.
+pub const PLUGIN_API_VERSION: u32 = 1;
+// Each plugin library exports a function of this name returning its `PluginVTable`
+const ENTRY_POINT: &[u8] = b"music_jester_plugin\0";
+
+/// Looks up details missing from a track's tags, e.g. from an online database.
+pub trait MetadataProvider: Send + Sync {
+    fn name(&self) -> &str;
+    /// What's known about `file_path` besides `known`. Only fields the tags left
+    /// empty are used.
+    fn track_info(&self, file_path: &Path, known: &TrackInfo) -> Option<TrackInfo>;
+}
+
+/// Finds the words to a track.
+pub trait LyricsSource: Send + Sync {
+    fn name(&self) -> &str;
+    fn lyrics(&self, file_path: &Path, info: &TrackInfo) -> Option<String>;
+}
+
+/// Gets a copy of everything played, e.g. to stream it elsewhere or drive lights.
+pub trait OutputSink: Send + Sync {
+    fn name(&self) -> &str;
+    /// Interleaved samples, in batches of whole frames. Called on the audio
+    /// thread, so this mustn't block.
+    fn write(&self, samples: &[f32], channels: u16, sample_rate: u32);
+}
+
+/// The plugins found at startup, sorted by what they provide.
+#[derive(Clone, Default)]
+pub struct Plugins {
+    metadata: Vec<Arc<dyn MetadataProvider>>,
+    lyrics: Vec<Arc<dyn LyricsSource>>,
+    sinks: Vec<Arc<dyn OutputSink>>,
+    pub loaded: Vec<PluginSummary>,
+    pub failed: Vec<(PathBuf, String)>, // Libraries that couldn't be loaded, and why
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginSummary {
+    pub name: String,
+    pub file_path: PathBuf,
+    pub provides: Vec<&'static str>,
+}
+
+impl std::fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Plugins").field("loaded", &self.loaded).field("failed", &self.failed).finish()
+    }
+}
+
+/// Where plugin libraries go: "plugins" in the platform data folder.
+pub fn plugins_dir() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.data_dir().join("plugins"))
+}
+
+impl Plugins {
+    /// Loads every dynamic library in `dir`. One that fails is noted and skipped.
+    pub fn load(dir: &Path) -> Self {
+        let mut plugins = Plugins::default();
+        let Ok(entries) = fs::read_dir(dir) else { return plugins };
+        let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        paths.sort();
+        for file_path in paths.into_iter().filter(|path| path.extension().is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION)) {
+            match NativePlugin::load(&file_path) {
+                Ok(plugin) => {
+                    tracing::info!(path = %file_path.display(), name = plugin.name, "Loaded plugin");
+                    plugins.add(Arc::new(plugin), file_path);
+                }
+                Err(e) => {
+                    tracing::warn!(path = %file_path.display(), "Failed to load plugin: {}", e);
+                    plugins.failed.push((file_path, e));
+                }
+            }
+        }
+        plugins
+    }
+
+    fn add(&mut self, plugin: Arc<NativePlugin>, file_path: PathBuf) {
+        let mut provides = Vec::new();
+        if plugin.vtable.track_info.is_some() {
+            self.metadata.push(plugin.clone());
+            provides.push("metadata");
+        }
+        if plugin.vtable.lyrics.is_some() {
+            self.lyrics.push(plugin.clone());
+            provides.push("lyrics");
+        }
+        if plugin.vtable.write_samples.is_some() {
+            self.sinks.push(plugin.clone());
+            provides.push("output");
+        }
+        self.loaded.push(PluginSummary { name: plugin.name.clone(), file_path, provides });
+    }
+
+    /// Fills in what `info`'s tags left empty from the metadata providers, asking
+    /// each in turn until nothing's missing. Only asks at all if the title, artist
+    /// or album is missing.
+    pub fn fill_in(&self, file_path: &Path, info: &mut TrackInfo) {
+        for provider in &self.metadata {
+            if info.title.is_some() && info.artist.is_some() && info.album.is_some() {
+                return;
+            }
+            if let Some(found) = provider.track_info(file_path, info) {
+                tracing::debug!(path = %file_path.display(), plugin = provider.name(), "Filled in track info");
+                fill_missing(info, found);
+            }
+        }
+    }
+
+    /// The first lyrics any source has for the track.
+    pub fn lyrics(&self, file_path: &Path, info: &TrackInfo) -> Option<String> {
+        let (source, lyrics) = self.lyrics.iter().find_map(|source| {
+            let lyrics = source.lyrics(file_path, info).filter(|lyrics| !lyrics.trim().is_empty())?;
+            Some((source.name(), lyrics))
+        })?;
+        tracing::debug!(path = %file_path.display(), plugin = source, "Found lyrics");
+        Some(lyrics)
+    }
+
+    pub fn has_lyrics_sources(&self) -> bool {
+        !self.lyrics.is_empty()
+    }
+
+    pub fn sinks(&self) -> Vec<Arc<dyn OutputSink>> {
+        self.sinks.clone()
+    }
+}
+
+fn fill_missing(info: &mut TrackInfo, found: TrackInfo) {
+    fn fill<T>(field: &mut Option<T>, found: Option<T>) {
+        if field.is_none() {
+            *field = found;
+        }
+    }
+    fill(&mut info.title, found.title);
+    fill(&mut info.artist, found.artist);
+    fill(&mut info.album, found.album);
+    fill(&mut info.album_artist, found.album_artist);
+    fill(&mut info.genre, found.genre);
+    fill(&mut info.year, found.year);
+    fill(&mut info.track_number, found.track_number);
+    fill(&mut info.disc_number, found.disc_number);
+    fill(&mut info.composer, found.composer);
+    fill(&mut info.duration, found.duration);
+}
+
+/// What a plugin library hands back from `music_jester_plugin`. Everything it
+/// points to has to stay valid while the library's loaded. Strings are UTF-8
+/// and NUL-terminated. Leave a function null for anything not provided.
+#[repr(C)]
+pub struct PluginVTable {
+    pub api_version: u32,
+    pub name: *const c_char,
+    /// Takes a [`PluginTrack`] as JSON and returns one with whatever it found,
+    /// or null for nothing.
+    pub track_info: Option<unsafe extern "C" fn(request: *const c_char) -> *mut c_char>,
+    /// Takes a [`PluginTrack`] as JSON and returns the lyrics as plain text, or null.
+    pub lyrics: Option<unsafe extern "C" fn(request: *const c_char) -> *mut c_char>,
+    /// Gets `len` interleaved samples.
+    pub write_samples: Option<unsafe extern "C" fn(samples: *const f32, len: usize, channels: u16, sample_rate: u32)>,
+    /// Frees a string returned by the functions above. Needed for either of them.
+    pub free_string: Option<unsafe extern "C" fn(string: *mut c_char)>,
+}
+
+// SAFETY: the vtable is only read, and a plugin's functions have to be callable
+// from any thread
+unsafe impl Sync for PluginVTable {}
+
+/// A track as passed to and from plugins, in JSON.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginTrack {
+    pub path: Option<PathBuf>, // Only set when sent to the plugin
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub composer: Option<String>,
+    pub duration_seconds: Option<f64>,
+}
+
+impl PluginTrack {
+    fn new(file_path: &Path, info: &TrackInfo) -> Self {
+        Self {
+            path: Some(file_path.to_path_buf()),
+            title: info.title.clone(),
+            artist: info.artist.clone(),
+            album: info.album.clone(),
+            album_artist: info.album_artist.clone(),
+            genre: info.genre.clone(),
+            year: info.year,
+            track_number: info.track_number,
+            disc_number: info.disc_number,
+            composer: info.composer.clone(),
+            duration_seconds: info.duration.map(|duration| duration.as_secs_f64()),
+        }
+    }
+
+    fn into_track_info(self) -> TrackInfo {
+        TrackInfo {
+            title: self.title,
+            artist: self.artist,
+            album: self.album,
+            album_artist: self.album_artist,
+            genre: self.genre,
+            year: self.year,
+            track_number: self.track_number,
+            disc_number: self.disc_number,
+            composer: self.composer,
+            duration: self.duration_seconds.and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()),
+            ..Default::default()
+        }
+    }
+}
+
+/// A plugin in a dynamic library, speaking the C interface above.
+struct NativePlugin {
+    name: String,
+    vtable: &'static PluginVTable,
+    // Declared last so it's unloaded after everything pointing into it is gone
+    _library: Option<libloading::Library>,
+}
+
+impl NativePlugin {
+    fn load(file_path: &Path) -> Result<Self, String> {
+        // SAFETY: loading a library runs its initialisers; plugins are trusted
+        // code the user put in the plugins folder themselves
+        let library = unsafe { libloading::Library::new(file_path) }.map_err(|e| e.to_string())?;
+        // SAFETY: the entry point's signature is part of the plugin API
+        let vtable = unsafe {
+            let entry = library
+                .get::<unsafe extern "C" fn() -> *const PluginVTable>(ENTRY_POINT)
+                .map_err(|_| "Not a Music Jester plugin: there's no music_jester_plugin function".to_string())?;
+            entry()
+        };
+        // SAFETY: the vtable lives as long as the library, which this keeps loaded
+        let vtable = unsafe { vtable.as_ref() }.ok_or("The plugin didn't describe itself")?;
+        Self::new(vtable, Some(library))
+    }
+
+    fn new(vtable: &'static PluginVTable, library: Option<libloading::Library>) -> Result<Self, String> {
+        if vtable.api_version != PLUGIN_API_VERSION {
+            return Err(format!("Built for plugin API version {}, this is version {}", vtable.api_version, PLUGIN_API_VERSION));
+        }
+        if vtable.name.is_null() {
+            return Err("The plugin has no name".to_string());
+        }
+        // Otherwise every string it hands back would be leaked
+        if (vtable.track_info.is_some() || vtable.lyrics.is_some()) && vtable.free_string.is_none() {
+            return Err("The plugin returns strings but has no free_string to free them".to_string());
+        }
+        // SAFETY: checked for null; the API asks for a NUL-terminated string
+        let name = unsafe { CStr::from_ptr(vtable.name) }.to_string_lossy().into_owned();
+        Ok(Self { name, vtable, _library: library })
+    }
+
+    /// Sends `request` to `function` and takes back the string it returns.
+    fn call(&self, function: unsafe extern "C" fn(*const c_char) -> *mut c_char, request: &str) -> Option<String> {
+        let request = CString::new(request).ok()?;
+        // SAFETY: the request outlives the call, and what comes back is either
+        // null or a string the plugin allocated, freed with its own free_string
+        unsafe {
+            let response = function(request.as_ptr());
+            if response.is_null() {
+                return None;
+            }
+            let text = CStr::from_ptr(response).to_string_lossy().into_owned();
+            if let Some(free_string) = self.vtable.free_string {
+                free_string(response);
+            }
+            Some(text)
+        }
+    }
+
+    fn request(&self, file_path: &Path, info: &TrackInfo) -> Option<String> {
+        serde_json::to_string(&PluginTrack::new(file_path, info)).ok()
+    }
+}
+
+impl MetadataProvider for NativePlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn track_info(&self, file_path: &Path, known: &TrackInfo) -> Option<TrackInfo> {
+        let response = self.call(self.vtable.track_info?, &self.request(file_path, known)?)?;
+        match serde_json::from_str::<PluginTrack>(&response) {
+            Ok(track) => Some(track.into_track_info()),
+            Err(e) => {
+                tracing::warn!(plugin = self.name, "Couldn't make sense of the track info: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl LyricsSource for NativePlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn lyrics(&self, file_path: &Path, info: &TrackInfo) -> Option<String> {
+        self.call(self.vtable.lyrics?, &self.request(file_path, info)?)
+    }
+}
+
+impl OutputSink for NativePlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        if let Some(write_samples) = self.vtable.write_samples {
+            // SAFETY: the plugin only reads `len` samples, during the call
+            unsafe { write_samples(samples.as_ptr(), samples.len(), channels, sample_rate) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // A plugin built into the test, going through the same C interface as a library
+
+    static SAMPLES_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn track_info(request: *const c_char) -> *mut c_char {
+        let request: PluginTrack = serde_json::from_str(unsafe { CStr::from_ptr(request) }.to_str().unwrap()).unwrap();
+        if request.path.as_deref() != Some(Path::new("/music/unknown.mp3")) {
+            return std::ptr::null_mut();
+        }
+        CString::new(r#"{"title": "Found", "artist": "Not used", "year": 1999}"#).unwrap().into_raw()
+    }
+
+    unsafe extern "C" fn write_samples(_samples: *const f32, len: usize, _channels: u16, _sample_rate: u32) {
+        SAMPLES_WRITTEN.fetch_add(len, Ordering::Relaxed);
+    }
+
+    unsafe extern "C" fn free_string(string: *mut c_char) {
+        drop(unsafe { CString::from_raw(string) });
+    }
+
+    static VTABLE: PluginVTable = PluginVTable {
+        api_version: PLUGIN_API_VERSION,
+        name: c"Test plugin".as_ptr(),
+        track_info: Some(track_info),
+        lyrics: None,
+        write_samples: Some(write_samples),
+        free_string: Some(free_string),
+    };
+
+    #[test]
+    fn plugins_fill_in_what_the_tags_leave_out() {
+        let mut plugins = Plugins::default();
+        plugins.add(Arc::new(NativePlugin::new(&VTABLE, None).unwrap()), PathBuf::from("test.so"));
+        assert_eq!(plugins.loaded[0].name, "Test plugin");
+        assert_eq!(plugins.loaded[0].provides, ["metadata", "output"]);
+
+        let mut info = TrackInfo { artist: Some("Tagged".to_string()), ..Default::default() };
+        plugins.fill_in(Path::new("/music/unknown.mp3"), &mut info);
+        assert_eq!((info.title.as_deref(), info.artist.as_deref(), info.year), (Some("Found"), Some("Tagged"), Some(1999)));
+        let endless = PluginTrack { duration_seconds: Some(1e300), ..Default::default() };
+        assert_eq!(endless.into_track_info().duration, None);
+        let mut other = TrackInfo::default();
+        plugins.fill_in(Path::new("/music/other.mp3"), &mut other);
+        assert_eq!(other, TrackInfo::default());
+
+        assert_eq!(plugins.lyrics(Path::new("/music/unknown.mp3"), &info), None);
+        plugins.sinks()[0].write(&[0.0; 8], 2, 44100);
+        assert_eq!(SAMPLES_WRITTEN.load(Ordering::Relaxed), 8);
+    }
+
+    #[test]
+    fn refuses_other_api_versions_and_non_plugins() {
+        static OLD: PluginVTable = PluginVTable {
+            api_version: PLUGIN_API_VERSION + 1,
+            name: c"Too new".as_ptr(),
+            track_info: None,
+            lyrics: None,
+            write_samples: None,
+            free_string: None,
+        };
+        assert!(NativePlugin::new(&OLD, None).is_err());
+        static LEAKY: PluginVTable = PluginVTable {
+            api_version: PLUGIN_API_VERSION,
+            name: c"Leaky".as_ptr(),
+            track_info: Some(track_info),
+            lyrics: None,
+            write_samples: None,
+            free_string: None,
+        };
+        assert!(NativePlugin::new(&LEAKY, None).err().unwrap().contains("free_string"));
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(format!("broken.{}", std::env::consts::DLL_EXTENSION)), b"not a library").unwrap();
+        fs::write(dir.path().join("readme.txt"), b"ignored").unwrap();
+        let plugins = Plugins::load(dir.path());
+        assert!(plugins.loaded.is_empty());
+        assert_eq!(plugins.failed.len(), 1);
+    }
+}
@@ -0,0 +1,117 @@
+use rodio::{Sample, Source};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// A source with its first stretch decoded up front, so playing it can start
+/// the moment it's asked to rather than after opening and decoding the file.
+/// Past that it decodes as it plays, like any other.
+pub struct Primed<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    inner: S,
+    head: VecDeque<S::Item>, // Decoded ahead, still to be played
+    carry: Option<S::Item>,  // The first sample in a new format, where priming stopped
+    channels: u16,           // Of the head
+    sample_rate: u32,
+}
+
+impl<S> Primed<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    /// Decodes `length` of `inner` now. Nothing, for a length of zero.
+    pub fn new(mut inner: S, length: Duration) -> Self {
+        let (channels, sample_rate) = (inner.channels(), inner.sample_rate());
+        let wanted = (length.as_secs_f64() * sample_rate as f64) as usize * channels as usize;
+        let mut head = VecDeque::with_capacity(wanted);
+        let mut carry = None;
+        while head.len() < wanted {
+            let Some(sample) = inner.next() else { break };
+            // Everything in the head has to share one format, which a change mid-file would break
+            if inner.channels() != channels || inner.sample_rate() != sample_rate {
+                carry = Some(sample);
+                break;
+            }
+            head.push_back(sample);
+        }
+        Self { inner, head, carry, channels, sample_rate }
+    }
+
+    /// How much was decoded up front.
+    pub fn primed(&self) -> Duration {
+        Duration::from_secs_f64(self.head.len() as f64 / (self.sample_rate as f64 * self.channels as f64).max(1.0))
+    }
+}
+
+impl<S> Iterator for Primed<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        self.head.pop_front().or_else(|| self.carry.take()).or_else(|| self.inner.next())
+    }
+}
+
+impl<S> Source for Primed<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        if !self.head.is_empty() {
+            Some(self.head.len())
+        } else if self.carry.is_some() {
+            self.inner.current_frame_len().map(|len| len + 1)
+        } else {
+            self.inner.current_frame_len()
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        if self.head.is_empty() { self.inner.channels() } else { self.channels }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        if self.head.is_empty() { self.inner.sample_rate() } else { self.sample_rate }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        // The decoder's already past the head, so it's simplest to drop it and go from there
+        self.head.clear();
+        self.carry = None;
+        self.inner.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn plays_the_head_then_the_rest() {
+        let samples: Vec<i16> = (0..6000).collect();
+        let primed = Primed::new(SamplesBuffer::new(2, 1000, samples.clone()), Duration::from_secs(1));
+        assert_eq!(primed.primed(), Duration::from_secs(1));
+        assert_eq!((primed.channels(), primed.sample_rate(), primed.current_frame_len()), (2, 1000, Some(2000)));
+        assert_eq!(primed.collect::<Vec<_>>(), samples);
+
+        let mut seeked = Primed::new(SamplesBuffer::new(2, 1000, samples.clone()), Duration::from_secs(1));
+        seeked.try_seek(Duration::from_secs(2)).unwrap();
+        assert_eq!(seeked.next(), Some(4000));
+
+        let unprimed = Primed::new(SamplesBuffer::new(1, 1000, samples.clone()), Duration::ZERO);
+        assert_eq!(unprimed.primed(), Duration::ZERO);
+        assert_eq!(unprimed.count(), samples.len());
+    }
+}
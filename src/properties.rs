@@ -0,0 +1,291 @@
+use lofty::id3::v2::Id3v2Version;
+use lofty::iff::wav::{WavFile, WavFormat};
+use lofty::mp4::{Mp4Codec, Mp4File};
+use lofty::mpeg::{Layer, MpegFile, MpegVersion};
+use lofty::{AudioFile, FileType, ParseOptions, TagType, TaggedFileExt};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Far enough into an MP3's audio to find the Xing or VBRI header in the first frame
+const MPEG_HEAD_BYTES: usize = 4096;
+
+/// Technical details of a file, for the Properties dialog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Properties {
+    pub path: PathBuf,
+    pub file_size: u64, // Bytes
+    pub container: String,
+    pub codec: String,
+    pub bitrate_mode: BitrateMode,
+    pub bitrate: Option<u32>, // kbps, audio only
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
+    pub duration: Duration,
+    pub tags: Vec<TagSummary>, // In the order lofty found them
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitrateMode {
+    Constant,
+    Variable,
+    Lossless,
+    Unknown,
+}
+
+/// One of the tags in a file, e.g. ID3v2.4, and how many pictures it carries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagSummary {
+    pub kind: String,
+    pub pictures: u32,
+}
+
+impl Properties {
+    pub fn picture_count(&self) -> u32 {
+        self.tags.iter().map(|tag| tag.pictures).sum()
+    }
+
+    /// Label and value pairs, in the order the dialog lists them.
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "Unknown".to_string());
+        let tags = match &self.tags[..] {
+            [] => "None".to_string(),
+            tags => tags.iter().map(|tag| tag.kind.as_str()).collect::<Vec<_>>().join(", "),
+        };
+        vec![
+            ("Path", self.path.display().to_string()),
+            ("File size", format_size(self.file_size)),
+            ("Container", self.container.clone()),
+            ("Codec", self.codec.clone()),
+            ("Bitrate", or_unknown(self.bitrate.map(|kbps| format!("{} kbps", kbps)))),
+            ("Bitrate mode", self.bitrate_mode.to_string()),
+            ("Sample rate", or_unknown(self.sample_rate.map(|hz| format!("{} Hz", hz)))),
+            ("Bit depth", or_unknown(self.bit_depth.map(|bits| format!("{}-bit", bits)))),
+            ("Channels", or_unknown(self.channels.map(|channels| channels.to_string()))),
+            ("Duration", format!("{}:{:02}", self.duration.as_secs() / 60, self.duration.as_secs() % 60)),
+            ("Tags", tags),
+            ("Embedded pictures", self.picture_count().to_string()),
+        ]
+    }
+}
+
+impl std::fmt::Display for BitrateMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BitrateMode::Constant => "Constant (CBR)",
+            BitrateMode::Variable => "Variable (VBR)",
+            BitrateMode::Lossless => "Lossless",
+            BitrateMode::Unknown => "Unknown",
+        })
+    }
+}
+
+/// Reads everything the Properties dialog shows about `file_path`.
+pub fn read_properties(file_path: &Path) -> Result<Properties, String> {
+    let file_size = fs::metadata(file_path).map_err(|e| e.to_string())?.len();
+    let tagged = lofty::read_from_path(file_path).map_err(|e| e.to_string())?;
+    let properties = tagged.properties();
+    let details = format_details(file_path, tagged.file_type()).map_err(|e| e.to_string())?;
+    let tags = tagged
+        .tags()
+        .iter()
+        .map(|tag| TagSummary {
+            kind: match (tag.tag_type(), details.id3v2_version) {
+                (TagType::Id3v2, Some(version)) => id3v2_name(version).to_string(),
+                (tag_type, _) => tag_type_name(tag_type).to_string(),
+            },
+            pictures: tag.picture_count(),
+        })
+        .collect();
+    Ok(Properties {
+        path: file_path.to_path_buf(),
+        file_size,
+        container: details.container.to_string(),
+        codec: details.codec,
+        bitrate_mode: details.bitrate_mode,
+        bitrate: properties.audio_bitrate().filter(|&kbps| kbps > 0),
+        sample_rate: properties.sample_rate(),
+        bit_depth: properties.bit_depth(),
+        channels: properties.channels(),
+        duration: properties.duration(),
+        tags,
+    })
+}
+
+/// What only the format-specific readers know.
+struct FormatDetails {
+    container: &'static str,
+    codec: String,
+    bitrate_mode: BitrateMode,
+    id3v2_version: Option<Id3v2Version>, // The generic tag doesn't say which ID3v2
+}
+
+fn format_details(file_path: &Path, file_type: FileType) -> lofty::Result<FormatDetails> {
+    let details = |container, codec: &str, bitrate_mode| FormatDetails { container, codec: codec.to_string(), bitrate_mode, id3v2_version: None };
+    Ok(match file_type {
+        FileType::Mpeg => {
+            let mpeg = MpegFile::read_from(&mut File::open(file_path)?, ParseOptions::new())?;
+            let version = match mpeg.properties().version() {
+                MpegVersion::V1 => "1",
+                MpegVersion::V2 => "2",
+                MpegVersion::V2_5 => "2.5",
+                MpegVersion::V4 => "4",
+            };
+            let layer = match mpeg.properties().layer() {
+                Layer::Layer1 => "I",
+                Layer::Layer2 => "II",
+                Layer::Layer3 => "III",
+            };
+            FormatDetails {
+                container: "MPEG audio",
+                codec: format!("MPEG-{} Layer {}", version, layer),
+                bitrate_mode: mpeg_bitrate_mode(&read_mpeg_head(file_path)?),
+                id3v2_version: mpeg.id3v2().map(|tag| tag.original_version()),
+            }
+        }
+        FileType::Mp4 => {
+            let mp4 = Mp4File::read_from(&mut File::open(file_path)?, ParseOptions::new())?;
+            match mp4.properties().codec() {
+                Mp4Codec::AAC => details("MPEG-4", "AAC", BitrateMode::Unknown),
+                Mp4Codec::ALAC => details("MPEG-4", "ALAC", BitrateMode::Lossless),
+                Mp4Codec::MP3 => details("MPEG-4", "MP3", BitrateMode::Unknown),
+                Mp4Codec::FLAC => details("MPEG-4", "FLAC", BitrateMode::Lossless),
+                _ => details("MPEG-4", "Unknown", BitrateMode::Unknown),
+            }
+        }
+        FileType::Wav => {
+            let wav = WavFile::read_from(&mut File::open(file_path)?, ParseOptions::new())?;
+            let codec = match wav.properties().format() {
+                WavFormat::PCM => "PCM".to_string(),
+                WavFormat::IEEE_FLOAT => "PCM (floating point)".to_string(),
+                WavFormat::Other(tag) => format!("Format {:#06x}", tag),
+            };
+            FormatDetails {
+                container: "RIFF WAVE",
+                codec,
+                bitrate_mode: BitrateMode::Lossless,
+                id3v2_version: wav.id3v2().map(|tag| tag.original_version()),
+            }
+        }
+        FileType::Flac => details("FLAC", "FLAC", BitrateMode::Lossless),
+        FileType::Vorbis => details("Ogg", "Vorbis", BitrateMode::Variable),
+        FileType::Opus => details("Ogg", "Opus", BitrateMode::Variable),
+        FileType::Speex => details("Ogg", "Speex", BitrateMode::Variable),
+        FileType::Aiff => details("AIFF", "PCM", BitrateMode::Lossless),
+        FileType::Ape => details("Monkey's Audio", "APE", BitrateMode::Lossless),
+        FileType::WavPack => details("WavPack", "WavPack", BitrateMode::Unknown),
+        FileType::Aac => details("ADTS", "AAC", BitrateMode::Unknown),
+        FileType::Mpc => details("Musepack", "Musepack", BitrateMode::Variable),
+        _ => details("Unknown", "Unknown", BitrateMode::Unknown),
+    })
+}
+
+/// The start of an MP3's audio, past any ID3v2 tag (which can be big, with album art).
+fn read_mpeg_head(file_path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(file_path)?;
+    let mut header = [0; 10];
+    let tag_size = match file.read_exact(&mut header) {
+        Ok(()) if header.starts_with(b"ID3") => {
+            let size = header[6..10].iter().fold(0u64, |size, &byte| (size << 7) | u64::from(byte & 0x7f));
+            let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        _ => 0,
+    };
+    file.seek(SeekFrom::Start(tag_size))?;
+    let mut head = Vec::with_capacity(MPEG_HEAD_BYTES);
+    file.take(MPEG_HEAD_BYTES as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Encoders put a Xing or VBRI header in the first frame of a VBR file, and LAME
+/// writes the same header as "Info" for CBR. No header at all means CBR.
+fn mpeg_bitrate_mode(head: &[u8]) -> BitrateMode {
+    let has = |marker: &[u8]| head.windows(marker.len()).any(|window| window == marker);
+    if has(b"Xing") || has(b"VBRI") { BitrateMode::Variable } else { BitrateMode::Constant }
+}
+
+fn id3v2_name(version: Id3v2Version) -> &'static str {
+    match version {
+        Id3v2Version::V2 => "ID3v2.2",
+        Id3v2Version::V3 => "ID3v2.3",
+        Id3v2Version::V4 => "ID3v2.4",
+    }
+}
+
+fn tag_type_name(tag_type: TagType) -> &'static str {
+    match tag_type {
+        TagType::Ape => "APEv2",
+        TagType::Id3v1 => "ID3v1",
+        TagType::Id3v2 => "ID3v2",
+        TagType::Mp4Ilst => "MP4 metadata (ilst)",
+        TagType::VorbisComments => "Vorbis comments",
+        TagType::RiffInfo => "RIFF INFO",
+        TagType::AiffText => "AIFF text chunks",
+        _ => "Other",
+    }
+}
+
+/// Bytes in binary units, e.g. "4.2 MB".
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {} ({} bytes)", size, UNITS[unit], bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_audio::write_wav;
+    use lofty::{MimeType, Picture, PictureType, Tag, TagExt};
+
+    #[test]
+    fn reads_a_wav_files_properties() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("take.wav");
+        write_wav(&path, 2, 8000, &[0; 16000]);
+        let mut tag = Tag::new(TagType::Id3v2);
+        for picture_type in [PictureType::CoverFront, PictureType::CoverBack] {
+            tag.push_picture(Picture::new_unchecked(picture_type, MimeType::Png, None, vec![0x89, b'P', b'N', b'G']));
+        }
+        tag.save_to_path(&path).unwrap();
+
+        let properties = read_properties(&path).unwrap();
+        assert_eq!(properties.container, "RIFF WAVE");
+        assert_eq!(properties.codec, "PCM");
+        assert_eq!(properties.bitrate_mode, BitrateMode::Lossless);
+        assert_eq!((properties.sample_rate, properties.bit_depth, properties.channels), (Some(8000), Some(16), Some(2)));
+        assert_eq!(properties.duration.as_secs(), 1);
+        assert_eq!(properties.tags, [TagSummary { kind: "ID3v2.4".to_string(), pictures: 2 }]);
+        assert_eq!(properties.picture_count(), 2);
+        assert_eq!(properties.file_size, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn tells_vbr_mp3s_by_their_header() {
+        let mut frame = vec![0xff, 0xfb, 0x90, 0x64];
+        frame.extend_from_slice(&[0; 32]);
+        assert_eq!(mpeg_bitrate_mode(&frame), BitrateMode::Constant);
+        let mut lame_cbr = frame.clone();
+        lame_cbr.extend_from_slice(b"Info");
+        assert_eq!(mpeg_bitrate_mode(&lame_cbr), BitrateMode::Constant);
+        frame.extend_from_slice(b"Xing");
+        assert_eq!(mpeg_bitrate_mode(&frame), BitrateMode::Variable);
+    }
+
+    #[test]
+    fn sizes_read_naturally() {
+        assert_eq!(format_size(512), "512 bytes");
+        assert_eq!(format_size(4_404_019), "4.2 MB (4404019 bytes)");
+    }
+}
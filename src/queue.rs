@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use crate::albums::Album;
+
+/// Tracks waiting to be played after the current one, first in first out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayQueue {
+    tracks: VecDeque<PathBuf>,
+}
+
+impl PlayQueue {
+    pub fn push(&mut self, file_path: PathBuf) {
+        self.tracks.push_back(file_path);
+    }
+
+    /// Queues `file_path`, or hands it back to be started straight away if
+    /// nothing's playing or about to (`busy`) and nothing's waiting its turn.
+    pub fn enqueue(&mut self, file_path: PathBuf, busy: bool) -> Option<PathBuf> {
+        if busy || !self.is_empty() {
+            self.push(file_path);
+            None
+        } else {
+            Some(file_path)
+        }
+    }
+
+    /// Takes the track that should play next.
+    pub fn pop_next(&mut self) -> Option<PathBuf> {
+        self.tracks.pop_front()
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<PathBuf> {
+        self.tracks.remove(index)
+    }
+
+    /// Drops every queued copy of `file_path`, e.g. once it's been deleted.
+    pub fn forget(&mut self, file_path: &Path) {
+        self.tracks.retain(|queued| queued != file_path);
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PathBuf> {
+        self.tracks.iter()
+    }
+}
+
+impl FromIterator<PathBuf> for PlayQueue {
+    fn from_iter<I: IntoIterator<Item = PathBuf>>(tracks: I) -> Self {
+        Self { tracks: tracks.into_iter().collect() }
+    }
+}
+
+/// What shuffling keeps together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShuffleMode {
+    #[default]
+    Tracks,
+    Albums, // Albums come in a random order, each played start to finish
+}
+
+impl ShuffleMode {
+    pub const ALL: [ShuffleMode; 2] = [ShuffleMode::Tracks, ShuffleMode::Albums];
+}
+
+impl std::fmt::Display for ShuffleMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ShuffleMode::Tracks => "Shuffle tracks",
+            ShuffleMode::Albums => "Shuffle albums",
+        })
+    }
+}
+
+/// Puts `files` in a random order. By album, each album's tracks stay together
+/// and in order; tracks that aren't on an album are shuffled in on their own.
+pub fn shuffle(mode: ShuffleMode, files: &[PathBuf], albums: &[Album]) -> Vec<PathBuf> {
+    let mut units: Vec<Vec<PathBuf>> = match mode {
+        ShuffleMode::Tracks => files.iter().map(|file_path| vec![file_path.clone()]).collect(),
+        ShuffleMode::Albums => {
+            let wanted: HashSet<&PathBuf> = files.iter().collect();
+            let mut on_album = HashSet::new();
+            let mut units = Vec::new();
+            for album in albums {
+                let tracks: Vec<PathBuf> = album.tracks.iter().filter(|track| wanted.contains(track)).cloned().collect();
+                if !tracks.is_empty() {
+                    on_album.extend(tracks.iter().cloned());
+                    units.push(tracks);
+                }
+            }
+            units.extend(files.iter().filter(|file_path| !on_album.contains(*file_path)).map(|file_path| vec![file_path.clone()]));
+            units
+        }
+    };
+    fastrand::shuffle(&mut units);
+    units.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::albums::AlbumKey;
+
+    #[test]
+    fn plays_in_the_order_queued() {
+        let mut queue = PlayQueue::default();
+        queue.push(PathBuf::from("a.mp3"));
+        queue.push(PathBuf::from("b.mp3"));
+        queue.push(PathBuf::from("a.mp3"));
+        queue.push(PathBuf::from("c.mp3"));
+
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.remove(2), Some(PathBuf::from("a.mp3")));
+        assert_eq!(queue.pop_next(), Some(PathBuf::from("a.mp3")));
+        queue.forget(Path::new("b.mp3"));
+        assert_eq!(queue.iter().collect::<Vec<_>>(), [Path::new("c.mp3")]);
+        queue.clear();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop_next(), None);
+    }
+
+    #[test]
+    fn enqueueing_while_idle_starts_only_the_first() {
+        let mut queue = PlayQueue::default();
+        // What the UI does: a track handed back is asked to start, which keeps it busy
+        let mut starting = None;
+        for file in ["a.mp3", "b.mp3", "c.mp3"] {
+            if let Some(start) = queue.enqueue(PathBuf::from(file), starting.is_some()) {
+                assert!(starting.is_none(), "started {} as well", start.display());
+                starting = Some(start);
+            }
+        }
+        assert_eq!(starting, Some(PathBuf::from("a.mp3")));
+        assert_eq!(queue.iter().collect::<Vec<_>>(), [Path::new("b.mp3"), Path::new("c.mp3")]);
+        // Something's queued, so even with nothing playing a new track waits its turn
+        assert_eq!(queue.enqueue(PathBuf::from("d.mp3"), false), None);
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn album_shuffle_keeps_each_album_in_order() {
+        let album = |title: &str, tracks: &[&str]| Album {
+            key: AlbumKey { title: title.to_string(), artist: None },
+            tracks: tracks.iter().map(PathBuf::from).collect(),
+        };
+        let albums = [album("A", &["a1.mp3", "a2.mp3", "a3.mp3"]), album("B", &["b1.mp3", "b2.mp3"])];
+        // b1 was filtered out of the list being shuffled; loose.mp3 has no album
+        let files: Vec<PathBuf> = ["a3.mp3", "loose.mp3", "a1.mp3", "b2.mp3", "a2.mp3"].iter().map(PathBuf::from).collect();
+
+        for _ in 0..20 {
+            let shuffled = shuffle(ShuffleMode::Albums, &files, &albums);
+            assert_eq!(shuffled.len(), files.len());
+            let names: Vec<&str> = shuffled.iter().map(|file_path| file_path.to_str().unwrap()).collect();
+            let start = names.iter().position(|name| *name == "a1.mp3").unwrap();
+            assert_eq!(names[start..start + 3], ["a1.mp3", "a2.mp3", "a3.mp3"]);
+            assert!(names.contains(&"loose.mp3") && names.contains(&"b2.mp3") && !names.contains(&"b1.mp3"));
+        }
+        let mut tracks = shuffle(ShuffleMode::Tracks, &files, &albums);
+        tracks.sort();
+        let mut sorted = files.clone();
+        sorted.sort();
+        assert_eq!(tracks, sorted);
+    }
+}
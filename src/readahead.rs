@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+// How far ahead of the decoder to read: a minute or so of FLAC, all of most MP3s
+const READ_AHEAD: usize = 16 * 1024 * 1024;
+// Kept behind the decoder too, as some formats step back a little while probing
+const READ_BEHIND: usize = 256 * 1024;
+const CHUNK: usize = 64 * 1024;
+// A jump forward no further than this is read through rather than started over
+const SEEK_GAP: u64 = 1024 * 1024;
+// How often to try again while the file can't be read
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+// Slow but still working is waited for this long before giving up on a read
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reads a file ahead of the decoder on a thread of its own, so a slow or
+/// briefly unreachable network share doesn't starve playback. When a read
+/// fails the thread keeps reopening the file and carries on where it left off;
+/// the decoder only gets an error if the buffered audio runs out first.
+pub struct ReadAhead {
+    shared: Arc<Shared>,
+    position: u64,
+    length: u64,
+}
+
+/// Whether a [`ReadAhead`] ran dry with the file unreachable, so playback
+/// stopped because the source went away rather than because the track ended.
+#[derive(Debug, Clone, Default)]
+pub struct SourceHealth(Arc<AtomicBool>);
+
+impl SourceHealth {
+    pub fn gave_out(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct Shared {
+    buffer: Mutex<Buffer>,
+    changed: Condvar,
+    health: SourceHealth,
+}
+
+struct Buffer {
+    start: u64, // Offset in the file of `data[0]`
+    data: VecDeque<u8>,
+    generation: u64, // Bumped when the reader has to start over somewhere else
+    failing: bool,   // The last read failed and the reader is retrying
+    at_end: bool,
+    closed: bool,
+}
+
+impl ReadAhead {
+    pub fn open(file_path: &Path) -> io::Result<Self> {
+        let file = fs::File::open(file_path)?;
+        let length = file.metadata()?.len();
+        let shared = Arc::new(Shared {
+            buffer: Mutex::new(Buffer { start: 0, data: VecDeque::new(), generation: 0, failing: false, at_end: false, closed: false }),
+            changed: Condvar::new(),
+            health: SourceHealth::default(),
+        });
+        let reader = Arc::clone(&shared);
+        let file_path = file_path.to_path_buf();
+        std::thread::Builder::new().name("read-ahead".to_string()).spawn(move || fetch(reader, file, file_path))?;
+        Ok(Self { shared, position: 0, length })
+    }
+
+    pub fn health(&self) -> SourceHealth {
+        self.shared.health.clone()
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.length || out.is_empty() {
+            return Ok(0);
+        }
+        let mut buffer = self.shared.buffer.lock().unwrap();
+        loop {
+            let end = buffer.start + buffer.data.len() as u64;
+            if (buffer.start..end).contains(&self.position) {
+                let skip = (self.position - buffer.start) as usize;
+                let mut count = 0;
+                for (slot, &byte) in out.iter_mut().zip(buffer.data.range(skip..)) {
+                    *slot = byte;
+                    count += 1;
+                }
+                self.position += count as u64;
+                // Let go of what's well behind, making room to read further ahead
+                let behind = (self.position - buffer.start) as usize;
+                if behind > READ_BEHIND {
+                    buffer.data.drain(..behind - READ_BEHIND);
+                    buffer.start += (behind - READ_BEHIND) as u64;
+                    self.shared.changed.notify_all();
+                }
+                return Ok(count);
+            }
+            if self.position < buffer.start || self.position > end + SEEK_GAP {
+                // Sought outside what's buffered, so start reading from there
+                buffer.data.clear();
+                buffer.start = self.position;
+                buffer.generation += 1;
+                buffer.at_end = false;
+                self.shared.changed.notify_all();
+            } else if buffer.failing {
+                self.shared.health.0.store(true, Ordering::Relaxed);
+                return Err(io::Error::other("The file can't be reached"));
+            } else if buffer.at_end {
+                return Ok(0);
+            }
+            let (next, timeout) = self.shared.changed.wait_timeout(buffer, STALL_TIMEOUT).unwrap();
+            buffer = next;
+            if timeout.timed_out() && !(buffer.start..buffer.start + buffer.data.len() as u64).contains(&self.position) {
+                self.shared.health.0.store(true, Ordering::Relaxed);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Reading the file timed out"));
+            }
+        }
+    }
+}
+
+impl Seek for ReadAhead {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let position = match to {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the file"))?;
+        Ok(self.position)
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        self.shared.buffer.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// The reading thread: fills the buffer up to `READ_AHEAD` past where the
+/// decoder is, reopening the file whenever a read fails.
+fn fetch(shared: Arc<Shared>, file: fs::File, file_path: PathBuf) {
+    let mut file = Some(file);
+    let mut chunk = vec![0u8; CHUNK];
+    loop {
+        let (generation, offset) = {
+            let mut buffer = shared.buffer.lock().unwrap();
+            while !buffer.closed && (buffer.at_end || buffer.data.len() >= READ_AHEAD + READ_BEHIND) {
+                buffer = shared.changed.wait(buffer).unwrap();
+            }
+            if buffer.closed {
+                return;
+            }
+            (buffer.generation, buffer.start + buffer.data.len() as u64)
+        };
+        let read = match file.as_mut() {
+            Some(open) => open.seek(SeekFrom::Start(offset)).and_then(|_| open.read(&mut chunk)),
+            None => fs::File::open(&file_path).and_then(|mut reopened| {
+                tracing::info!(path = %file_path.display(), "The file can be read again");
+                let read = reopened.seek(SeekFrom::Start(offset)).and_then(|_| reopened.read(&mut chunk));
+                file = Some(reopened);
+                read
+            }),
+        };
+        let mut buffer = shared.buffer.lock().unwrap();
+        match read {
+            // The decoder jumped elsewhere while this was being read
+            _ if buffer.generation != generation => {}
+            Ok(0) => buffer.at_end = true,
+            Ok(count) => {
+                buffer.data.extend(&chunk[..count]);
+                buffer.failing = false;
+            }
+            Err(e) => {
+                if !buffer.failing {
+                    tracing::warn!(path = %file_path.display(), "Failed to read, retrying: {}", e);
+                }
+                buffer.failing = true;
+                file = None;
+                shared.changed.notify_all();
+                let (buffer, _) = shared.changed.wait_timeout(buffer, RETRY_INTERVAL).unwrap();
+                if buffer.closed {
+                    return;
+                }
+                continue;
+            }
+        }
+        shared.changed.notify_all();
+    }
+}
+
+/// Whether the folder `file_path` is in can't be read at all, as when a network
+/// share has dropped or a drive's been unplugged, rather than just the file
+/// being missing or broken.
+pub fn location_unreachable(file_path: &Path) -> bool {
+    file_path.parent().is_some_and(|folder| fs::read_dir(folder).is_err())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_seeks_like_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.flac");
+        let contents: Vec<u8> = (0..READ_BEHIND * 3).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &contents).unwrap();
+
+        let mut reader = ReadAhead::open(&path).unwrap();
+        let mut start = vec![0u8; 1000];
+        reader.read_exact(&mut start).unwrap();
+        assert_eq!(start, contents[..1000]);
+        // Well past what's been read, then back before what's still kept
+        reader.seek(SeekFrom::End(-100)).unwrap();
+        let mut end = Vec::new();
+        reader.read_to_end(&mut end).unwrap();
+        assert_eq!(end, contents[contents.len() - 100..]);
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, contents[10..]);
+        assert!(!reader.health().gave_out());
+
+        assert!(!location_unreachable(&path));
+        assert!(location_unreachable(&dir.path().join("unmounted").join("track.flac")));
+    }
+}
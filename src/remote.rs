@@ -0,0 +1,186 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7700";
+
+const HELP: &str = "commands: status, list, queue, play <index|path>, enqueue <index|path>, next, pause, resume, stop, \
+                    seek <seconds>, volume <0-1>, clear, rescan, help";
+
+/// A command sent over the remote control socket, one per line. Replies are a
+/// single line too: `ok`, `error: <why>` or a line of JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteCommand {
+    Status,
+    List,
+    Queue,
+    Play(TrackRef),
+    Enqueue(TrackRef),
+    Next,
+    Pause,
+    Resume,
+    Stop,
+    Seek(Duration),
+    Volume(f32),
+    ClearQueue,
+    Rescan,
+}
+
+/// A track given either by its index in `list` or by path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackRef {
+    Index(usize),
+    Path(PathBuf),
+}
+
+impl RemoteCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (line, None),
+        };
+        let track = || match argument {
+            Some(argument) => Ok(argument.parse().map(TrackRef::Index).unwrap_or_else(|_| TrackRef::Path(PathBuf::from(argument)))),
+            None => Err(format!("{} needs a track index or path", name)),
+        };
+        let command = match name.to_ascii_lowercase().as_str() {
+            "status" => Self::Status,
+            "list" => Self::List,
+            "queue" => Self::Queue,
+            "play" => Self::Play(track()?),
+            "enqueue" => Self::Enqueue(track()?),
+            "next" => Self::Next,
+            "pause" => Self::Pause,
+            "resume" => Self::Resume,
+            "stop" => Self::Stop,
+            "seek" => {
+                // Negative, infinite and far too long are all refused here
+                let position = argument.and_then(|seconds| seconds.parse::<f64>().ok()).and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+                Self::Seek(position.ok_or("seek needs a number of seconds")?)
+            }
+            "volume" => {
+                let volume = argument.and_then(|volume| volume.parse::<f32>().ok()).filter(|volume| (0.0..=1.0).contains(volume));
+                Self::Volume(volume.ok_or("volume needs a number from 0 to 1")?)
+            }
+            "clear" => Self::ClearQueue,
+            "rescan" => Self::Rescan,
+            _ => return Err(format!("unknown command {:?}, try help", name)),
+        };
+        Ok(command)
+    }
+}
+
+/// A parsed command and where to send the reply.
+#[derive(Debug)]
+pub struct Request {
+    pub command: RemoteCommand,
+    pub reply: mpsc::Sender<String>,
+}
+
+/// Accepts connections on `listener` in the background, passing each command on
+/// to `requests` and writing back whatever reply comes out the other end.
+pub fn serve<T: From<Request> + Send + 'static>(listener: TcpListener, requests: mpsc::Sender<T>) {
+    thread::Builder::new()
+        .name("remote".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        tracing::warn!("Failed to accept a remote connection: {}", e);
+                        continue;
+                    }
+                };
+                let requests = requests.clone();
+                let spawned = thread::Builder::new().name("remote-client".to_string()).spawn(move || {
+                    let peer = stream.peer_addr().map(|peer| peer.to_string()).unwrap_or_default();
+                    tracing::info!(%peer, "Remote connected");
+                    if let Err(e) = handle_client(stream, &requests) {
+                        tracing::debug!(%peer, "Remote connection ended: {}", e);
+                    }
+                    tracing::info!(%peer, "Remote disconnected");
+                });
+                if let Err(e) = spawned {
+                    tracing::warn!("Failed to start a remote connection thread: {}", e);
+                }
+            }
+        })
+        .expect("failed to spawn the remote thread");
+}
+
+fn handle_client<T: From<Request>>(stream: TcpStream, requests: &mpsc::Sender<T>) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = if line.trim().eq_ignore_ascii_case("help") {
+            HELP.to_string()
+        } else {
+            match RemoteCommand::parse(&line) {
+                Ok(command) => {
+                    let (reply, replies) = mpsc::channel();
+                    if requests.send(Request { command, reply }.into()).is_err() {
+                        return Ok(());
+                    }
+                    replies.recv().unwrap_or_else(|_| "error: no reply".to_string())
+                }
+                Err(e) => format!("error: {}", e),
+            }
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(RemoteCommand::parse("status\n"), Ok(RemoteCommand::Status));
+        assert_eq!(RemoteCommand::parse("  PAUSE "), Ok(RemoteCommand::Pause));
+        assert_eq!(RemoteCommand::parse("play 12"), Ok(RemoteCommand::Play(TrackRef::Index(12))));
+        assert_eq!(
+            RemoteCommand::parse("enqueue /music/Some Band/01 Song.mp3"),
+            Ok(RemoteCommand::Enqueue(TrackRef::Path(PathBuf::from("/music/Some Band/01 Song.mp3"))))
+        );
+        assert_eq!(RemoteCommand::parse("seek 90.5"), Ok(RemoteCommand::Seek(Duration::from_millis(90500))));
+        assert_eq!(RemoteCommand::parse("volume 0.4"), Ok(RemoteCommand::Volume(0.4)));
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert!(RemoteCommand::parse("play").is_err());
+        assert!(RemoteCommand::parse("seek -3").is_err());
+        assert!(RemoteCommand::parse("seek 1e300").is_err());
+        assert!(RemoteCommand::parse("seek inf").is_err());
+        assert!(RemoteCommand::parse("volume 11").is_err());
+        assert!(RemoteCommand::parse("shuffle").unwrap_err().contains("unknown command"));
+    }
+
+    #[test]
+    fn replies_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (requests, received) = mpsc::channel::<Request>();
+        serve(listener, requests);
+        thread::spawn(move || {
+            for request in received {
+                let _ = request.reply.send(format!("{:?}", request.command));
+            }
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"stop\nfrobnicate\n").unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "Stop");
+        assert!(lines.next().unwrap().unwrap().starts_with("error: unknown command"));
+    }
+}
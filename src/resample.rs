@@ -0,0 +1,249 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use crate::effects::BoxedSource;
+
+/// How tracks are converted when their sample rate differs from the device's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResampleQuality {
+    /// rodio's own linear interpolation. Cheapest, but dulls the top end and lets
+    /// some aliasing through.
+    Fast,
+    #[default]
+    Good,
+    Best,
+}
+
+impl ResampleQuality {
+    pub const ALL: [ResampleQuality; 3] = [ResampleQuality::Fast, ResampleQuality::Good, ResampleQuality::Best];
+
+    /// Input frames looked at either side of each output frame, and how close to
+    /// the lower Nyquist frequency the filter reaches.
+    fn sinc(self) -> Option<(usize, f64)> {
+        match self {
+            ResampleQuality::Fast => None,
+            ResampleQuality::Good => Some((16, 0.92)),
+            ResampleQuality::Best => Some((64, 0.97)),
+        }
+    }
+}
+
+impl std::fmt::Display for ResampleQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ResampleQuality::Fast => "Fast",
+            ResampleQuality::Good => "Good",
+            ResampleQuality::Best => "Best",
+        })
+    }
+}
+
+/// Converts `source` to `sample_rate` at `quality`. Left alone when the rates
+/// already match or the device rate isn't known, and for `Fast`, in which case
+/// rodio converts it on the way out.
+pub fn resample(source: BoxedSource, sample_rate: Option<u32>, quality: ResampleQuality) -> BoxedSource {
+    match (sample_rate, quality.sinc()) {
+        (Some(to), Some((half_taps, bandwidth))) if to != source.sample_rate() && source.channels() > 0 => {
+            Box::new(Sinc::new(source, to, half_taps, bandwidth))
+        }
+        _ => source,
+    }
+}
+
+/// Band-limited interpolation with a Blackman-windowed sinc kernel, computed
+/// directly for each output frame.
+struct Sinc {
+    inner: BoxedSource,
+    channels: usize,
+    from: u64,
+    to: u32,
+    half_taps: usize,
+    cutoff: f64, // As a fraction of the input Nyquist frequency
+    history: VecDeque<f32>, // Interleaved input frames, starting at frame `first`
+    first: u64,
+    loaded: u64, // Input frames read so far
+    exhausted: bool,
+    output_frame: u64,
+    frame: Vec<f32>,
+    index: usize, // Next sample of `frame` to hand out
+    weights: Vec<f64>,
+}
+
+impl Sinc {
+    fn new(inner: BoxedSource, to: u32, half_taps: usize, bandwidth: f64) -> Self {
+        let from = inner.sample_rate();
+        Self {
+            channels: inner.channels() as usize,
+            from: from as u64,
+            to,
+            half_taps,
+            cutoff: bandwidth * (to as f64 / from as f64).min(1.0),
+            history: VecDeque::new(),
+            first: 0,
+            loaded: 0,
+            exhausted: false,
+            output_frame: 0,
+            frame: Vec::new(),
+            index: 0,
+            weights: Vec::with_capacity(half_taps * 2),
+            inner,
+        }
+    }
+
+    fn kernel(&self, x: f64) -> f64 {
+        let position = x / self.half_taps as f64;
+        if position.abs() >= 1.0 {
+            return 0.0;
+        }
+        let window = 0.42 + 0.5 * (PI * position).cos() + 0.08 * (2.0 * PI * position).cos();
+        let sinc = if x == 0.0 { 1.0 } else { (PI * self.cutoff * x).sin() / (PI * self.cutoff * x) };
+        self.cutoff * sinc * window
+    }
+
+    /// Reads input until frame `last` is buffered or the source runs dry.
+    fn load_until(&mut self, last: u64) {
+        while !self.exhausted && self.loaded <= last {
+            let before = self.history.len();
+            self.history.extend(self.inner.by_ref().take(self.channels));
+            if self.history.len() - before < self.channels {
+                // Drop a partial frame rather than misalign the channels
+                self.history.truncate(before);
+                self.exhausted = true;
+            } else {
+                self.loaded += 1;
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> bool {
+        let position = self.output_frame * self.from;
+        let center = position / self.to as u64;
+        let fraction = (position % self.to as u64) as f64 / self.to as f64;
+        let half_taps = self.half_taps as u64;
+        self.load_until(center + half_taps);
+        if self.exhausted && center >= self.loaded {
+            return false;
+        }
+        // Forget frames too old to contribute again
+        let keep_from = (center + 1).saturating_sub(half_taps);
+        while self.first < keep_from && !self.history.is_empty() {
+            self.history.drain(..self.channels);
+            self.first += 1;
+        }
+
+        let start = (center + 1).saturating_sub(half_taps);
+        let end = (center + half_taps).min(self.loaded.saturating_sub(1));
+        self.weights.clear();
+        for input_frame in start..=end {
+            let x = (center as f64 + fraction) - input_frame as f64;
+            self.weights.push(self.kernel(x));
+        }
+        self.frame.clear();
+        for channel in 0..self.channels {
+            let mut sum = 0.0;
+            for (offset, weight) in self.weights.iter().enumerate() {
+                let frame = (start - self.first) as usize + offset;
+                sum += self.history[frame * self.channels + channel] as f64 * weight;
+            }
+            self.frame.push(sum as f32);
+        }
+        self.index = 0;
+        self.output_frame += 1;
+        true
+    }
+}
+
+impl Iterator for Sinc {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.index >= self.frame.len() && !self.next_frame() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.frame[self.index - 1])
+    }
+}
+
+impl Source for Sinc {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.to
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)?;
+        // Start over as though the track began here
+        self.history.clear();
+        self.frame.clear();
+        self.index = 0;
+        self.first = 0;
+        self.loaded = 0;
+        self.output_frame = 0;
+        self.exhausted = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// `seconds` of a stereo sine wave at `frequency`.
+    fn sine(frequency: f64, sample_rate: u32, seconds: f64) -> BoxedSource {
+        let frames = (sample_rate as f64 * seconds) as usize;
+        let samples = (0..frames)
+            .flat_map(|frame| {
+                let sample = (2.0 * PI * frequency * frame as f64 / sample_rate as f64).sin() as f32 * 0.5;
+                [sample, -sample]
+            })
+            .collect::<Vec<_>>();
+        Box::new(SamplesBuffer::new(2, sample_rate, samples))
+    }
+
+    #[test]
+    fn converts_the_rate_without_changing_the_sound() {
+        let output = resample(sine(1000.0, 44100, 1.0), Some(48000), ResampleQuality::Good);
+        assert_eq!((output.sample_rate(), output.channels()), (48000, 2));
+        let samples: Vec<f32> = output.collect();
+        assert_eq!(samples.len(), 48000 * 2);
+
+        // Away from the edges, every frame should match the sine at the new rate
+        for frame in 100..47900 {
+            let expected = (2.0 * PI * 1000.0 * frame as f64 / 48000.0).sin() as f32 * 0.5;
+            assert!((samples[frame * 2] - expected).abs() < 0.01, "frame {}: {} vs {}", frame, samples[frame * 2], expected);
+            assert_eq!(samples[frame * 2 + 1], -samples[frame * 2]);
+        }
+    }
+
+    #[test]
+    fn leaves_matching_rates_and_fast_to_rodio() {
+        assert_eq!(resample(sine(440.0, 48000, 0.1), Some(48000), ResampleQuality::Best).sample_rate(), 48000);
+        assert_eq!(resample(sine(440.0, 44100, 0.1), Some(48000), ResampleQuality::Fast).sample_rate(), 44100);
+        assert_eq!(resample(sine(440.0, 44100, 0.1), None, ResampleQuality::Best).sample_rate(), 44100);
+    }
+
+    #[test]
+    fn downsampling_filters_out_what_no_longer_fits() {
+        // 30 kHz can't be represented at 44.1 kHz, so it should all but vanish
+        // rather than fold back down as an audible tone
+        let samples: Vec<f32> = resample(sine(30000.0, 96000, 0.5), Some(44100), ResampleQuality::Best).collect();
+        let peak = samples[400..samples.len() - 400].iter().fold(0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak < 0.01, "peak {}", peak);
+    }
+}
@@ -0,0 +1,451 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::autodj::AutoDjRule;
+use crate::columns::{complete_columns, default_columns, ColumnSlot};
+use crate::convert::{Format, Quality};
+use crate::effects::{complete_chain, default_chain, EffectSlot};
+use crate::hooks::HookEvent;
+use crate::project_dirs;
+
+use crate::queue::ShuffleMode;
+use crate::resample::ResampleQuality;
+use crate::ui::Tab;
+
+// Folders offered for switching back to
+const RECENT_FOLDERS: usize = 8;
+
+/// Everything the app remembers between launches. Missing keys fall back to
+/// their defaults, so older config files keep loading as settings are added.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub setup_complete: bool, // The first-run setup's been through or skipped
+    pub library_folders: Vec<PathBuf>,
+    pub recent_folders: Vec<PathBuf>, // Library folders picked before, most recent first
+    pub scan: ScanSettings,
+    pub theme: ThemeChoice,
+    pub volume: f32,
+    pub output_device: Option<String>, // None means the system default
+    pub bit_perfect: bool,
+    pub normalize: bool, // Level tracks by their ReplayGain tags, or by ear without them
+    pub sample_rate: Option<u32>, // None leaves the device at its default
+    pub resample_quality: ResampleQuality,
+    pub features: Features,
+    pub layout: Layout,
+    pub columns: Vec<ColumnSlot>, // Track list columns, left to right
+    pub convert: ConvertSettings,
+    pub auto_dj: AutoDjSettings,
+    pub shuffle: ShuffleMode,
+    pub effects: Vec<EffectSlot>, // In the order they're applied
+    pub silence: SilenceSettings,
+    pub audiobooks: AudiobookSettings,
+    pub hooks: HookSettings,
+    pub jellyfin: JellyfinSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            setup_complete: false,
+            library_folders: Vec::new(),
+            recent_folders: Vec::new(),
+            scan: ScanSettings::default(),
+            theme: ThemeChoice::default(),
+            volume: 1.0,
+            output_device: None,
+            bit_perfect: false,
+            normalize: false,
+            sample_rate: None,
+            resample_quality: ResampleQuality::default(),
+            features: Features::default(),
+            layout: Layout::default(),
+            columns: default_columns(),
+            convert: ConvertSettings::default(),
+            auto_dj: AutoDjSettings::default(),
+            shuffle: ShuffleMode::default(),
+            effects: default_chain(),
+            silence: SilenceSettings::default(),
+            audiobooks: AudiobookSettings::default(),
+            hooks: HookSettings::default(),
+            jellyfin: JellyfinSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThemeChoice {
+    #[default]
+    Light,
+    Dark,
+    HighContrast, // For low vision: stark colours, bigger controls, clear focus
+}
+
+impl ThemeChoice {
+    pub const ALL: [ThemeChoice; 3] = [ThemeChoice::Light, ThemeChoice::Dark, ThemeChoice::HighContrast];
+}
+
+impl std::fmt::Display for ThemeChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ThemeChoice::Light => "Light",
+            ThemeChoice::Dark => "Dark",
+            ThemeChoice::HighContrast => "High contrast",
+        })
+    }
+}
+
+/// Switches for optional behaviour.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Features {
+    pub metadata_cache: bool,
+    pub thumbnail_cache: bool,
+    pub spectrum: bool,
+    pub level_meters: bool,
+    pub follow_playing: bool, // Scroll the track list along as tracks change
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self { metadata_cache: true, thumbnail_cache: true, spectrum: true, level_meters: true, follow_playing: false }
+    }
+}
+
+/// Window geometry and pane arrangement from the last session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub window_size: (u32, u32),
+    pub window_position: Option<(i32, i32)>, // None lets the OS place the window
+    pub split_ratio: f32,                    // Share of the width given to the library pane
+    pub tab: Tab,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self { window_size: (800, 600), window_position: None, split_ratio: 0.5, tab: Tab::Library }
+    }
+}
+
+/// The choices made last time tracks were converted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConvertSettings {
+    pub format: Format,
+    pub quality: Quality,
+    pub destination: Option<PathBuf>,
+}
+
+/// How library folders are walked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanSettings {
+    pub follow_symlinks: bool,
+    pub max_depth: usize, // Folders below the library folder, which is depth 0
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        Self { follow_symlinks: false, max_depth: 32 }
+    }
+}
+
+/// Keeping the queue topped up with tracks that fit what's playing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoDjSettings {
+    pub enabled: bool,
+    pub rule: AutoDjRule,
+}
+
+/// Skipping long silences at either end of a track and in the middle of it,
+/// such as the gap before a hidden track.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SilenceSettings {
+    pub enabled: bool,
+    pub threshold_db: f32, // Anything quieter than this counts as silence
+    pub min_seconds: f32,  // Shorter silences are left alone
+}
+
+impl Default for SilenceSettings {
+    fn default() -> Self {
+        Self { enabled: false, threshold_db: -50.0, min_seconds: 2.0 }
+    }
+}
+
+/// Folders of audiobooks. Their files are listed in reading order, pick up where
+/// they were left, play a little faster, and stay out of Auto-DJ, the history and
+/// play counts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudiobookSettings {
+    pub folders: Vec<PathBuf>,
+    pub speed: f32, // 1 is normal speed
+}
+
+impl Default for AudiobookSettings {
+    fn default() -> Self {
+        Self { folders: Vec::new(), speed: 1.25 }
+    }
+}
+
+impl AudiobookSettings {
+    pub fn contains(&self, file_path: &Path) -> bool {
+        self.folders.iter().any(|folder| file_path.starts_with(folder))
+    }
+
+    pub fn speed_for(&self, file_path: &Path) -> f32 {
+        if self.contains(file_path) { self.speed } else { 1.0 }
+    }
+}
+
+/// Shell commands run on playback events, empty for none. See [`crate::hooks::run_hook`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HookSettings {
+    pub track_started: String,
+    pub track_finished: String,
+    pub paused: String,
+}
+
+impl HookSettings {
+    pub fn command(&self, event: HookEvent) -> &str {
+        match event {
+            HookEvent::TrackStarted => &self.track_started,
+            HookEvent::TrackFinished => &self.track_finished,
+            HookEvent::Paused => &self.paused,
+        }
+    }
+
+    pub fn set_command(&mut self, event: HookEvent, command: String) {
+        match event {
+            HookEvent::TrackStarted => self.track_started = command,
+            HookEvent::TrackFinished => self.track_finished = command,
+            HookEvent::Paused => self.paused = command,
+        }
+    }
+}
+
+/// The Jellyfin server signed in to. The password isn't kept, only the access
+/// token the server hands back for it, and that goes in the credentials file
+/// rather than with the rest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JellyfinSettings {
+    pub server: String,
+    pub username: String,
+    pub user_id: String, // Empty until signed in
+    #[serde(skip)]
+    pub token: String,
+    pub device_id: String, // Picked once, so the server sees the same device every time
+    pub transcode: bool,   // Ask for MP3 rather than the original file
+}
+
+impl JellyfinSettings {
+    pub fn signed_in(&self) -> bool {
+        !self.token.is_empty() && !self.user_id.is_empty()
+    }
+}
+
+impl Settings {
+    /// Puts `folder` at the top of the recent folders, forgetting the oldest past
+    /// `RECENT_FOLDERS`.
+    pub fn remember_folder(&mut self, folder: &Path) {
+        self.recent_folders.retain(|recent| recent != folder);
+        self.recent_folders.insert(0, folder.to_path_buf());
+        self.recent_folders.truncate(RECENT_FOLDERS);
+    }
+
+    /// Loads the settings file, falling back to defaults if it's missing or unreadable.
+    pub fn load() -> Self {
+        settings_path().map(|path| Self::load_from(&path)).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = settings_path()
+            && let Err(e) = self.save_to(&path)
+        {
+            tracing::warn!("Failed to save settings: {}", e);
+        }
+    }
+
+    fn load_from(path: &Path) -> Self {
+        let mut settings = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring invalid settings file {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        complete_chain(&mut settings.effects);
+        complete_columns(&mut settings.columns);
+        settings.jellyfin.token = Credentials::load_from(&credentials_path(path)).jellyfin_token;
+        settings
+    }
+
+    fn save_to(&self, path: &Path) -> std::io::Result<()> {
+        let contents = toml::to_string_pretty(self).map_err(std::io::Error::other)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+        Credentials { jellyfin_token: self.jellyfin.token.clone() }.save_to(&credentials_path(path))
+    }
+}
+
+/// Secrets kept out of the settings file, so they don't end up in backups or
+/// wherever else someone copies their settings.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Credentials {
+    jellyfin_token: String,
+}
+
+impl Credentials {
+    fn load_from(path: &Path) -> Self {
+        fs::read_to_string(path).ok().and_then(|contents| toml::from_str(&contents).ok()).unwrap_or_default()
+    }
+
+    /// Writes the file readable only by its owner, or removes it once there's nothing to keep.
+    fn save_to(&self, path: &Path) -> std::io::Result<()> {
+        if self.jellyfin_token.is_empty() {
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let contents = toml::to_string_pretty(self).map_err(std::io::Error::other)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(path)?, contents.as_bytes())
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.config_dir().join("settings.toml"))
+}
+
+fn credentials_path(settings_path: &Path) -> PathBuf {
+    settings_path.with_file_name("credentials.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::ListColumn;
+    use crate::effects::Effect;
+
+    #[test]
+    fn round_trips_through_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config").join("settings.toml");
+        let settings = Settings {
+            setup_complete: true,
+            library_folders: vec![PathBuf::from("/music"), PathBuf::from("/podcasts")],
+            recent_folders: vec![PathBuf::from("/music"), PathBuf::from("/old music")],
+            scan: ScanSettings { follow_symlinks: true, max_depth: 8 },
+            theme: ThemeChoice::Dark,
+            volume: 0.5,
+            output_device: Some("USB DAC".to_string()),
+            bit_perfect: true,
+            normalize: true,
+            sample_rate: Some(96000),
+            resample_quality: ResampleQuality::Best,
+            features: Features { metadata_cache: false, thumbnail_cache: true, spectrum: false, level_meters: true, follow_playing: true },
+            layout: Layout { window_size: (1280, 720), window_position: Some((-1200, 40)), split_ratio: 0.65, tab: Tab::Albums },
+            columns: {
+                let mut columns = vec![
+                    ColumnSlot { column: ListColumn::Composer, shown: true },
+                    ColumnSlot { column: ListColumn::Title, shown: true },
+                    ColumnSlot { column: ListColumn::Bitrate, shown: true },
+                ];
+                complete_columns(&mut columns);
+                columns
+            },
+            convert: ConvertSettings { format: Format::Opus, quality: Quality::Small, destination: Some(PathBuf::from("/phone")) },
+            auto_dj: AutoDjSettings { enabled: true, rule: AutoDjRule::SameDecade },
+            shuffle: ShuffleMode::Albums,
+            effects: vec![
+                EffectSlot { effect: Effect::Mono, enabled: true },
+                EffectSlot { effect: Effect::Preamp { gain_db: -2.5 }, enabled: false },
+                EffectSlot { effect: Effect::HighPass { cutoff_hz: 60 }, enabled: true },
+                EffectSlot { effect: Effect::LowPass { cutoff_hz: 12000 }, enabled: false },
+                EffectSlot { effect: Effect::Karaoke { strength: 70 }, enabled: true },
+                EffectSlot { effect: Effect::Crossfeed { level_db: 9.5 }, enabled: true },
+            ],
+            silence: SilenceSettings { enabled: true, threshold_db: -60.0, min_seconds: 4.0 },
+            audiobooks: AudiobookSettings { folders: vec![PathBuf::from("/music/Audiobooks")], speed: 1.5 },
+            hooks: HookSettings { track_started: "notify-send {title} {artist}".to_string(), paused: "hass-pause".to_string(), ..Default::default() },
+            jellyfin: JellyfinSettings {
+                server: "http://media.local:8096".to_string(),
+                username: "moazie".to_string(),
+                user_id: "5d1c".to_string(),
+                token: "b2f0".to_string(),
+                device_id: "9e3a".to_string(),
+                transcode: true,
+            },
+        };
+
+        settings.save_to(&path).unwrap();
+        assert_eq!(Settings::load_from(&path), settings);
+    }
+
+    #[test]
+    fn token_is_kept_out_of_the_settings_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        let mut settings = Settings::default();
+        settings.jellyfin.token = "b2f0".to_string();
+
+        settings.save_to(&path).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("b2f0"));
+        assert_eq!(Settings::load_from(&path).jellyfin.token, "b2f0");
+
+        // Signing out forgets it
+        settings.jellyfin.token.clear();
+        settings.save_to(&path).unwrap();
+        assert!(!credentials_path(&path).exists());
+        assert_eq!(Settings::load_from(&path).jellyfin.token, "");
+    }
+
+    #[test]
+    fn recent_folders_are_most_recent_first() {
+        let mut settings = Settings::default();
+        for number in 0..RECENT_FOLDERS + 2 {
+            settings.remember_folder(Path::new(&format!("/folder{}", number)));
+        }
+        settings.remember_folder(Path::new("/folder5"));
+
+        assert_eq!(settings.recent_folders.len(), RECENT_FOLDERS);
+        assert_eq!(settings.recent_folders[..3], [PathBuf::from("/folder5"), PathBuf::from("/folder9"), PathBuf::from("/folder8")]);
+        assert_eq!(settings.recent_folders.last(), Some(&PathBuf::from("/folder2")));
+    }
+
+    #[test]
+    fn missing_keys_use_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(&path, "volume = 0.25\n").unwrap();
+
+        let settings = Settings::load_from(&path);
+        assert_eq!(settings.volume, 0.25);
+        assert_eq!(settings.theme, ThemeChoice::Light);
+        assert!(settings.features.metadata_cache);
+        assert_eq!(settings.layout, Layout::default());
+    }
+
+    #[test]
+    fn missing_or_invalid_file_uses_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(Settings::load_from(&dir.path().join("nope.toml")), Settings::default());
+
+        let path = dir.path().join("settings.toml");
+        fs::write(&path, "volume = \"loud\"").unwrap();
+        assert_eq!(Settings::load_from(&path), Settings::default());
+    }
+}
@@ -0,0 +1,79 @@
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::player::db_to_gain;
+use crate::settings::SilenceSettings;
+use crate::waveform::{chunk_peaks, CHUNK_DURATION};
+
+// A moment of each silence is kept so tracks don't start or stop abruptly
+const KEEP: Duration = Duration::from_millis(250);
+
+/// Stretches of `file_path` quiet enough and long enough to skip under `settings`.
+/// A range ending at `Duration::MAX` runs to the end of the track. Decodes the
+/// whole file, so run it off the audio thread.
+#[tracing::instrument(level = "debug", skip(settings), fields(path = %file_path.display()))]
+pub fn find_silences(file_path: &Path, settings: &SilenceSettings) -> Result<Vec<Range<Duration>>, String> {
+    Ok(silences_in(&chunk_peaks(file_path)?, settings))
+}
+
+fn silences_in(chunks: &[f32], settings: &SilenceSettings) -> Vec<Range<Duration>> {
+    let threshold = db_to_gain(settings.threshold_db);
+    let min_chunks = (settings.min_seconds / CHUNK_DURATION.as_secs_f32()).ceil() as usize;
+    let at = |chunk: usize| CHUNK_DURATION * chunk as u32;
+
+    let mut silences = Vec::new();
+    let mut quiet_since = None;
+    // The infinite peak at the end closes a silence that runs to the end of the track
+    for (index, &peak) in chunks.iter().chain([f32::INFINITY].iter()).enumerate() {
+        if peak < threshold {
+            quiet_since.get_or_insert(index);
+            continue;
+        }
+        let Some(start) = quiet_since.take() else { continue };
+        if index - start < min_chunks.max(1) {
+            continue;
+        }
+        let from = if start == 0 { Duration::ZERO } else { at(start) + KEEP };
+        let to = if index == chunks.len() { Duration::MAX } else { at(index).saturating_sub(KEEP) };
+        if from < to {
+            silences.push(from..to);
+        }
+    }
+    silences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> SilenceSettings {
+        SilenceSettings { enabled: true, threshold_db: -60.0, min_seconds: 2.0 }
+    }
+
+    /// Peaks for `seconds` of audio at `level`.
+    fn stretch(seconds: f32, level: f32) -> Vec<f32> {
+        vec![level; (seconds / CHUNK_DURATION.as_secs_f32()).round() as usize]
+    }
+
+    #[test]
+    fn finds_leading_trailing_and_hidden_track_gaps() {
+        let chunks = [stretch(3.0, 0.0), stretch(60.0, 0.5), stretch(1.0, 0.0), stretch(60.0, 0.5), stretch(120.0, 0.0001), stretch(30.0, 0.5), stretch(5.0, 0.0)].concat();
+
+        let silences = silences_in(&chunks, &settings());
+        let seconds = |seconds: f32| Duration::from_secs_f32(seconds);
+        assert_eq!(silences.len(), 3);
+        // Leading silence is skipped right up to the music, less the bit that's kept
+        assert_eq!(silences[0], Duration::ZERO..seconds(2.75));
+        // The one second pause is too short to bother with; the hidden track gap isn't
+        assert_eq!(silences[1], seconds(124.25)..seconds(243.75));
+        assert_eq!(silences[2], seconds(274.25)..Duration::MAX);
+    }
+
+    #[test]
+    fn quiet_music_is_left_alone() {
+        let chunks = [stretch(10.0, 0.01), stretch(10.0, 0.5)].concat();
+        assert!(silences_in(&chunks, &settings()).is_empty());
+        assert!(silences_in(&[], &settings()).is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use iced::widget::canvas::{self, Cursor, Frame, Geometry};
+use iced::{Point, Rectangle, Size, Theme};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+use crate::player::TapBuffer;
+
+pub const BAR_COUNT: usize = 32;
+const FFT_SIZE: usize = 2048;
+// Bands are spaced logarithmically across this range, roughly how we hear pitch
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16_000.0;
+// Anything quieter than this draws as an empty bar
+const FLOOR_DB: f32 = -70.0;
+// How far a bar can fall per frame, so peaks linger long enough to see
+const DECAY: f32 = 0.04;
+
+/// Turns recent output samples into bar heights between 0 and 1.
+pub struct Analyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    bars: [f32; BAR_COUNT],
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        // Hann window to keep energy from leaking into neighbouring bins
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+        Self { fft, window, bars: [0.0; BAR_COUNT] }
+    }
+
+    pub fn bars(&self) -> &[f32] {
+        &self.bars
+    }
+
+    pub fn reset(&mut self) {
+        self.bars = [0.0; BAR_COUNT];
+    }
+
+    pub fn update(&mut self, buffer: &TapBuffer) {
+        let levels = self.analyze(buffer).unwrap_or([0.0; BAR_COUNT]);
+        for (bar, level) in self.bars.iter_mut().zip(levels) {
+            *bar = level.max(*bar - DECAY);
+        }
+    }
+
+    /// Levels for the latest `FFT_SIZE` frames, or `None` if there isn't that much audio yet.
+    fn analyze(&self, buffer: &TapBuffer) -> Option<[f32; BAR_COUNT]> {
+        let channels = buffer.channels.max(1) as usize;
+        let frames = buffer.samples.len() / channels;
+        if frames < FFT_SIZE || buffer.sample_rate == 0 {
+            return None;
+        }
+
+        // Mix down to mono
+        let start = (frames - FFT_SIZE) * channels;
+        let mut spectrum: Vec<Complex<f32>> = buffer
+            .samples
+            .range(start..)
+            .collect::<Vec<_>>()
+            .chunks(channels)
+            .zip(&self.window)
+            .map(|(frame, window)| Complex::new(frame.iter().copied().sum::<f32>() / channels as f32 * window, 0.0))
+            .collect();
+        self.fft.process(&mut spectrum);
+
+        // A full-scale sine comes out at FFT_SIZE / 4 once the window is applied
+        let scale = 4.0 / FFT_SIZE as f32;
+        let bin_width = buffer.sample_rate as f32 / FFT_SIZE as f32;
+        let mut levels = [0.0; BAR_COUNT];
+        for (i, level) in levels.iter_mut().enumerate() {
+            let low = band_edge(i) / bin_width;
+            let high = band_edge(i + 1) / bin_width;
+            let first = (low.floor() as usize).max(1);
+            let last = (high.ceil() as usize).clamp(first + 1, FFT_SIZE / 2);
+            let peak = spectrum[first..last].iter().map(|bin| bin.norm() * scale).fold(0.0, f32::max);
+            let db = 20.0 * peak.max(f32::EPSILON).log10();
+            *level = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+        }
+        Some(levels)
+    }
+}
+
+fn band_edge(band: usize) -> f32 {
+    MIN_FREQUENCY * (MAX_FREQUENCY / MIN_FREQUENCY).powf(band as f32 / BAR_COUNT as f32)
+}
+
+/// Draws spectrum bars along the bottom of the canvas.
+pub struct SpectrumBars<'a> {
+    pub bars: &'a [f32],
+}
+
+impl<Message> canvas::Program<Message> for SpectrumBars<'_> {
+    type State = ();
+
+    fn draw(&self, _state: &(), theme: &Theme, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let color = theme.extended_palette().primary.base.color;
+        let slot = bounds.width / self.bars.len().max(1) as f32;
+        for (i, level) in self.bars.iter().enumerate() {
+            let height = level * bounds.height;
+            frame.fill_rectangle(
+                Point::new(i as f32 * slot + 1.0, bounds.height - height),
+                Size::new((slot - 2.0).max(1.0), height),
+                color,
+            );
+        }
+        vec![frame.into_geometry()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: u32, channels: u16) -> TapBuffer {
+        let samples = (0..FFT_SIZE)
+            .flat_map(|i| {
+                let value = (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin();
+                std::iter::repeat_n(value, channels as usize)
+            })
+            .collect();
+        TapBuffer { channels, sample_rate, samples, ..Default::default() }
+    }
+
+    fn band_of(frequency: f32) -> usize {
+        (0..BAR_COUNT).find(|&band| band_edge(band + 1) > frequency).unwrap()
+    }
+
+    #[test]
+    fn sine_peaks_in_its_band() {
+        let analyzer = Analyzer::new();
+        let levels = analyzer.analyze(&sine(1000.0, 44100, 2)).unwrap();
+
+        let loudest = levels.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0;
+        assert_eq!(loudest, band_of(1000.0));
+        assert!(levels[loudest] > 0.9);
+        assert!(levels[0] < 0.5);
+    }
+
+    #[test]
+    fn not_enough_audio_yet() {
+        let analyzer = Analyzer::new();
+        let mut buffer = sine(1000.0, 44100, 1);
+        buffer.samples.truncate(FFT_SIZE / 2);
+        assert_eq!(analyzer.analyze(&buffer), None);
+    }
+
+    #[test]
+    fn bars_fall_gradually() {
+        let mut analyzer = Analyzer::new();
+        analyzer.update(&sine(1000.0, 44100, 1));
+        let peak = analyzer.bars()[band_of(1000.0)];
+
+        analyzer.update(&TapBuffer::default());
+        assert_eq!(analyzer.bars()[band_of(1000.0)], peak - DECAY);
+    }
+}
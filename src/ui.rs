@@ -0,0 +1,4155 @@
+use async_io::Timer;
+use chrono::{DateTime, Local};
+use iced::futures::channel::mpsc as async_mpsc;
+use iced::futures::StreamExt;
+use iced::keyboard::{self, Modifiers};
+use iced::widget::scrollable::RelativeOffset;
+use iced::widget::pane_grid::{self, PaneGrid};
+use iced::widget::{button, canvas, checkbox, image, mouse_area, pick_list, progress_bar, radio, scrollable, slider, text_input, tooltip, Column, Container, Row, Space, Text};
+use iced::{alignment, event, theme, window, Alignment, Application, Color, Command, ContentFit, Element, Event, Length, Subscription, Theme};
+use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::albums::{disc_of, discs, group_albums, group_artists, group_composers, Album, AlbumKey, Person};
+use crate::autodj::{pick_tracks, AutoDjRule, QUEUE_TARGET};
+use crate::backup::{read_backup, write_backup, Backup};
+use crate::chapters::{chapter_at, read_chapters, Chapter};
+use crate::collate::natural_cmp;
+use crate::columns::{format_duration, work_and_movement, ListColumn};
+use crate::contrast::{self, high_contrast_theme};
+use crate::convert::{ConvertEvent, ConvertJob, Converter, Format, Quality};
+use crate::database::{passed_save_point, Bookmark, LibraryDb, QueueSnapshot};
+use crate::effects::Leveling;
+use crate::export::export_library;
+use crate::hooks::{run_hook, HookEvent};
+use crate::jellyfin::{self, Collection, Playback, RemoteItem, RemoteTrack, Session};
+use crate::import::{import_library, Import};
+use crate::journal::{restore_from_trash, Change, Journal, Removal};
+use crate::library::{is_supported_audio_file, matches_search, order_audiobooks, scan_folders, AUDIO_EXTENSIONS};
+use crate::logging::{latest_log_file, log_dir};
+use crate::meters::Meters;
+use crate::metadata::{album_art_thumbnail, extract_metadata, load_cover, load_track_info, Cover, TrackInfo};
+use crate::player::{output_devices, AudioCommand, AudioEngine, AudioEvent};
+use crate::plugins::{plugins_dir, Plugins};
+use crate::properties::{read_properties, Properties};
+use crate::queue::{shuffle, PlayQueue, ShuffleMode};
+use crate::resample::ResampleQuality;
+use crate::settings::{JellyfinSettings, Settings, ThemeChoice};
+use crate::spectrum::{Analyzer, SpectrumBars};
+use crate::waveform::{compute_peaks, WaveformBar, PEAK_COUNT};
+
+// How long a notification stays up unless it's dismissed
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+const ERROR_COLOR: Color = Color::from_rgb(0.85, 0.2, 0.2);
+const WARNING_COLOR: Color = Color::from_rgb(0.9, 0.6, 0.0);
+
+// Don't let either pane be squeezed out of sight
+const MIN_SPLIT: f32 = 0.15;
+const MAX_SPLIT: f32 = 0.85;
+
+// Every track row has the same height so the list can work out which rows are on screen
+const TRACK_ROW_HEIGHT: f32 = 32.0;
+
+// The History tab only builds rows for this many of the latest plays
+const HISTORY_SHOWN: usize = 200;
+
+// How far Page Up and Page Down move through the track list
+const PAGE_ROWS: isize = 10;
+
+// Range of the per-track gain slider, in dB. Boosting much past this just clips.
+const MIN_TRACK_GAIN: f32 = -12.0;
+const MAX_TRACK_GAIN: f32 = 6.0;
+const MIN_AUDIOBOOK_SPEED: f32 = 0.75;
+const MAX_AUDIOBOOK_SPEED: f32 = 2.0;
+
+// Short enough to type at a party, long enough not to guess by accident
+const MIN_PIN_LENGTH: usize = 4;
+
+// How far the album art viewer zooms, and by how much each step
+const MIN_COVER_ZOOM: f32 = 0.1;
+const MAX_COVER_ZOOM: f32 = 8.0;
+const COVER_ZOOM_STEP: f32 = 1.25;
+
+// Roughly 30 fps is smooth enough for the visualisations without keeping the CPU busy
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+// Tags are read this many files at a time, so a big library's scan shows progress
+const TAG_BATCH: usize = 500;
+
+// How often Jellyfin hears how far through one of its tracks playback is, as its own clients do
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct MusicJester {
+    settings: Settings,
+    db: LibraryDb,
+    panes: pane_grid::State<PaneKind>, // Tabs on the left, now playing on the right
+    fullscreen: bool,                  // Distraction-free now playing view
+    cover: Option<CoverViewer>,        // Album art at full size, over everything else
+    properties: Option<PropertiesDialog>,
+    selected_folder: String,
+    audio_files: Vec<PathBuf>,
+    tab: Tab,
+    track_list: TrackList, // Which tracks the list shows
+    albums: Vec<Album>,
+    artists: Vec<Person>,
+    composers: Vec<Person>,
+    search: String,
+    search_results: Option<Vec<PathBuf>>, // The listed tracks matching `search`, unless it's empty
+    track_info: HashMap<PathBuf, TrackInfo>, // Tags and durations for the list
+    track_errors: HashMap<PathBuf, String>,  // Why a track couldn't be read or played
+    toasts: Vec<Toast>,
+    next_toast_id: u64,
+    scan_status: String,
+    pending_tags: Vec<PathBuf>, // Scanned files still to read the tags of
+    tags_total: usize,          // How many files the scan found, for showing progress
+    setup: Option<SetupStep>,   // The first-run setup, while it's on screen
+    audio: Option<AudioEngine>, // Handle to the audio thread, set once its subscription starts
+    album_art: Option<Vec<u8>>, // Store album art
+    song_title: Option<String>, // Store song title
+    artist: Option<String>,     // Store artist
+    now_playing: Option<PathBuf>,
+    starting: Option<PathBuf>, // Asked to play, until the audio thread says it has
+    between_tracks: bool,      // The last track played out and the next's been asked for, so nothing can be heard
+    queue: PlayQueue,
+    paused: bool,
+    position: Duration,
+    track_duration: Option<Duration>,
+    seek_preview: Option<f32>, // Seek bar value while it's being dragged
+    waveform: Option<Vec<f32>>, // Peaks of the playing track once they've been worked out
+    chapters: Vec<Chapter>,     // Of the playing track, if it has any
+    preloaded: Option<PathBuf>, // The track the audio thread was last asked to get ready
+    lyrics: Option<String>,     // Of the playing track, from a plugin
+    plugins: Plugins,
+    output_devices: Vec<OutputDevice>,
+    spectrum: Analyzer,
+    meters: Meters,
+    limiting: bool,                     // The limiter is turning peaks down
+    unavailable: Option<PathBuf>,       // A track that can't be reached, waiting for it to come back
+    selected: HashSet<PathBuf>,         // Tracks picked with click / Ctrl / Shift
+    selection_anchor: Option<PathBuf>,  // Where a Shift+click range starts from
+    cursor: Option<PathBuf>,            // The row the arrow keys move from
+    modifiers: Modifiers,
+    list_scroll: RelativeOffset, // Current scroll position of the track list
+    pending_removal: Vec<PathBuf>, // Tracks waiting for the user to confirm removal
+    journal: Journal,
+    converter: Option<Converter>,
+    pending_conversion: Vec<PathBuf>, // Tracks waiting for the user to pick a format
+    conversions: Vec<Conversion>,
+    next_conversion_id: u64,
+    bookmark_name: String, // Name for the next bookmark on the playing track
+    snapshot_name: String, // Name to save the queue under
+    history_playlist: String, // Playlist the History tab adds tracks to
+    pending_restore: Option<Backup>, // Waiting to hear where the library lives on this machine
+    jukebox: Jukebox,
+    show_effects: bool,
+    show_hooks: bool,
+    show_plugins: bool,
+    show_columns: bool,
+    show_snapshots: bool,
+    jellyfin_password: String, // Only held until signed in
+    jellyfin_collection: Collection,
+    jellyfin_items: Vec<RemoteItem>,
+    jellyfin_open: Option<(RemoteItem, Vec<RemoteTrack>)>, // The album or playlist being looked at
+    jellyfin_status: String, // What's being waited on from the server
+    jellyfin_reported: Option<Instant>, // When playback progress was last sent to the server
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    FolderButtonPressed,
+    OpenFilesPressed,
+    FilesOpened(Option<Vec<PathBuf>>),
+    FolderSelected(Option<String>),
+    SetupAddFolder,
+    SetupFolderPicked(Option<PathBuf>),
+    SetupRemoveFolder(usize),
+    SetupStepSelected(SetupStep),
+    SetupFinished,
+    ScanComplete(Vec<PathBuf>),
+    TrackInfoLoaded(HashMap<PathBuf, Result<TrackInfo, String>>),
+    PlayAudio(PathBuf),
+    AudioReady(AudioEngine),
+    Audio(AudioEvent),
+    SeekChanged(f32),
+    SeekReleased,
+    WaveformDragged(f32),
+    WaveformLoaded(PathBuf, Result<Vec<f32>, String>),
+    ChaptersLoaded(PathBuf, Result<Vec<Chapter>, String>),
+    LyricsLoaded(PathBuf, Option<String>),
+    JumpToChapter(Duration),
+    VolumeChanged(f32),
+    TrackGainChanged(f32),
+    TrackGainReleased,
+    EffectsPressed,
+    HooksPressed,
+    HookChanged(HookEvent, String),
+    PluginsPressed,
+    JellyfinServerChanged(String),
+    JellyfinUsernameChanged(String),
+    JellyfinPasswordChanged(String),
+    JellyfinSignIn,
+    JellyfinSignedIn(Result<JellyfinSettings, String>),
+    JellyfinSignOut,
+    JellyfinTranscodeToggled(bool),
+    JellyfinBrowse(Collection),
+    JellyfinListed(Collection, Result<Vec<RemoteItem>, String>),
+    JellyfinOpen(RemoteItem),
+    JellyfinTracksLoaded(RemoteItem, Result<Vec<RemoteTrack>, String>),
+    JellyfinBack,
+    JellyfinFetch(RemoteTrack, bool), // Whether to play it rather than queue it once it's here
+    JellyfinFetched(RemoteTrack, bool, Result<PathBuf, String>),
+    OpenPluginsFolder,
+    ColumnsPressed,
+    ColumnToggled(usize, bool),
+    MoveColumn(usize, usize),
+    EffectToggled(usize, bool),
+    MoveEffect(usize, usize),
+    EffectParameterChanged(usize, f32),
+    EffectParameterReleased,
+    SilenceTrimmingToggled(bool),
+    SilenceThresholdChanged(f32),
+    SilenceLengthChanged(f32),
+    SilenceSettingsReleased,
+    ThemeSelected(ThemeChoice),
+    OutputDevicesListed(Vec<String>),
+    OutputDeviceSelected(OutputDevice),
+    BitPerfectToggled(bool),
+    NormalizeToggled(bool),
+    FollowSymlinksToggled(bool),
+    SampleRateSelected(SampleRate),
+    ResampleQualitySelected(ResampleQuality),
+    CloseRequested,
+    OpenLog,
+    DismissToast(u64),
+    SpectrumToggled(bool),
+    LevelMetersToggled(bool),
+    Frame,
+    PausePlayback,
+    ResumePlayback,
+    StopPlayback,
+    DisplayAlbumArtAndMetadata(Option<Vec<u8>>, Option<String>, Option<String>), // New message
+    TrackClicked(PathBuf),
+    ModifiersChanged(Modifiers),
+    TrackListScrolled(RelativeOffset),
+    WindowResized(u32, u32),
+    WindowMoved(i32, i32),
+    PaneResized(pane_grid::ResizeEvent),
+    ToggleFullscreen,
+    ViewCover,
+    CoverLoaded(PathBuf, Result<Cover, String>),
+    ZoomCover(Option<f32>), // None fits it to the window
+    SaveCoverPressed,
+    SaveCoverTo(Option<PathBuf>),
+    CoverSaved(Result<PathBuf, String>),
+    CloseCover,
+    ShowProperties(PathBuf),
+    PropertiesRead(PathBuf, Result<Properties, String>),
+    CloseProperties,
+    JumpToPlaying,
+    FollowPlayingToggled(bool),
+    EscapePressed,
+    FocusNext(bool), // Backwards with Shift
+    MoveCursor(isize),
+    ActivateCursor,
+    TogglePause,
+    ClearSelection,
+    RemoveRequested,
+    MoveToTrash,
+    RemoveFromLibrary,
+    CancelRemoval,
+    Undo,
+    ConvertRequested,
+    ConvertFormatSelected(Format),
+    ConvertQualitySelected(Quality),
+    ConvertDestinationPressed,
+    ConvertDestinationSelected(Option<PathBuf>),
+    StartConversion,
+    CancelConversion,
+    ClearFinishedConversions,
+    ConverterReady(Converter),
+    Convert(ConvertEvent),
+    ImportPressed,
+    ImportFileSelected(Option<PathBuf>),
+    ImportFinished(Result<Import, String>),
+    ExportPressed,
+    ExportFileSelected(Option<PathBuf>),
+    ExportFinished(Result<Vec<PathBuf>, String>),
+    BackupPressed,
+    BackupFileSelected(Option<PathBuf>),
+    BackupFinished(Result<PathBuf, String>),
+    RestorePressed,
+    RestoreFileSelected(Option<PathBuf>),
+    RestoreRead(Result<Box<Backup>, String>), // Boxed, as backups are much bigger than other messages
+    RestoreRootPressed,
+    RestoreRootSelected(Option<PathBuf>),
+    RestoreKeepingPaths,
+    CancelRestore,
+    TabSelected(Tab),
+    TrackListSelected(TrackList),
+    BookmarkNameChanged(String),
+    AddBookmark,
+    JumpToBookmark(PathBuf, Duration),
+    DeleteBookmark(PathBuf, usize),
+    SearchChanged(String),
+    EnqueueTrack(PathBuf),
+    EnqueueSelected,
+    RemoveFromQueue(usize),
+    PlayNext,
+    RetrySource,
+    SkipUnavailable,
+    RateDownSelected,
+    HistoryPlaylistChanged(String),
+    AddToPlaylist(PathBuf),
+    AddSelectedToPlaylist(String),
+    SnapshotsPressed,
+    SnapshotNameChanged(String),
+    SaveQueueSnapshot,
+    LoadQueueSnapshot(String),
+    DeleteQueueSnapshot(String),
+    AutoDjToggled(bool),
+    AutoDjRuleSelected(AutoDjRule),
+    ShuffleModeSelected(ShuffleMode),
+    ShuffleLibrary,
+    AddAudiobookFolderPressed,
+    AudiobookFolderSelected(Option<PathBuf>),
+    RemoveAudiobookFolder(usize),
+    AudiobookSpeedChanged(f32),
+    JukeboxPressed,
+    JukeboxPinChanged(String),
+    StartJukebox,
+    CancelJukebox,
+    UnlockJukebox,
+}
+
+impl Application for MusicJester {
+    type Message = Message;
+    type Theme = Theme;
+    type Executor = iced::executor::Default;
+    type Flags = Settings;
+
+    fn new(mut settings: Self::Flags) -> (Self, Command<Message>) {
+        // Settings from before recent folders were kept still know the current one
+        if settings.recent_folders.is_empty()
+            && let Some(folder) = settings.library_folders.first().cloned()
+        {
+            settings.remember_folder(&folder);
+        }
+        let selected_folder = settings.library_folders.first().map(|path| path.display().to_string()).unwrap_or_default();
+
+        let mut commands = vec![Command::perform(async { output_devices() }, Message::OutputDevicesListed)];
+        let scanning = !settings.library_folders.is_empty();
+        // Anyone with a library from before there was a setup has been through it, near enough
+        if scanning {
+            settings.setup_complete = true;
+        }
+        let setup = (!settings.setup_complete).then_some(SetupStep::Folders);
+        if scanning {
+            let (folders, scan) = (settings.library_folders.clone(), settings.scan.clone());
+            commands.push(Command::perform(async move { scan_folders(&folders, &scan) }, Message::ScanComplete));
+        }
+
+        let panes = pane_grid::State::with_configuration(pane_grid::Configuration::Split {
+            axis: pane_grid::Axis::Vertical,
+            ratio: settings.layout.split_ratio.clamp(MIN_SPLIT, MAX_SPLIT),
+            a: Box::new(pane_grid::Configuration::Pane(PaneKind::Library)),
+            b: Box::new(pane_grid::Configuration::Pane(PaneKind::NowPlaying)),
+        });
+
+        let plugins = plugins_dir().map(|dir| Plugins::load(&dir)).unwrap_or_default();
+
+        // Open on the tab that was showing last time
+        let tab = settings.layout.tab;
+        if tab == Tab::Jellyfin && settings.jellyfin.signed_in() {
+            commands.push(Command::perform(async {}, |()| Message::JellyfinBrowse(Collection::default())));
+        }
+
+        // Pick up the queue where it was left, minus anything since deleted
+        let db = LibraryDb::load();
+        let queue = db.queue.iter().filter(|file_path| file_path.exists()).cloned().collect();
+
+        (
+            Self {
+                settings,
+                db,
+                panes,
+                fullscreen: false,
+                cover: None,
+                properties: None,
+                selected_folder,
+                audio_files: Vec::new(),
+                tab,
+                track_list: tab.track_list().unwrap_or(TrackList::All),
+                albums: Vec::new(),
+                artists: Vec::new(),
+                composers: Vec::new(),
+                search: String::new(),
+                search_results: None,
+                track_info: HashMap::new(),
+                track_errors: HashMap::new(),
+                toasts: Vec::new(),
+                next_toast_id: 0,
+                scan_status: if scanning { "Scanning...".to_string() } else { String::new() },
+                pending_tags: Vec::new(),
+                tags_total: 0,
+                setup,
+                audio: None,
+                album_art: None,
+                song_title: None,
+                artist: None,
+                now_playing: None,
+                starting: None,
+                between_tracks: false,
+                queue,
+                paused: false,
+                position: Duration::ZERO,
+                track_duration: None,
+                seek_preview: None,
+                waveform: None,
+                chapters: Vec::new(),
+                preloaded: None,
+                lyrics: None,
+                plugins,
+                output_devices: vec![OutputDevice(None)],
+                spectrum: Analyzer::new(),
+                meters: Meters::default(),
+                limiting: false,
+                unavailable: None,
+                selected: HashSet::new(),
+                selection_anchor: None,
+                cursor: None,
+                modifiers: Modifiers::default(),
+                list_scroll: RelativeOffset::START,
+                pending_removal: Vec::new(),
+                journal: Journal::default(),
+                converter: None,
+                pending_conversion: Vec::new(),
+                conversions: Vec::new(),
+                next_conversion_id: 0,
+                bookmark_name: String::new(),
+                snapshot_name: String::new(),
+                history_playlist: String::new(),
+                pending_restore: None,
+                jukebox: Jukebox::Off,
+                show_effects: false,
+                show_hooks: false,
+                show_plugins: false,
+                show_columns: false,
+                show_snapshots: false,
+                jellyfin_password: String::new(),
+                jellyfin_collection: Collection::default(),
+                jellyfin_items: Vec::new(),
+                jellyfin_open: None,
+                jellyfin_status: String::new(),
+                jellyfin_reported: None,
+            },
+            Command::batch(commands),
+        )
+    }
+
+    fn title(&self) -> String {
+        String::from("Music Jester")
+    }
+
+    fn theme(&self) -> Theme {
+        match self.settings.theme {
+            ThemeChoice::Light => Theme::Light,
+            ThemeChoice::Dark => Theme::Dark,
+            ThemeChoice::HighContrast => high_contrast_theme(),
+        }
+    }
+
+    fn scale_factor(&self) -> f64 {
+        if self.settings.theme == ThemeChoice::HighContrast { contrast::SCALE } else { 1.0 }
+    }
+
+    fn update(&mut self, message: Message) -> Command<Message> {
+        // Guests shouldn't be able to shut the party down, or get at anything but the queue
+        if self.jukebox.keeps_guests_from(&message) {
+            return Command::none();
+        }
+        match message {
+            Message::FolderButtonPressed => {
+                Command::perform(
+                    async {
+                        let result = FileDialog::new().pick_folder();
+                        result.map(|path| path.display().to_string())
+                    },
+                    Message::FolderSelected,
+                )
+            }
+            Message::OpenFilesPressed => Command::perform(
+                async { FileDialog::new().set_title("Play files").add_filter("Audio", &AUDIO_EXTENSIONS).pick_files() },
+                Message::FilesOpened,
+            ),
+            // Played or queued as they are, without becoming part of the library
+            Message::FilesOpened(files) => {
+                let mut files: Vec<PathBuf> = files.unwrap_or_default().into_iter().filter(|file_path| is_supported_audio_file(file_path)).collect();
+                files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+                // The first starts if nothing's playing; the rest follow it in order
+                let mut files = files.into_iter();
+                if let Some(first) = files.next() {
+                    self.enqueue(first);
+                }
+                for file_path in files {
+                    self.queue.push(file_path);
+                }
+                Command::none()
+            }
+            Message::FolderSelected(maybe_path) => {
+                if let Some(path) = maybe_path {
+                    self.settings.library_folders = vec![PathBuf::from(&path)];
+                    self.settings.remember_folder(Path::new(&path));
+                    self.settings.save();
+                    self.selected_folder = path;
+                    return self.start_scan();
+                }
+                Command::none()
+            }
+            Message::SetupAddFolder => Command::perform(async { FileDialog::new().set_title("Add a music folder").pick_folder() }, Message::SetupFolderPicked),
+            Message::SetupFolderPicked(folder) => {
+                if let Some(folder) = folder.filter(|folder| !self.settings.library_folders.contains(folder)) {
+                    self.settings.remember_folder(&folder);
+                    self.settings.library_folders.push(folder);
+                }
+                Command::none()
+            }
+            Message::SetupRemoveFolder(index) => {
+                if index < self.settings.library_folders.len() {
+                    self.settings.library_folders.remove(index);
+                }
+                Command::none()
+            }
+            Message::SetupStepSelected(step) => {
+                self.setup = Some(step);
+                if step != SetupStep::Scanning {
+                    return Command::none();
+                }
+                self.settings.save();
+                self.selected_folder = self.settings.library_folders.first().map(|path| path.display().to_string()).unwrap_or_default();
+                self.start_scan()
+            }
+            // The scan carries on if it's still going
+            Message::SetupFinished => {
+                self.setup = None;
+                self.settings.setup_complete = true;
+                self.settings.save();
+                self.show_tab(Tab::Library);
+                self.track_list = TrackList::All;
+                Command::none()
+            }
+            Message::ScanComplete(files) => {
+                self.audio_files = files;
+                self.refresh_albums();
+                self.refresh_search();
+                self.pending_tags = self.audio_files.clone();
+                self.tags_total = self.pending_tags.len();
+                self.read_more_tags()
+            }
+            Message::TrackInfoLoaded(track_info) => {
+                for (file_path, info) in track_info {
+                    match info {
+                        Ok(info) => {
+                            self.track_info.insert(file_path, info);
+                        }
+                        Err(e) => {
+                            self.track_errors.insert(file_path, e);
+                        }
+                    }
+                }
+                if !self.pending_tags.is_empty() {
+                    return self.read_more_tags();
+                }
+                self.scan_status = format!("Found {} audio files", self.audio_files.len());
+                // Setup's done once the library's ready
+                if self.setup == Some(SetupStep::Scanning) {
+                    self.setup = None;
+                    self.settings.setup_complete = true;
+                    self.settings.save();
+                }
+                order_audiobooks(&mut self.audio_files, &self.settings.audiobooks, &self.track_info);
+                // Titles and artists are searchable now, and albums can be put together
+                self.refresh_albums();
+                self.refresh_search();
+                Command::none()
+            }
+            Message::PlayAudio(file_path) => {
+                self.play_track(file_path, Duration::ZERO);
+                Command::none()
+            }
+            Message::AudioReady(audio) => {
+                audio.send(AudioCommand::SetVolume(self.settings.volume));
+                audio.send(AudioCommand::SetOutputDevice(self.settings.output_device.clone()));
+                audio.send(AudioCommand::SetSampleRate(self.settings.sample_rate));
+                audio.send(AudioCommand::SetResampleQuality(self.settings.resample_quality));
+                audio.send(AudioCommand::SetEffects(self.settings.effects.clone()));
+                audio.send(AudioCommand::SetSilenceTrimming(self.settings.silence.clone()));
+                audio.send(AudioCommand::SetBitPerfect(self.settings.bit_perfect));
+                audio.tap.set_enabled(self.visualisations_enabled());
+                audio.tap.set_sinks(self.plugins.sinks());
+                self.audio = Some(audio);
+                Command::none()
+            }
+            Message::Audio(AudioEvent::Started { file_path, duration }) => {
+                self.track_errors.remove(&file_path);
+                self.starting = None;
+                self.between_tracks = false;
+                if let Some(previous) = self.now_playing.as_ref().filter(|previous| **previous != file_path) {
+                    self.report_to_server(previous, Playback::Stopped { position: self.position });
+                }
+                self.now_playing = Some(file_path.clone());
+                // Picking up again after the file came back isn't another play, and nor is a
+                // restart for new settings, which comes as `Restarted` instead
+                let resumed = self.unavailable.take().as_ref() == Some(&file_path);
+                if !resumed && !self.settings.audiobooks.contains(&file_path) {
+                    self.db.record_play(&file_path);
+                }
+                if !resumed {
+                    run_hook(&self.settings.hooks, HookEvent::TrackStarted, &file_path, self.track_info.get(&file_path));
+                    self.report_to_server(&file_path, Playback::Started);
+                    self.jellyfin_reported = Some(Instant::now());
+                }
+                self.db.save();
+                self.paused = false;
+                self.position = Duration::ZERO;
+                self.track_duration = duration;
+                self.seek_preview = None;
+                self.waveform = None;
+                self.chapters.clear();
+                if !resumed {
+                    self.lyrics = None;
+                }
+                // Whatever was preloaded has just been used, if it was this
+                self.preloaded = None;
+                self.preload_next();
+                self.meters.reset();
+                self.limiting = false;
+                self.top_up_queue();
+                let follow = if self.settings.features.follow_playing { self.scroll_to_playing(false) } else { Command::none() };
+
+                // Decoding the whole track for its waveform takes a moment
+                let waveform_path = file_path.clone();
+                let waveform = Command::perform(
+                    async move {
+                        let peaks = compute_peaks(&waveform_path, PEAK_COUNT);
+                        (waveform_path, peaks)
+                    },
+                    |(file_path, peaks)| Message::WaveformLoaded(file_path, peaks),
+                );
+                let chapters_path = file_path.clone();
+                let chapters = Command::perform(
+                    async move {
+                        let chapters = read_chapters(&chapters_path);
+                        (chapters_path, chapters)
+                    },
+                    |(file_path, chapters)| Message::ChaptersLoaded(file_path, chapters),
+                );
+                // Lyrics plugins may well go online for them
+                let lyrics = if self.plugins.has_lyrics_sources() && !resumed {
+                    let plugins = self.plugins.clone();
+                    let info = self.track_info.get(&file_path).cloned().unwrap_or_default();
+                    let lyrics_path = file_path.clone();
+                    Command::perform(
+                        async move {
+                            let lyrics = plugins.lyrics(&lyrics_path, &info);
+                            (lyrics_path, lyrics)
+                        },
+                        |(file_path, lyrics)| Message::LyricsLoaded(file_path, lyrics),
+                    )
+                } else {
+                    Command::none()
+                };
+
+                // Extract album art, title, and artist, then update UI
+                let use_cache = self.settings.features.thumbnail_cache;
+                let details = Command::perform(
+                    async move {
+                        let album_art = album_art_thumbnail(&file_path, use_cache);
+                        let (title, artist) = extract_metadata(&file_path);
+                        (album_art, title, artist)
+                    },
+                    |(album_art, title, artist)| Message::DisplayAlbumArtAndMetadata(album_art, title, artist),
+                );
+                Command::batch([details, waveform, chapters, lyrics, follow])
+            }
+            // Same track, same play: only where it's got to and whether it's paused can have changed
+            Message::Audio(AudioEvent::Restarted { position, paused }) => {
+                self.position = position;
+                self.paused = paused;
+                self.seek_preview = None;
+                self.meters.reset();
+                self.limiting = false;
+                // The server already knows it started; just bring its idea of where it's got to up to date
+                if let Some(file_path) = &self.now_playing {
+                    self.report_to_server(file_path, Playback::Progress { position, paused });
+                    self.jellyfin_reported = Some(Instant::now());
+                }
+                Command::none()
+            }
+            Message::Audio(AudioEvent::PositionChanged(position)) => {
+                if let Some(file_path) = &self.now_playing
+                    && self.settings.audiobooks.contains(file_path)
+                {
+                    self.db.set_resume_position(file_path, Some(position));
+                    if passed_save_point(self.position, position) {
+                        self.db.save();
+                    }
+                }
+                self.position = position;
+                // Catches the queue changing while this track plays
+                self.preload_next();
+                if let Some(file_path) = &self.now_playing
+                    && self.jellyfin_reported.is_none_or(|reported| reported.elapsed() >= PROGRESS_REPORT_INTERVAL)
+                {
+                    self.report_to_server(file_path, Playback::Progress { position, paused: self.paused });
+                    self.jellyfin_reported = Some(Instant::now());
+                }
+                Command::none()
+            }
+            Message::Audio(AudioEvent::Limiting(limiting)) => {
+                self.limiting = limiting;
+                Command::none()
+            }
+            Message::Audio(AudioEvent::TrackEnded) => {
+                if let Some(file_path) = &self.now_playing {
+                    run_hook(&self.settings.hooks, HookEvent::TrackFinished, file_path, self.track_info.get(file_path));
+                    if self.settings.audiobooks.contains(file_path) {
+                        self.db.set_resume_position(file_path, None);
+                    } else {
+                        self.db.count_play(file_path);
+                    }
+                    self.db.save();
+                }
+                match self.queue.pop_next() {
+                    Some(file_path) => {
+                        self.between_tracks = true;
+                        self.update(Message::PlayAudio(file_path))
+                    }
+                    None => {
+                        self.stop_playback();
+                        Command::none()
+                    }
+                }
+            }
+            Message::Audio(AudioEvent::SourceUnavailable(file_path)) => {
+                self.unavailable = Some(file_path);
+                Command::none()
+            }
+            Message::RetrySource => {
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::Retry);
+                }
+                Command::none()
+            }
+            Message::SkipUnavailable => {
+                self.unavailable = None;
+                match self.queue.pop_next() {
+                    Some(file_path) => {
+                        self.between_tracks = true;
+                        self.update(Message::PlayAudio(file_path))
+                    }
+                    None => {
+                        self.stop_playback();
+                        Command::none()
+                    }
+                }
+            }
+            Message::Audio(AudioEvent::Error { file_path, message }) => {
+                let toast = match &file_path {
+                    Some(file_path) => format!("{}: {}", display_name(file_path), message),
+                    None => message.clone(),
+                };
+                let mut commands = vec![self.notify(toast)];
+                if let Some(file_path) = file_path {
+                    // A file that fails to open leaves whatever was playing still playing
+                    let silent = self.now_playing.is_none() || self.between_tracks || self.unavailable.is_some();
+                    let was_starting = self.starting.as_ref() == Some(&file_path);
+                    if was_starting {
+                        self.starting = None;
+                    }
+                    self.track_errors.insert(file_path, message);
+                    // Carry on with the queue rather than going quiet on one broken file
+                    if was_starting
+                        && silent
+                        && let Some(next) = self.queue.pop_next()
+                    {
+                        commands.push(self.update(Message::PlayAudio(next)));
+                    }
+                }
+                Command::batch(commands)
+            }
+            Message::SeekChanged(seconds) => {
+                self.seek_preview = Some(seconds);
+                Command::none()
+            }
+            Message::SeekReleased => {
+                if let (Some(seconds), Some(audio)) = (self.seek_preview.take(), &self.audio) {
+                    let position = Duration::from_secs_f32(seconds);
+                    audio.send(AudioCommand::Seek(position));
+                    self.position = position;
+                }
+                Command::none()
+            }
+            Message::WaveformDragged(fraction) => {
+                if let Some(duration) = self.track_duration {
+                    self.seek_preview = Some(fraction * duration.as_secs_f32());
+                }
+                Command::none()
+            }
+            Message::WaveformLoaded(file_path, peaks) => {
+                // Ignore a slow result for a track that's no longer playing
+                if self.now_playing.as_ref() == Some(&file_path) {
+                    match peaks {
+                        Ok(peaks) => self.waveform = Some(peaks),
+                        Err(e) => tracing::warn!(path = %file_path.display(), "No waveform, keeping the plain seek bar: {}", e),
+                    }
+                }
+                Command::none()
+            }
+            Message::ChaptersLoaded(file_path, chapters) => {
+                if self.now_playing.as_ref() == Some(&file_path) {
+                    match chapters {
+                        Ok(chapters) => self.chapters = chapters,
+                        Err(e) => tracing::warn!(path = %file_path.display(), "No chapters: {}", e),
+                    }
+                }
+                Command::none()
+            }
+            Message::LyricsLoaded(file_path, lyrics) => {
+                if self.now_playing.as_ref() == Some(&file_path) {
+                    self.lyrics = lyrics;
+                }
+                Command::none()
+            }
+            Message::JumpToChapter(position) => {
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::Seek(position));
+                    self.position = position;
+                }
+                Command::none()
+            }
+            Message::VolumeChanged(volume) => {
+                // Saved on exit rather than for every step of the slider
+                self.settings.volume = volume;
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::SetVolume(volume));
+                }
+                Command::none()
+            }
+            Message::TrackGainChanged(gain_db) => {
+                if let Some(file_path) = &self.now_playing {
+                    self.db.set_gain(file_path, gain_db);
+                    if let Some(audio) = &self.audio {
+                        audio.send(AudioCommand::SetTrackGain(gain_db));
+                    }
+                }
+                Command::none()
+            }
+            // Saved once the slider is let go of rather than for every step
+            Message::TrackGainReleased => {
+                self.db.save();
+                Command::none()
+            }
+            Message::HooksPressed => {
+                self.show_hooks = !self.show_hooks;
+                Command::none()
+            }
+            // Saved on exit rather than for every keystroke
+            Message::HookChanged(event, command) => {
+                self.settings.hooks.set_command(event, command);
+                Command::none()
+            }
+            Message::JellyfinServerChanged(server) => {
+                self.settings.jellyfin.server = server;
+                Command::none()
+            }
+            Message::JellyfinUsernameChanged(username) => {
+                self.settings.jellyfin.username = username;
+                Command::none()
+            }
+            Message::JellyfinPasswordChanged(password) => {
+                self.jellyfin_password = password;
+                Command::none()
+            }
+            Message::JellyfinSignIn => {
+                self.jellyfin_status = "Signing in…".to_string();
+                let (settings, password) = (self.settings.jellyfin.clone(), std::mem::take(&mut self.jellyfin_password));
+                Command::perform(async move { jellyfin::sign_in(&settings, &password) }, Message::JellyfinSignedIn)
+            }
+            Message::JellyfinSignedIn(signed_in) => {
+                self.jellyfin_status.clear();
+                match signed_in {
+                    Ok(settings) => {
+                        self.settings.jellyfin = settings;
+                        self.settings.save();
+                        self.update(Message::JellyfinBrowse(self.jellyfin_collection))
+                    }
+                    Err(e) => self.notify(format!("Failed to sign in to Jellyfin: {}", e)),
+                }
+            }
+            Message::JellyfinSignOut => {
+                self.settings.jellyfin.token.clear();
+                self.settings.jellyfin.user_id.clear();
+                self.settings.save();
+                self.jellyfin_items.clear();
+                self.jellyfin_open = None;
+                Command::none()
+            }
+            Message::JellyfinTranscodeToggled(transcode) => {
+                self.settings.jellyfin.transcode = transcode;
+                self.settings.save();
+                Command::none()
+            }
+            Message::JellyfinBrowse(collection) => {
+                let Some(session) = Session::new(&self.settings.jellyfin) else {
+                    return Command::none();
+                };
+                self.jellyfin_collection = collection;
+                self.jellyfin_open = None;
+                self.jellyfin_status = format!("Loading {}…", collection.to_string().to_lowercase());
+                Command::perform(async move { session.list(collection) }, move |items| Message::JellyfinListed(collection, items))
+            }
+            Message::JellyfinListed(collection, items) => {
+                if collection != self.jellyfin_collection {
+                    return Command::none();
+                }
+                self.jellyfin_status.clear();
+                match items {
+                    Ok(items) => {
+                        self.jellyfin_items = items;
+                        Command::none()
+                    }
+                    Err(e) => self.notify(format!("Failed to load {} from Jellyfin: {}", collection.to_string().to_lowercase(), e)),
+                }
+            }
+            Message::JellyfinOpen(item) => {
+                let Some(session) = Session::new(&self.settings.jellyfin) else {
+                    return Command::none();
+                };
+                self.jellyfin_status = format!("Loading {}…", item.name);
+                Command::perform(
+                    async move {
+                        let tracks = session.tracks(&item);
+                        (item, tracks)
+                    },
+                    |(item, tracks)| Message::JellyfinTracksLoaded(item, tracks),
+                )
+            }
+            Message::JellyfinTracksLoaded(item, tracks) => {
+                self.jellyfin_status.clear();
+                match tracks {
+                    Ok(tracks) => {
+                        self.jellyfin_open = Some((item, tracks));
+                        Command::none()
+                    }
+                    Err(e) => self.notify(format!("Failed to load {}: {}", item.name, e)),
+                }
+            }
+            Message::JellyfinBack => {
+                self.jellyfin_open = None;
+                Command::none()
+            }
+            Message::JellyfinFetch(track, play) => {
+                let Some(session) = Session::new(&self.settings.jellyfin) else {
+                    return Command::none();
+                };
+                self.jellyfin_status = format!("Downloading {}…", track.info.title.as_deref().unwrap_or("the track"));
+                let transcode = self.settings.jellyfin.transcode;
+                Command::perform(
+                    async move {
+                        let fetched = session.fetch(&track, transcode);
+                        (track, fetched)
+                    },
+                    move |(track, fetched)| Message::JellyfinFetched(track, play, fetched),
+                )
+            }
+            Message::JellyfinFetched(track, play, fetched) => {
+                self.jellyfin_status.clear();
+                match fetched {
+                    Ok(file_path) => {
+                        // The server knows the track better than whatever tags the download has
+                        self.track_info.insert(file_path.clone(), track.info);
+                        if play {
+                            self.play_track(file_path, Duration::ZERO);
+                        } else {
+                            self.enqueue(file_path);
+                        }
+                        Command::none()
+                    }
+                    Err(e) => self.notify(e),
+                }
+            }
+            Message::PluginsPressed => {
+                self.show_plugins = !self.show_plugins;
+                Command::none()
+            }
+            Message::OpenPluginsFolder => {
+                let Some(dir) = plugins_dir() else {
+                    return self.notify("There's no data folder to keep plugins in".to_string());
+                };
+                if let Err(e) = std::fs::create_dir_all(&dir) {
+                    return self.notify(format!("Failed to create {}: {}", dir.display(), e));
+                }
+                match open::that(&dir) {
+                    Ok(()) => Command::none(),
+                    Err(e) => self.notify(format!("Failed to open {}: {}", dir.display(), e)),
+                }
+            }
+            Message::EffectsPressed => {
+                self.show_effects = !self.show_effects;
+                Command::none()
+            }
+            Message::ColumnsPressed => {
+                self.show_columns = !self.show_columns;
+                Command::none()
+            }
+            Message::ColumnToggled(index, shown) => {
+                if let Some(slot) = self.settings.columns.get_mut(index) {
+                    slot.shown = shown;
+                    self.settings.save();
+                }
+                Command::none()
+            }
+            Message::MoveColumn(from, to) => {
+                if from < self.settings.columns.len() && to < self.settings.columns.len() {
+                    self.settings.columns.swap(from, to);
+                    self.settings.save();
+                }
+                Command::none()
+            }
+            Message::EffectToggled(index, enabled) => {
+                if let Some(slot) = self.settings.effects.get_mut(index) {
+                    slot.enabled = enabled;
+                    self.apply_effects();
+                }
+                Command::none()
+            }
+            Message::MoveEffect(from, to) => {
+                if from < self.settings.effects.len() && to < self.settings.effects.len() {
+                    self.settings.effects.swap(from, to);
+                    self.apply_effects();
+                }
+                Command::none()
+            }
+            // Only takes effect once the slider is let go of, since the track restarts to pick it up
+            Message::EffectParameterChanged(index, value) => {
+                if let Some(slot) = self.settings.effects.get_mut(index) {
+                    slot.effect.set_parameter(value);
+                }
+                Command::none()
+            }
+            Message::EffectParameterReleased => {
+                self.apply_effects();
+                Command::none()
+            }
+            Message::SilenceTrimmingToggled(enabled) => {
+                self.settings.silence.enabled = enabled;
+                self.apply_silence_trimming();
+                Command::none()
+            }
+            Message::SilenceThresholdChanged(threshold_db) => {
+                self.settings.silence.threshold_db = threshold_db;
+                Command::none()
+            }
+            Message::SilenceLengthChanged(seconds) => {
+                self.settings.silence.min_seconds = seconds;
+                Command::none()
+            }
+            // Every change means decoding the track again to find its silences, so wait for the release
+            Message::SilenceSettingsReleased => {
+                self.apply_silence_trimming();
+                Command::none()
+            }
+            Message::ThemeSelected(theme) => {
+                self.settings.theme = theme;
+                self.settings.save();
+                Command::none()
+            }
+            Message::OutputDevicesListed(devices) => {
+                self.output_devices = std::iter::once(OutputDevice(None))
+                    .chain(devices.into_iter().map(|name| OutputDevice(Some(name))))
+                    .collect();
+                Command::none()
+            }
+            Message::OutputDeviceSelected(OutputDevice(device)) => {
+                self.settings.output_device = device.clone();
+                self.settings.save();
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::SetOutputDevice(device));
+                }
+                Command::none()
+            }
+            Message::SampleRateSelected(SampleRate(sample_rate)) => {
+                self.settings.sample_rate = sample_rate;
+                self.settings.save();
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::SetSampleRate(sample_rate));
+                }
+                Command::none()
+            }
+            Message::ResampleQualitySelected(quality) => {
+                self.settings.resample_quality = quality;
+                self.settings.save();
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::SetResampleQuality(quality));
+                }
+                Command::none()
+            }
+            // Takes effect from the next scan
+            Message::FollowSymlinksToggled(follow) => {
+                self.settings.scan.follow_symlinks = follow;
+                self.settings.save();
+                Command::none()
+            }
+            Message::BitPerfectToggled(bit_perfect) => {
+                self.settings.bit_perfect = bit_perfect;
+                self.settings.save();
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::SetBitPerfect(bit_perfect));
+                }
+                Command::none()
+            }
+            Message::NormalizeToggled(normalize) => {
+                self.settings.normalize = normalize;
+                self.settings.save();
+                if let (Some(audio), Some(file_path)) = (&self.audio, &self.now_playing) {
+                    audio.send(AudioCommand::SetLeveling(self.leveling(file_path)));
+                }
+                Command::none()
+            }
+            Message::OpenLog => {
+                let Some(path) = latest_log_file().or_else(log_dir) else {
+                    return self.notify("There is no log file yet".to_string());
+                };
+                match open::that(&path) {
+                    Ok(()) => Command::none(),
+                    Err(e) => self.notify(format!("Failed to open {}: {}", path.display(), e)),
+                }
+            }
+            Message::DismissToast(id) => {
+                self.toasts.retain(|toast| toast.id != id);
+                Command::none()
+            }
+            Message::SpectrumToggled(enabled) => {
+                self.settings.features.spectrum = enabled;
+                self.settings.save();
+                self.spectrum.reset();
+                self.update_tap();
+                Command::none()
+            }
+            Message::LevelMetersToggled(enabled) => {
+                self.settings.features.level_meters = enabled;
+                self.settings.save();
+                self.meters.reset();
+                self.update_tap();
+                Command::none()
+            }
+            Message::Frame => {
+                if let Some(audio) = &self.audio {
+                    if self.settings.features.spectrum {
+                        self.spectrum.update(&audio.tap.snapshot());
+                    }
+                    if self.settings.features.level_meters {
+                        self.meters.update(&audio.tap.take_peaks());
+                    }
+                }
+                Command::none()
+            }
+            Message::CloseRequested => {
+                self.settings.save();
+                self.db.queue = self.queue.iter().cloned().collect();
+                self.db.save();
+                iced::window::close()
+            }
+            Message::DisplayAlbumArtAndMetadata(Some(album_art), Some(title), Some(artist)) => {
+                self.album_art = Some(album_art);
+                self.song_title = Some(title);
+                self.artist = Some(artist);
+                Command::none()
+            }
+            Message::DisplayAlbumArtAndMetadata(_, _, _) => {
+                // Handle the case where album art, title, or artist is None
+                self.album_art = None;
+                self.song_title = None;
+                self.artist = None;
+                Command::none()
+            }
+            Message::PausePlayback => {
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::Pause);
+                    self.paused = true;
+                }
+                if let Some(file_path) = &self.now_playing {
+                    run_hook(&self.settings.hooks, HookEvent::Paused, file_path, self.track_info.get(file_path));
+                    self.report_to_server(file_path, Playback::Progress { position: self.position, paused: true });
+                }
+                // Keep an audiobook's place in case the app doesn't get closed properly
+                if self.now_playing.as_ref().is_some_and(|file_path| self.settings.audiobooks.contains(file_path)) {
+                    self.db.save();
+                }
+                Command::none()
+            }
+            Message::ResumePlayback => {
+                if let Some(audio) = &self.audio {
+                    audio.send(AudioCommand::Resume);
+                    self.paused = false;
+                }
+                if let Some(file_path) = &self.now_playing {
+                    self.report_to_server(file_path, Playback::Progress { position: self.position, paused: false });
+                }
+                Command::none()
+            }
+            Message::StopPlayback => {
+                self.stop_playback();
+                Command::none()
+            }
+            Message::TrackClicked(file_path) => {
+                if self.modifiers.shift() {
+                    self.select_range_to(&file_path);
+                    self.cursor = Some(file_path);
+                    Command::none()
+                } else if self.modifiers.command() {
+                    if !self.selected.remove(&file_path) {
+                        self.selected.insert(file_path.clone());
+                    }
+                    self.selection_anchor = Some(file_path.clone());
+                    self.cursor = Some(file_path);
+                    Command::none()
+                } else {
+                    // A plain click selects just this track and plays it
+                    self.selected.clear();
+                    self.selected.insert(file_path.clone());
+                    self.selection_anchor = Some(file_path.clone());
+                    self.cursor = Some(file_path.clone());
+                    self.update(Message::PlayAudio(file_path))
+                }
+            }
+            Message::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+                Command::none()
+            }
+            Message::TrackListScrolled(offset) => {
+                self.list_scroll = offset;
+                Command::none()
+            }
+            // Full screen geometry isn't worth remembering
+            Message::WindowResized(width, height) => {
+                if !self.fullscreen {
+                    self.settings.layout.window_size = (width, height);
+                }
+                Command::none()
+            }
+            Message::WindowMoved(x, y) => {
+                if !self.fullscreen {
+                    self.settings.layout.window_position = Some((x, y));
+                }
+                Command::none()
+            }
+            Message::ToggleFullscreen => self.set_fullscreen(!self.fullscreen),
+            Message::ViewCover => {
+                let Some(file_path) = self.now_playing.clone() else { return Command::none() };
+                self.cover = Some(CoverViewer { file_path: file_path.clone(), cover: None, zoom: None });
+                Command::perform(
+                    async move {
+                        let cover = load_cover(&file_path);
+                        (file_path, cover)
+                    },
+                    |(file_path, cover)| Message::CoverLoaded(file_path, cover),
+                )
+            }
+            Message::CoverLoaded(file_path, cover) => {
+                if let Some(viewer) = &mut self.cover
+                    && viewer.file_path == file_path
+                {
+                    viewer.cover = Some(cover.map(|cover| {
+                        let handle = image::Handle::from_memory(cover.bytes.clone());
+                        (cover, handle)
+                    }));
+                }
+                Command::none()
+            }
+            Message::ZoomCover(zoom) => {
+                if let Some(viewer) = &mut self.cover {
+                    viewer.zoom = zoom.map(|zoom| zoom.clamp(MIN_COVER_ZOOM, MAX_COVER_ZOOM));
+                }
+                Command::none()
+            }
+            Message::SaveCoverPressed => {
+                let Some(CoverViewer { file_path, cover: Some(Ok((cover, _))), .. }) = &self.cover else { return Command::none() };
+                let stem = file_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                let (name, extension) = (format!("{} cover.{}", stem, cover.extension), cover.extension);
+                Command::perform(
+                    async move {
+                        FileDialog::new()
+                            .set_title("Save image as")
+                            .set_file_name(&name)
+                            .add_filter(extension.to_uppercase(), &[extension])
+                            .save_file()
+                    },
+                    Message::SaveCoverTo,
+                )
+            }
+            Message::SaveCoverTo(path) => {
+                let (Some(path), Some(CoverViewer { cover: Some(Ok((cover, _))), .. })) = (path, &self.cover) else {
+                    return Command::none();
+                };
+                let bytes = cover.bytes.clone();
+                Command::perform(
+                    async move { std::fs::write(&path, bytes).map(|_| path).map_err(|e| format!("Failed to save the image: {}", e)) },
+                    Message::CoverSaved,
+                )
+            }
+            Message::CoverSaved(Ok(path)) => self.notify(format!("Saved {}", display_name(&path))),
+            Message::CoverSaved(Err(e)) => self.notify(e),
+            Message::CloseCover => {
+                self.cover = None;
+                Command::none()
+            }
+            Message::ShowProperties(file_path) => {
+                self.properties = Some(PropertiesDialog { file_path: file_path.clone(), properties: None });
+                Command::perform(
+                    async move {
+                        let properties = read_properties(&file_path);
+                        (file_path, properties)
+                    },
+                    |(file_path, properties)| Message::PropertiesRead(file_path, properties),
+                )
+            }
+            Message::PropertiesRead(file_path, properties) => {
+                if let Some(dialog) = &mut self.properties
+                    && dialog.file_path == file_path
+                {
+                    dialog.properties = Some(properties);
+                }
+                Command::none()
+            }
+            Message::CloseProperties => {
+                self.properties = None;
+                Command::none()
+            }
+            Message::EscapePressed if self.properties.is_some() => {
+                self.properties = None;
+                Command::none()
+            }
+            Message::EscapePressed if self.cover.is_some() => {
+                self.cover = None;
+                Command::none()
+            }
+            Message::EscapePressed => {
+                if self.fullscreen {
+                    self.set_fullscreen(false)
+                } else {
+                    Command::none()
+                }
+            }
+            Message::JumpToPlaying => self.scroll_to_playing(true),
+            Message::FollowPlayingToggled(enabled) => {
+                self.settings.features.follow_playing = enabled;
+                self.settings.save();
+                if enabled { self.scroll_to_playing(false) } else { Command::none() }
+            }
+            Message::FocusNext(backwards) => {
+                if backwards {
+                    iced::widget::focus_previous()
+                } else {
+                    iced::widget::focus_next()
+                }
+            }
+            Message::MoveCursor(step) => self.move_cursor(step),
+            Message::ActivateCursor => match self.cursor.clone().filter(|_| self.list_showing()) {
+                Some(file_path) if matches!(self.jukebox, Jukebox::Locked { .. }) => self.update(Message::EnqueueTrack(file_path)),
+                Some(file_path) => self.update(Message::PlayAudio(file_path)),
+                None => Command::none(),
+            },
+            Message::TogglePause => match (&self.now_playing, self.paused) {
+                (None, _) => Command::none(),
+                (Some(_), true) => self.update(Message::ResumePlayback),
+                (Some(_), false) => self.update(Message::PausePlayback),
+            },
+            Message::PaneResized(pane_grid::ResizeEvent { split, ratio }) => {
+                let ratio = ratio.clamp(MIN_SPLIT, MAX_SPLIT);
+                self.panes.resize(&split, ratio);
+                self.settings.layout.split_ratio = ratio;
+                Command::none()
+            }
+            Message::ClearSelection => {
+                self.selected.clear();
+                self.selection_anchor = None;
+                self.cursor = None;
+                Command::none()
+            }
+            Message::RemoveRequested => {
+                self.pending_removal = self.selected_files();
+                Command::none()
+            }
+            Message::MoveToTrash => {
+                let mut failures = Vec::new();
+                let mut removals = Vec::new();
+                for file_path in std::mem::take(&mut self.pending_removal) {
+                    match trash::delete(&file_path) {
+                        Ok(()) => {
+                            removals.push(self.remove_from_library(&file_path));
+                            self.db.forget(&file_path);
+                        }
+                        Err(e) => {
+                            tracing::warn!(path = %file_path.display(), "Failed to move to trash: {}", e);
+                            failures.push(self.notify(format!("Failed to move {} to trash: {}", display_name(&file_path), e)));
+                        }
+                    }
+                }
+                if !removals.is_empty() {
+                    self.journal.record(Change::Trashed(removals));
+                }
+                self.db.save();
+                Command::batch(failures)
+            }
+            Message::RemoveFromLibrary => {
+                let removals = std::mem::take(&mut self.pending_removal).iter().map(|file_path| self.remove_from_library(file_path)).collect();
+                self.journal.record(Change::Removed(removals));
+                Command::none()
+            }
+            Message::Undo => match self.journal.undo() {
+                Some(change) => self.undo(change),
+                None => Command::none(),
+            },
+            Message::CancelRemoval => {
+                self.pending_removal.clear();
+                Command::none()
+            }
+            Message::ConvertRequested => {
+                self.pending_conversion = self.selected_files();
+                Command::none()
+            }
+            Message::ConvertFormatSelected(format) => {
+                self.settings.convert.format = format;
+                Command::none()
+            }
+            Message::ConvertQualitySelected(quality) => {
+                self.settings.convert.quality = quality;
+                Command::none()
+            }
+            Message::ConvertDestinationPressed => {
+                let start = self.settings.convert.destination.clone();
+                Command::perform(
+                    async move {
+                        let dialog = FileDialog::new().set_title("Save converted tracks to");
+                        match start {
+                            Some(folder) => dialog.set_directory(folder),
+                            None => dialog,
+                        }
+                        .pick_folder()
+                    },
+                    Message::ConvertDestinationSelected,
+                )
+            }
+            Message::ConvertDestinationSelected(folder) => {
+                if folder.is_some() {
+                    self.settings.convert.destination = folder;
+                }
+                Command::none()
+            }
+            Message::StartConversion => {
+                let (Some(converter), Some(folder)) = (&self.converter, &self.settings.convert.destination) else {
+                    return Command::none();
+                };
+                for source in std::mem::take(&mut self.pending_conversion) {
+                    let id = self.next_conversion_id;
+                    self.next_conversion_id += 1;
+                    self.conversions.push(Conversion { id, name: display_name(&source), progress: 0.0, result: None });
+                    converter.queue(ConvertJob {
+                        id,
+                        duration: self.track_info.get(&source).and_then(|info| info.duration),
+                        source,
+                        destination_folder: folder.clone(),
+                        format: self.settings.convert.format,
+                        quality: self.settings.convert.quality,
+                    });
+                }
+                self.settings.save();
+                Command::none()
+            }
+            Message::CancelConversion => {
+                self.pending_conversion.clear();
+                Command::none()
+            }
+            Message::ClearFinishedConversions => {
+                self.conversions.retain(|conversion| conversion.result.is_none());
+                Command::none()
+            }
+            Message::ImportPressed => Command::perform(
+                async {
+                    FileDialog::new()
+                        .set_title("Import an iTunes Library.xml or Rhythmbox rhythmdb.xml")
+                        .add_filter("Library XML", &["xml"])
+                        .pick_file()
+                },
+                Message::ImportFileSelected,
+            ),
+            Message::ImportFileSelected(path) => {
+                let Some(path) = path else { return Command::none() };
+                self.scan_status = "Importing...".to_string();
+                let library = self.audio_files.clone();
+                Command::perform(async move { import_library(&path, &library) }, Message::ImportFinished)
+            }
+            Message::ImportFinished(Ok(import)) => {
+                self.scan_status = import.summary();
+                import.apply_to(&mut self.db);
+                self.db.save();
+                Command::none()
+            }
+            Message::ImportFinished(Err(e)) => {
+                self.scan_status = format!("Found {} audio files", self.audio_files.len());
+                self.notify(e)
+            }
+            Message::ExportPressed => Command::perform(
+                async {
+                    FileDialog::new()
+                        .set_title("Export the library")
+                        .set_file_name("music-jester-library.json")
+                        .add_filter("JSON", &["json"])
+                        .add_filter("CSV", &["csv"])
+                        .save_file()
+                },
+                Message::ExportFileSelected,
+            ),
+            Message::ExportFileSelected(path) => {
+                let Some(path) = path else { return Command::none() };
+                let files = self.audio_files.clone();
+                let track_info = self.track_info.clone();
+                let db = self.db.clone();
+                Command::perform(async move { export_library(&path, &files, &track_info, &db) }, Message::ExportFinished)
+            }
+            Message::ExportFinished(Ok(written)) => {
+                let names: Vec<_> = written.iter().map(|path| display_name(path)).collect();
+                self.scan_status = format!("Exported to {}", names.join(" and "));
+                Command::none()
+            }
+            Message::ExportFinished(Err(e)) => self.notify(e),
+            Message::BackupPressed => Command::perform(
+                async {
+                    FileDialog::new()
+                        .set_title("Back up the library and settings")
+                        .set_file_name("music-jester-backup.zip")
+                        .add_filter("Backup", &["zip"])
+                        .save_file()
+                },
+                Message::BackupFileSelected,
+            ),
+            Message::BackupFileSelected(path) => {
+                let Some(path) = path else { return Command::none() };
+                let settings = self.settings.clone();
+                let db = self.db.clone();
+                Command::perform(async move { write_backup(&path, &settings, &db).map(|_| path) }, Message::BackupFinished)
+            }
+            Message::BackupFinished(Ok(path)) => {
+                self.scan_status = format!("Backed up to {}", display_name(&path));
+                Command::none()
+            }
+            Message::BackupFinished(Err(e)) => self.notify(e),
+            Message::RestorePressed => Command::perform(
+                async {
+                    FileDialog::new()
+                        .set_title("Restore a backup")
+                        .add_filter("Backup", &["zip"])
+                        .pick_file()
+                },
+                Message::RestoreFileSelected,
+            ),
+            Message::RestoreFileSelected(path) => {
+                let Some(path) = path else { return Command::none() };
+                Command::perform(async move { read_backup(&path).map(Box::new) }, Message::RestoreRead)
+            }
+            Message::RestoreRead(Ok(backup)) => {
+                let backup = *backup;
+                // Only ask about the library's location if it isn't where it used to be
+                if backup.library_root().is_some_and(|root| !root.exists()) {
+                    self.pending_restore = Some(backup);
+                    Command::none()
+                } else {
+                    self.restore(backup)
+                }
+            }
+            Message::RestoreRead(Err(e)) => self.notify(e),
+            Message::RestoreRootPressed => Command::perform(
+                async { FileDialog::new().set_title("Where is the music library now?").pick_folder() },
+                Message::RestoreRootSelected,
+            ),
+            Message::RestoreRootSelected(root) => {
+                let (Some(root), Some(mut backup)) = (root, self.pending_restore.take()) else { return Command::none() };
+                if let Some(old_root) = backup.library_root().map(Path::to_path_buf) {
+                    backup.remap(&old_root, &root);
+                }
+                self.restore(backup)
+            }
+            Message::RestoreKeepingPaths => match self.pending_restore.take() {
+                Some(backup) => self.restore(backup),
+                None => Command::none(),
+            },
+            Message::CancelRestore => {
+                self.pending_restore = None;
+                Command::none()
+            }
+            Message::TabSelected(tab) => {
+                self.show_tab(tab);
+                // Coming back to a tab finds it as it was left
+                if let Some(track_list) = tab.track_list().filter(|_| Tab::showing(&self.track_list) != tab) {
+                    self.track_list = track_list;
+                    self.list_scroll = RelativeOffset::START;
+                    self.refresh_search();
+                }
+                if tab == Tab::Jellyfin && self.jellyfin_items.is_empty() && self.settings.jellyfin.signed_in() {
+                    return self.update(Message::JellyfinBrowse(self.jellyfin_collection));
+                }
+                Command::none()
+            }
+            Message::TrackListSelected(track_list) => {
+                self.show_tab(Tab::showing(&track_list));
+                self.track_list = track_list;
+                self.list_scroll = RelativeOffset::START;
+                self.refresh_search();
+                Command::none()
+            }
+            Message::HistoryPlaylistChanged(name) => {
+                self.history_playlist = name;
+                Command::none()
+            }
+            Message::AddToPlaylist(file_path) => {
+                let name = self.history_playlist.trim();
+                if !name.is_empty() {
+                    self.db.add_to_playlist(name, &file_path);
+                    self.db.save();
+                }
+                Command::none()
+            }
+            Message::AddSelectedToPlaylist(name) => {
+                let files = self.selected_files();
+                for file_path in &files {
+                    self.db.add_to_playlist(&name, file_path);
+                }
+                self.db.save();
+                self.notify(match files.len() {
+                    1 => format!("Added 1 track to \"{}\"", name),
+                    count => format!("Added {} tracks to \"{}\"", count, name),
+                })
+            }
+            Message::SnapshotsPressed => {
+                self.show_snapshots = !self.show_snapshots;
+                Command::none()
+            }
+            Message::SnapshotNameChanged(name) => {
+                self.snapshot_name = name;
+                Command::none()
+            }
+            Message::SaveQueueSnapshot => {
+                let name = self.snapshot_name.trim().to_string();
+                if name.is_empty() || self.queue.is_empty() {
+                    return Command::none();
+                }
+                self.db.save_queue_snapshot(QueueSnapshot { name, tracks: self.queue.iter().cloned().collect() });
+                self.db.save();
+                self.snapshot_name.clear();
+                Command::none()
+            }
+            Message::LoadQueueSnapshot(name) => {
+                let Some(snapshot) = self.db.queue_snapshot(&name) else { return Command::none() };
+                let missing = snapshot.tracks.iter().filter(|file_path| !file_path.exists()).count();
+                self.queue = snapshot.tracks.iter().filter(|file_path| file_path.exists()).cloned().collect();
+                if missing > 0 {
+                    return self.notify(format!("{} tracks in \"{}\" no longer exist and were left out", missing, name));
+                }
+                Command::none()
+            }
+            Message::DeleteQueueSnapshot(name) => {
+                self.db.delete_queue_snapshot(&name);
+                self.db.save();
+                Command::none()
+            }
+            Message::BookmarkNameChanged(name) => {
+                self.bookmark_name = name;
+                Command::none()
+            }
+            Message::AddBookmark => {
+                let Some(file_path) = &self.now_playing else { return Command::none() };
+                let position = self.position;
+                let name = match self.bookmark_name.trim() {
+                    "" => format!("Bookmark at {}", format_duration(position)),
+                    name => name.to_string(),
+                };
+                self.db.add_bookmark(file_path, Bookmark { name, position });
+                self.db.save();
+                self.bookmark_name.clear();
+                Command::none()
+            }
+            Message::JumpToBookmark(file_path, position) => {
+                if let Some(audio) = &self.audio {
+                    if self.now_playing.as_ref() == Some(&file_path) {
+                        audio.send(AudioCommand::Seek(position));
+                        self.position = position;
+                    } else {
+                        self.play_track(file_path, position);
+                    }
+                }
+                Command::none()
+            }
+            Message::DeleteBookmark(file_path, index) => {
+                let bookmark = self.db.tracks.get(&file_path).and_then(|record| record.bookmarks.get(index)).cloned();
+                self.db.remove_bookmark(&file_path, index);
+                self.db.save();
+                if let Some(bookmark) = bookmark {
+                    self.journal.record(Change::DeletedBookmark { file_path, bookmark });
+                }
+                Command::none()
+            }
+            Message::ConverterReady(converter) => {
+                self.converter = Some(converter);
+                Command::none()
+            }
+            Message::Convert(ConvertEvent::Progress { id, fraction }) => {
+                if let Some(conversion) = self.conversions.iter_mut().find(|conversion| conversion.id == id) {
+                    conversion.progress = fraction;
+                }
+                Command::none()
+            }
+            Message::Convert(ConvertEvent::Finished { id, result }) => {
+                let Some(conversion) = self.conversions.iter_mut().find(|conversion| conversion.id == id) else {
+                    return Command::none();
+                };
+                conversion.progress = 1.0;
+                conversion.result = Some(result.clone());
+                match result {
+                    Ok(_) => Command::none(),
+                    Err(e) => {
+                        let message = format!("Failed to convert {}: {}", conversion.name, e);
+                        self.notify(message)
+                    }
+                }
+            }
+            Message::SearchChanged(search) => {
+                self.search = search;
+                self.list_scroll = RelativeOffset::START;
+                self.refresh_search();
+                Command::none()
+            }
+            Message::EnqueueTrack(file_path) => {
+                self.enqueue(file_path);
+                Command::none()
+            }
+            Message::EnqueueSelected => {
+                for file_path in self.selected_files() {
+                    self.enqueue(file_path);
+                }
+                Command::none()
+            }
+            Message::RemoveFromQueue(index) => {
+                self.queue.remove(index);
+                self.top_up_queue();
+                Command::none()
+            }
+            Message::PlayNext => match self.queue.pop_next() {
+                Some(file_path) => {
+                    if let Some(playing) = &self.now_playing
+                        && !self.settings.audiobooks.contains(playing)
+                        && self.db.note_skip(playing, self.position, self.track_duration)
+                    {
+                        self.db.save();
+                    }
+                    self.update(Message::PlayAudio(file_path))
+                }
+                None => Command::none(),
+            },
+            Message::RateDownSelected => {
+                for file_path in self.selected_files() {
+                    self.db.rate_down(&file_path);
+                }
+                self.db.save();
+                Command::none()
+            }
+            Message::AutoDjToggled(enabled) => {
+                self.settings.auto_dj.enabled = enabled;
+                self.settings.save();
+                self.top_up_queue();
+                Command::none()
+            }
+            Message::AutoDjRuleSelected(rule) => {
+                self.settings.auto_dj.rule = rule;
+                self.settings.save();
+                Command::none()
+            }
+            Message::ShuffleModeSelected(mode) => {
+                self.settings.shuffle = mode;
+                self.settings.save();
+                Command::none()
+            }
+            Message::ShuffleLibrary => {
+                // Audiobooks would land mid-story, so they're left out
+                let files: Vec<PathBuf> =
+                    self.audio_files.iter().filter(|file_path| !self.settings.audiobooks.contains(file_path)).cloned().collect();
+                self.queue = shuffle(self.settings.shuffle, &files, &self.albums).into_iter().collect();
+                if self.now_playing.is_none()
+                    && let Some(file_path) = self.queue.pop_next()
+                {
+                    self.play_track(file_path, Duration::ZERO);
+                }
+                Command::none()
+            }
+            Message::AddAudiobookFolderPressed => {
+                let start = self.settings.library_folders.first().cloned();
+                Command::perform(
+                    async move {
+                        let dialog = FileDialog::new().set_title("Folder of audiobooks");
+                        match start {
+                            Some(folder) => dialog.set_directory(folder),
+                            None => dialog,
+                        }
+                        .pick_folder()
+                    },
+                    Message::AudiobookFolderSelected,
+                )
+            }
+            Message::AudiobookFolderSelected(folder) => {
+                if let Some(folder) = folder
+                    && !self.settings.audiobooks.folders.contains(&folder)
+                {
+                    self.settings.audiobooks.folders.push(folder);
+                    self.settings.save();
+                    order_audiobooks(&mut self.audio_files, &self.settings.audiobooks, &self.track_info);
+                    self.refresh_search();
+                }
+                Command::none()
+            }
+            Message::RemoveAudiobookFolder(index) => {
+                if index < self.settings.audiobooks.folders.len() {
+                    self.settings.audiobooks.folders.remove(index);
+                    self.settings.save();
+                }
+                Command::none()
+            }
+            Message::AudiobookSpeedChanged(speed) => {
+                self.settings.audiobooks.speed = speed;
+                self.settings.save();
+                if let (Some(audio), Some(file_path)) = (&self.audio, &self.now_playing) {
+                    audio.send(AudioCommand::SetSpeed(self.settings.audiobooks.speed_for(file_path)));
+                }
+                Command::none()
+            }
+            Message::JukeboxPressed => {
+                self.jukebox = Jukebox::ChoosingPin(String::new());
+                Command::none()
+            }
+            Message::JukeboxPinChanged(pin) => {
+                match &mut self.jukebox {
+                    Jukebox::Off => {}
+                    Jukebox::ChoosingPin(entry) | Jukebox::Locked { entry, .. } => *entry = pin,
+                }
+                Command::none()
+            }
+            Message::StartJukebox => {
+                let Jukebox::ChoosingPin(pin) = &self.jukebox else { return Command::none() };
+                if pin.chars().count() < MIN_PIN_LENGTH {
+                    return self.notify(format!("Pick a PIN of at least {} characters", MIN_PIN_LENGTH));
+                }
+                tracing::info!("Jukebox mode on");
+                self.jukebox = Jukebox::Locked { pin: pin.clone(), entry: String::new() };
+                // Nothing half-finished should be left for guests to confirm
+                self.selected.clear();
+                self.selection_anchor = None;
+                self.cursor = None;
+                self.pending_removal.clear();
+                self.pending_conversion.clear();
+                self.pending_restore = None;
+                Command::none()
+            }
+            Message::CancelJukebox => {
+                self.jukebox = Jukebox::Off;
+                Command::none()
+            }
+            Message::UnlockJukebox => {
+                let Jukebox::Locked { pin, entry } = &mut self.jukebox else { return Command::none() };
+                if entry == pin {
+                    tracing::info!("Jukebox mode off");
+                    self.jukebox = Jukebox::Off;
+                    Command::none()
+                } else {
+                    entry.clear();
+                    self.notify("Wrong PIN".to_string())
+                }
+            }
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        // Only redraw the visualisations while there's something to show
+        let frames = if self.visualisations_enabled() && self.now_playing.is_some() && !self.paused {
+            frame_subscription()
+        } else {
+            Subscription::none()
+        };
+        Subscription::batch([
+            iced::subscription::events_with(|event, status| match event {
+                Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => Some(Message::ModifiersChanged(modifiers)),
+                Event::Keyboard(keyboard::Event::KeyPressed { key_code: keyboard::KeyCode::F11, .. }) => Some(Message::ToggleFullscreen),
+                Event::Keyboard(keyboard::Event::KeyPressed { key_code: keyboard::KeyCode::Escape, .. }) => Some(Message::EscapePressed),
+                Event::Keyboard(keyboard::Event::KeyPressed { key_code: keyboard::KeyCode::Z, modifiers })
+                    if modifiers.command() && status == event::Status::Ignored =>
+                {
+                    Some(Message::Undo)
+                }
+                // Anything else a text field has taken, like Space or Enter while typing, is left to it
+                Event::Keyboard(keyboard::Event::KeyPressed { key_code, modifiers }) if status == event::Status::Ignored => match key_code {
+                    keyboard::KeyCode::Tab => Some(Message::FocusNext(modifiers.shift())),
+                    keyboard::KeyCode::Up => Some(Message::MoveCursor(-1)),
+                    keyboard::KeyCode::Down => Some(Message::MoveCursor(1)),
+                    keyboard::KeyCode::PageUp => Some(Message::MoveCursor(-PAGE_ROWS)),
+                    keyboard::KeyCode::PageDown => Some(Message::MoveCursor(PAGE_ROWS)),
+                    keyboard::KeyCode::Enter | keyboard::KeyCode::NumpadEnter => Some(Message::ActivateCursor),
+                    keyboard::KeyCode::Space | keyboard::KeyCode::PlayPause => Some(Message::TogglePause),
+                    keyboard::KeyCode::NextTrack => Some(Message::PlayNext),
+                    keyboard::KeyCode::MediaStop => Some(Message::StopPlayback),
+                    key_code if modifiers.command() => Tab::for_shortcut(key_code).map(Message::TabSelected),
+                    _ => None,
+                },
+                Event::Window(iced::window::Event::Resized { width, height }) => Some(Message::WindowResized(width, height)),
+                Event::Window(iced::window::Event::Moved { x, y }) => Some(Message::WindowMoved(x, y)),
+                Event::Window(iced::window::Event::CloseRequested) => Some(Message::CloseRequested),
+                _ => None,
+            }),
+            audio_subscription(),
+            converter_subscription(),
+            frames,
+        ])
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        if let Some(step) = self.setup {
+            return self.with_toasts(self.setup_view(step));
+        }
+        if matches!(self.jukebox, Jukebox::Locked { .. }) {
+            return self.with_toasts(self.jukebox_view());
+        }
+        if let Some(dialog) = &self.properties {
+            return self.with_toasts(dialog.view());
+        }
+        if let Some(viewer) = &self.cover {
+            return self.with_toasts(self.cover_view(viewer));
+        }
+        if self.fullscreen {
+            return self.fullscreen_view();
+        }
+
+        let panes = PaneGrid::new(&self.panes, |_pane, kind, _is_maximized| {
+            pane_grid::Content::new(match kind {
+                PaneKind::Library => self.browse_pane(),
+                PaneKind::NowPlaying => self.now_playing_pane(),
+            })
+        })
+        .spacing(20)
+        .on_resize(10, Message::PaneResized)
+        .height(Length::Fill);
+
+        self.with_toasts(panes.into())
+    }
+}
+
+impl MusicJester {
+    fn with_toasts<'a>(&'a self, content: Element<'a, Message>) -> Element<'a, Message> {
+        let toasts = self.toasts.iter().fold(Column::new().spacing(5), |col, toast| {
+            let content = Row::new()
+                .spacing(10)
+                .push(Text::new(&toast.message).style(ERROR_COLOR).width(Length::Fill))
+                .push(icon_button("×", "Dismiss", Some(Message::DismissToast(toast.id))));
+            col.push(Container::new(content).padding(10).width(Length::Fill).style(theme::Container::Box))
+        });
+
+        Column::new()
+            .spacing(10)
+            .push(content)
+            .push(toasts)
+            .padding(20)
+            .into()
+    }
+
+    /// The first-run setup: music folders, theme and output, then the first scan.
+    fn setup_view(&self, step: SetupStep) -> Element<'_, Message> {
+        let back = |to| button("Back").style(theme::Button::Secondary).on_press(Message::SetupStepSelected(to));
+        let next = |to| button("Next").on_press(Message::SetupStepSelected(to));
+        let (content, buttons): (Column<'_, Message>, Row<'_, Message>) = match step {
+            SetupStep::Folders => {
+                let mut folders = Column::new()
+                    .spacing(10)
+                    .push(Text::new("Where's your music? Add every folder it's in; they're scanned for audio files, subfolders and all."));
+                for (index, folder) in self.settings.library_folders.iter().enumerate() {
+                    folders = folders.push(
+                        Row::new()
+                            .spacing(10)
+                            .align_items(Alignment::Center)
+                            .push(Text::new(folder.display().to_string()).width(Length::Fill))
+                            .push(icon_button("×", "Remove", Some(Message::SetupRemoveFolder(index)))),
+                    );
+                }
+                folders = folders.push(button("Add folder…").on_press(Message::SetupAddFolder));
+                let mut next = button("Next");
+                if !self.settings.library_folders.is_empty() {
+                    next = next.on_press(Message::SetupStepSelected(SetupStep::Theme));
+                }
+                let skip = button("Skip setup").style(theme::Button::Secondary).on_press(Message::SetupFinished);
+                (folders, Row::new().spacing(10).push(skip).push(next))
+            }
+            SetupStep::Theme => {
+                let themes = ThemeChoice::ALL.iter().fold(Column::new().spacing(10).push(Text::new("How should it look?")), |themes, &choice| {
+                    themes.push(radio(choice.to_string(), choice, Some(self.settings.theme), Message::ThemeSelected))
+                });
+                (themes, Row::new().spacing(10).push(back(SetupStep::Folders)).push(next(SetupStep::Output)))
+            }
+            SetupStep::Output => {
+                let output = Column::new()
+                    .spacing(10)
+                    .push(Text::new("Where should it play? The system default follows whatever the computer's set to use."))
+                    .push(pick_list(&self.output_devices[..], Some(OutputDevice(self.settings.output_device.clone())), Message::OutputDeviceSelected));
+                (output, Row::new().spacing(10).push(back(SetupStep::Theme)).push(button("Scan my music").on_press(Message::SetupStepSelected(SetupStep::Scanning))))
+            }
+            SetupStep::Scanning => {
+                let read = self.tags_total - self.pending_tags.len();
+                let progress = if self.tags_total == 0 { 0.0 } else { read as f32 / self.tags_total as f32 };
+                let scanning = Column::new()
+                    .spacing(10)
+                    .push(Text::new("Finding your music. The library opens once it's done."))
+                    .push(progress_bar(0.0..=1.0, progress).height(Length::Fixed(16.0)))
+                    .push(Text::new(&self.scan_status));
+                (scanning, Row::new().push(button("Carry on in the background").on_press(Message::SetupFinished)))
+            }
+        };
+        let page = Column::new()
+            .spacing(20)
+            .max_width(640)
+            .push(Text::new("Welcome to Music Jester").size(32))
+            .push(Text::new(format!("Step {} of {}: {}", step as usize + 1, SetupStep::ALL.len(), step)).size(18))
+            .push(content)
+            .push(buttons);
+        Container::new(page).width(Length::Fill).height(Length::Fill).center_x().center_y().into()
+    }
+
+    /// The tab bar down the side, and whichever tab is open.
+    fn browse_pane(&self) -> Element<'_, Message> {
+        let tabs = Tab::ALL.iter().fold(Column::new().spacing(5).width(Length::Fixed(110.0)), |tabs, &tab| {
+            let style = if tab == self.tab { theme::Button::Primary } else { theme::Button::Text };
+            tabs.push(button(Text::new(tab.to_string())).on_press(Message::TabSelected(tab)).style(style).padding(8).width(Length::Fill))
+        });
+        let content = match self.tab {
+            Tab::Library | Tab::Albums | Tab::Artists | Tab::Playlists => self.library_pane(),
+            Tab::History => self.history_tab(),
+            Tab::Queue => self.queue_tab(),
+            Tab::Jellyfin => self.jellyfin_tab(),
+            Tab::Settings => self.settings_tab(),
+        };
+        Row::new().spacing(15).push(tabs).push(content).into()
+    }
+
+    fn library_pane(&self) -> Element<'_, Message> {
+        let files = self.listed_files();
+        let files_list = if let Some((groups, none_yet)) = self.group_list() {
+            self.group_rows(groups, none_yet)
+        } else if let Some(rows) = self.headed_track_rows().filter(|_| !files.is_empty() && self.search_results.is_none()) {
+            rows
+        } else if files.is_empty() {
+            Column::new().push(Text::new(match self.track_list {
+                _ if self.search_results.is_some() => "No tracks match",
+                TrackList::Album(_) => "This album is empty",
+                TrackList::Artist(_) => "No tracks by this artist",
+                TrackList::Composer(_) => "No tracks by this composer",
+                TrackList::Playlist(_) => "This playlist is empty",
+                TrackList::Skipped => "Nothing has been skipped often yet",
+                _ => "No audio files found yet",
+            }))
+        } else {
+            self.track_rows(&files, Message::TrackClicked)
+        };
+    
+        let files_scrollable = scrollable(Container::new(files_list).width(Length::Fill))
+            .id(track_list_id())
+            .on_scroll(Message::TrackListScrolled)
+            .height(Length::Fill);
+    
+        let mut left_column = Column::new().spacing(10);
+        if self.tab == Tab::Library {
+            let folder_display = Text::new(if self.selected_folder.is_empty() {
+                "No folder selected".to_string()
+            } else {
+                format!("Selected folder: {}", self.selected_folder)
+            });
+            left_column = left_column
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(button("Select Folder").on_press(Message::FolderButtonPressed))
+                        .push(self.recent_folders())
+                        .push(button("Open Files…").on_press(Message::OpenFilesPressed)),
+                )
+                .push(folder_display)
+                .push(Text::new(&self.scan_status));
+        }
+        left_column = left_column.push(self.search_input());
+
+        let mut show = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new(self.track_list.to_string()).size(24).width(Length::Fill));
+        match self.track_list {
+            TrackList::Album(_) => show = show.push(button("Back to albums").on_press(Message::TrackListSelected(TrackList::Albums))),
+            TrackList::Artist(_) => show = show.push(button("Back to artists").on_press(Message::TrackListSelected(TrackList::Artists))),
+            TrackList::Composer(_) => {
+                show = show.push(button("Back to composers").on_press(Message::TrackListSelected(TrackList::Composers)))
+            }
+            TrackList::Playlist(_) | TrackList::Skipped => {
+                show = show.push(button("Back to playlists").on_press(Message::TrackListSelected(TrackList::Playlists)))
+            }
+            // Composers are artists too, for classical music
+            TrackList::Artists => show = show.push(button("Composers").on_press(Message::TrackListSelected(TrackList::Composers))),
+            TrackList::Composers => show = show.push(button("Artists").on_press(Message::TrackListSelected(TrackList::Artists))),
+            _ => {}
+        }
+        if self.now_playing.is_some() {
+            show = show.push(button("Show playing").on_press(Message::JumpToPlaying));
+        }
+        left_column = left_column.push(show);
+
+        // Batch actions for whatever is selected
+        if !self.selected.is_empty() && self.pending_removal.is_empty() && self.pending_conversion.is_empty() {
+            let mut selection_bar = Row::new()
+                .spacing(10)
+                .push(Text::new(format!("{} selected", self.selected.len())))
+                .push(button("Add to queue").on_press(Message::EnqueueSelected));
+            if !self.db.playlists.is_empty() {
+                let playlists: Vec<String> = self.db.playlists.iter().map(|playlist| playlist.name.clone()).collect();
+                selection_bar = selection_bar.push(pick_list(playlists, None::<String>, Message::AddSelectedToPlaylist).placeholder("Add to playlist…"));
+            }
+            selection_bar = selection_bar.push(button("Convert…").on_press(Message::ConvertRequested)).push(button("Remove…").on_press(Message::RemoveRequested));
+            if self.track_list == TrackList::Skipped {
+                selection_bar = selection_bar.push(button("Rate down").on_press(Message::RateDownSelected));
+            }
+            if let Some(file_path) = self.selected.iter().next().filter(|_| self.selected.len() == 1) {
+                selection_bar = selection_bar.push(button("Properties…").on_press(Message::ShowProperties(file_path.clone())));
+            }
+            left_column = left_column.push(selection_bar.push(button("Clear selection").on_press(Message::ClearSelection)));
+        }
+
+        if let Some(change) = self.journal.last().filter(|_| !matches!(self.jukebox, Jukebox::Locked { .. })) {
+            let undo = button("Undo last change").on_press(Message::Undo);
+            left_column = left_column.push(tooltip(undo, format!("Undo {}", change), tooltip::Position::Right).style(theme::Container::Box));
+        }
+
+        // Ask before touching anything on disk
+        if !self.pending_removal.is_empty() {
+            let prompt = match self.pending_removal.as_slice() {
+                [file_path] => {
+                    let filename = file_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                    format!("Remove \"{}\"?", filename)
+                }
+                files => format!("Remove {} tracks?", files.len()),
+            };
+            let confirmation = Column::new()
+                .spacing(5)
+                .push(Text::new(prompt))
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(button("Move to trash").on_press(Message::MoveToTrash))
+                        .push(button("Remove from library only").on_press(Message::RemoveFromLibrary))
+                        .push(button("Cancel").on_press(Message::CancelRemoval)),
+                );
+            left_column = left_column.push(confirmation);
+        }
+
+        if !self.pending_conversion.is_empty() {
+            left_column = left_column.push(self.convert_options());
+        }
+        if !self.conversions.is_empty() {
+            left_column = left_column.push(self.conversion_progress());
+        }
+
+        if !self.track_list.is_groups() {
+            left_column = left_column.push(self.column_headings());
+        }
+        left_column.push(files_scrollable).into()
+    }
+
+    /// The albums, artists, composers or playlists to pick from, and what to say
+    /// when there are none, if that's what the list is showing.
+    fn group_list(&self) -> Option<(Vec<Group>, &'static str)> {
+        let people = |people: &[Person], opens: fn(String) -> TrackList| {
+            people.iter().map(|person| Group { name: person.name.clone(), tracks: person.tracks.len(), opens: opens(person.name.clone()) }).collect()
+        };
+        match self.track_list {
+            TrackList::Albums => Some((
+                self.albums
+                    .iter()
+                    .map(|album| Group { name: album.key.to_string(), tracks: album.tracks.len(), opens: TrackList::Album(album.key.clone()) })
+                    .collect(),
+                "No tagged albums found yet",
+            )),
+            TrackList::Artists => Some((people(&self.artists, TrackList::Artist), "No tracks with an artist found yet")),
+            TrackList::Composers => Some((people(&self.composers, TrackList::Composer), "No tracks with a composer found yet")),
+            TrackList::Playlists => Some((
+                self.db
+                    .playlists
+                    .iter()
+                    .map(|playlist| Group { name: playlist.name.clone(), tracks: playlist.tracks.len(), opens: TrackList::Playlist(playlist.name.clone()) })
+                    // Only there once there's something in it
+                    .chain(Some(self.db.frequently_skipped().len()).filter(|&tracks| tracks > 0).map(|tracks| Group {
+                        name: TrackList::Skipped.to_string(),
+                        tracks,
+                        opens: TrackList::Skipped,
+                    }))
+                    .collect(),
+                "No playlists yet; import some under Settings, or add tracks from History",
+            )),
+            _ => None,
+        }
+    }
+
+    /// Rows for `files`, of which only the ones that can be on screen are built;
+    /// spacers stand in for the rest. The scrollable reports a relative offset, and
+    /// its viewport is at most the window height, so the range built always covers
+    /// what is visible.
+    fn track_rows(&self, files: &[PathBuf], on_press: fn(PathBuf) -> Message) -> Column<'_, Message> {
+        let total_height = files.len() as f32 * TRACK_ROW_HEIGHT;
+        let window_height = self.settings.layout.window_size.1 as f32;
+        let top = self.list_scroll.y * (total_height - window_height).max(0.0);
+        let bottom = self.list_scroll.y * total_height + window_height;
+        let first = ((top / TRACK_ROW_HEIGHT) as usize).min(files.len());
+        let last = ((bottom / TRACK_ROW_HEIGHT).ceil() as usize).clamp(first, files.len());
+
+        let mut col = Column::new()
+            .push(Space::with_height(Length::Fixed(first as f32 * TRACK_ROW_HEIGHT)));
+        for file in &files[first..last] {
+            col = col.push(self.track_row(file, on_press));
+        }
+        col.push(Space::with_height(Length::Fixed(
+            (files.len() - last) as f32 * TRACK_ROW_HEIGHT,
+        )))
+    }
+
+    fn track_row(&self, file: &Path, on_press: fn(PathBuf) -> Message) -> Element<'_, Message> {
+        let (info, record) = (self.track_info.get(file), self.db.tracks.get(file));
+        let style = if self.selected.contains(file) {
+            theme::Button::Primary
+        } else if self.now_playing.as_deref() == Some(file) {
+            theme::Button::Positive
+        } else {
+            theme::Button::Secondary
+        };
+        let mut row = Row::new().spacing(5);
+        for column in self.shown_columns() {
+            row = row.push(Text::new(column.cell(file, info, record)).width(column_width(column)));
+        }
+        if let Some(error) = self.track_errors.get(file) {
+            let badge = Text::new("!").style(ERROR_COLOR);
+            row = row.push(tooltip(badge, error, tooltip::Position::Left).style(theme::Container::Box));
+        }
+        button(row)
+            .on_press(on_press(file.to_path_buf()))
+            .style(style)
+            .padding(5)
+            .width(Length::Fill)
+            .height(Length::Fixed(TRACK_ROW_HEIGHT))
+            .into()
+    }
+
+    fn shown_columns(&self) -> impl Iterator<Item = ListColumn> + '_ {
+        self.settings.columns.iter().filter(|slot| slot.shown).map(|slot| slot.column)
+    }
+
+    /// Names of the shown columns, lined up with the rows below.
+    fn column_headings(&self) -> Element<'_, Message> {
+        self.shown_columns()
+            .fold(Row::new().spacing(5).padding([0, 5]), |row, column| row.push(Text::new(column.label()).size(14).width(column_width(column))))
+            .into()
+    }
+
+    /// An album's tracks headed by disc for multi-disc sets, an artist's by album,
+    /// or a composer's by work. `None` for other lists, which have no headings.
+    /// These are short enough to build every row.
+    fn headed_track_rows(&self) -> Option<Column<'_, Message>> {
+        let (tracks, headings): (&[PathBuf], Vec<Option<String>>) = match &self.track_list {
+            TrackList::Album(key) => {
+                let album = self.albums.iter().find(|album| &album.key == key)?;
+                let multi_disc = !discs(album, &self.track_info).is_empty();
+                let headings = album.tracks.iter().map(|file| multi_disc.then(|| format!("Disc {}", disc_of(file, &self.track_info))));
+                (&album.tracks, headings.collect())
+            }
+            TrackList::Artist(name) => {
+                let artist = self.artists.iter().find(|artist| &artist.name == name)?;
+                let headings = artist.tracks.iter().map(|file| {
+                    let album = self.track_info.get(file).and_then(|info| info.album.clone());
+                    Some(album.unwrap_or_else(|| "Other tracks".to_string()))
+                });
+                (&artist.tracks, headings.collect())
+            }
+            TrackList::Composer(name) => {
+                let composer = self.composers.iter().find(|composer| &composer.name == name)?;
+                let headings = composer.tracks.iter().map(|file| {
+                    let work = self.track_info.get(file).and_then(|info| info.work.clone());
+                    Some(work.unwrap_or_else(|| "Other works".to_string()))
+                });
+                (&composer.tracks, headings.collect())
+            }
+            _ => return None,
+        };
+
+        let mut col = Column::new();
+        let mut last_heading = None;
+        for (file, heading) in tracks.iter().zip(headings) {
+            if heading.is_some() && heading != last_heading {
+                col = col.push(Container::new(Text::new(heading.clone().unwrap_or_default()).size(18)).padding([10, 5, 5, 5]));
+            }
+            last_heading = heading;
+            col = col.push(self.track_row(file, Message::TrackClicked));
+        }
+        Some(col)
+    }
+
+    /// One button per group, narrowed down by the search.
+    fn group_rows(&self, groups: Vec<Group>, none_yet: &str) -> Column<'_, Message> {
+        let query = self.search.to_lowercase();
+        let (mut total, mut shown) = (0, 0);
+        let mut column = Column::new().spacing(2);
+        for Group { name, tracks, opens } in groups {
+            total += 1;
+            if !query.split_whitespace().all(|word| name.to_lowercase().contains(word)) {
+                continue;
+            }
+            let row = Row::new().spacing(5).push(Text::new(name).width(Length::Fill)).push(Text::new(format!("{} tracks", tracks)));
+            shown += 1;
+            column = column.push(
+                button(row)
+                    .on_press(Message::TrackListSelected(opens))
+                    .style(theme::Button::Secondary)
+                    .padding(5)
+                    .width(Length::Fill),
+            );
+        }
+        match (total, shown) {
+            (0, _) => Column::new().push(Text::new(none_yet.to_string())),
+            (_, 0) => Column::new().push(Text::new("Nothing matches")),
+            _ => column,
+        }
+    }
+
+    fn search_input(&self) -> Element<'_, Message> {
+        text_input("Search titles, artists and file names", &self.search)
+            .on_input(Message::SearchChanged)
+            .style(self.text_input_style())
+            .into()
+    }
+
+    /// Text fields get a heavier outline in high contrast mode.
+    fn text_input_style(&self) -> theme::TextInput {
+        match self.settings.theme {
+            ThemeChoice::HighContrast => contrast::text_input_style(),
+            _ => theme::TextInput::Default,
+        }
+    }
+
+    /// "Artist - Title" from the tags, or the file name if there aren't any.
+    fn track_name(&self, file_path: &Path) -> String {
+        let info = self.track_info.get(file_path);
+        match info.and_then(|info| info.title.as_ref()) {
+            Some(title) => match info.and_then(|info| info.artist.as_ref()) {
+                Some(artist) => format!("{} - {}", artist, title),
+                None => title.clone(),
+            },
+            None => display_name(file_path),
+        }
+    }
+
+    /// Tracks waiting to play, with remove buttons unless guests are looking.
+    fn up_next(&self, editable: bool) -> Element<'_, Message> {
+        let mut list = Column::new().spacing(5);
+        for (index, file_path) in self.queue.iter().enumerate() {
+            let mut row = Row::new()
+                .spacing(5)
+                .align_items(Alignment::Center)
+                .push(Text::new(format!("{}. {}", index + 1, self.track_name(file_path))).size(14).width(Length::Fill));
+            if editable {
+                row = row.push(icon_button("×", "Remove from queue", Some(Message::RemoveFromQueue(index))));
+            }
+            list = list.push(row);
+        }
+        if self.queue.is_empty() {
+            list = list.push(Text::new("Nothing queued").size(14));
+        }
+        let mut header = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Up next").width(Length::Fill));
+        if editable {
+            header = header
+                .push(checkbox("Auto-DJ", self.settings.auto_dj.enabled, Message::AutoDjToggled))
+                .push(pick_list(&AutoDjRule::ALL[..], Some(self.settings.auto_dj.rule), Message::AutoDjRuleSelected));
+        }
+        Column::new()
+            .spacing(5)
+            .push(header)
+            // The queue tab has room to spare; the jukebox shares it with the art
+            .push(scrollable(list).height(if editable { Length::Fill } else { Length::Fixed(120.0) }))
+            .into()
+    }
+
+    /// Saving the queue under a name, and the queues saved so far.
+    fn snapshots_panel(&self) -> Element<'_, Message> {
+        let toggle = button(if self.show_snapshots { "Hide saved queues" } else { "Saved queues…" }).on_press(Message::SnapshotsPressed);
+        if !self.show_snapshots {
+            return toggle.into();
+        }
+        let mut save = button("Save queue");
+        if !self.snapshot_name.trim().is_empty() && !self.queue.is_empty() {
+            save = save.on_press(Message::SaveQueueSnapshot);
+        }
+        let mut list = Column::new().spacing(5);
+        for snapshot in &self.db.queue_snapshots {
+            list = list.push(
+                Row::new()
+                    .spacing(5)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(format!("{} ({} tracks)", snapshot.name, snapshot.tracks.len())).size(14).width(Length::Fill))
+                    .push(button("Load").on_press(Message::LoadQueueSnapshot(snapshot.name.clone())).style(theme::Button::Secondary))
+                    .push(icon_button("×", "Delete saved queue", Some(Message::DeleteQueueSnapshot(snapshot.name.clone())))),
+            );
+        }
+        if self.db.queue_snapshots.is_empty() {
+            list = list.push(Text::new("No saved queues yet").size(14));
+        }
+        Column::new()
+            .spacing(5)
+            .push(toggle)
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        text_input("Name, e.g. Last weekend", &self.snapshot_name)
+                            .on_input(Message::SnapshotNameChanged)
+                            .style(self.text_input_style())
+                            .on_submit(Message::SaveQueueSnapshot),
+                    )
+                    .push(save),
+            )
+            .push(scrollable(list).height(Length::Fixed(100.0)))
+            .into()
+    }
+
+    /// Party mode: search the library and queue things up, nothing else.
+    fn jukebox_view(&self) -> Element<'_, Message> {
+        let files = self.listed_files();
+        let results = if files.is_empty() {
+            Column::new().push(Text::new("No tracks match"))
+        } else {
+            self.track_rows(&files, Message::EnqueueTrack)
+        };
+        let library = Column::new()
+            .spacing(10)
+            .width(Length::FillPortion(3))
+            .push(Text::new("Jukebox").size(32))
+            .push(Text::new("Find a song and click it to add it to the queue"))
+            .push(self.search_input())
+            .push(
+                scrollable(Container::new(results).width(Length::Fill))
+                    .id(track_list_id())
+                    .on_scroll(Message::TrackListScrolled)
+                    .height(Length::Fill),
+            );
+
+        let title = match &self.now_playing {
+            Some(file_path) => self.track_name(file_path),
+            None => "Nothing playing".to_string(),
+        };
+        let entry = match &self.jukebox {
+            Jukebox::Locked { entry, .. } => entry.as_str(),
+            _ => "",
+        };
+        let unlock = Row::new()
+            .spacing(10)
+            .push(
+                text_input("PIN", entry)
+                    .password()
+                    .on_input(Message::JukeboxPinChanged)
+                    .style(self.text_input_style())
+                    .on_submit(Message::UnlockJukebox),
+            )
+            .push(button("Unlock").on_press(Message::UnlockJukebox));
+        let now_playing = Column::new()
+            .spacing(10)
+            .width(Length::FillPortion(2))
+            .push(self.album_art_image().width(Length::Fixed(270.0)).height(Length::Fixed(270.0)))
+            .push(Text::new(title).size(24))
+            .push(self.up_next(false))
+            .push(Space::with_height(Length::Fill))
+            .push(unlock);
+
+        Row::new().spacing(20).push(library).push(now_playing).into()
+    }
+
+    fn convert_options(&self) -> Element<'_, Message> {
+        let options = &self.settings.convert;
+        let mut format = Row::new()
+            .spacing(10)
+            .align_items(Alignment::Center)
+            .push(Text::new(format!("Convert {} tracks to", self.pending_conversion.len())))
+            .push(pick_list(&Format::ALL[..], Some(options.format), Message::ConvertFormatSelected));
+        if options.format.is_lossless() {
+            format = format.push(Text::new("(lossless)"));
+        } else {
+            format = format.push(pick_list(&Quality::ALL[..], Some(options.quality), Message::ConvertQualitySelected));
+        }
+
+        let destination = match &options.destination {
+            Some(folder) => format!("Save to: {}", folder.display()),
+            None => "Pick a folder to save to".to_string(),
+        };
+        let mut start = button("Convert");
+        if options.destination.is_some() {
+            start = start.on_press(Message::StartConversion);
+        }
+
+        Column::new()
+            .spacing(5)
+            .push(format)
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(destination))
+                    .push(button("Choose…").on_press(Message::ConvertDestinationPressed)),
+            )
+            .push(Row::new().spacing(10).push(start).push(button("Cancel").on_press(Message::CancelConversion)))
+            .into()
+    }
+
+    fn conversion_progress(&self) -> Element<'_, Message> {
+        let rows = self.conversions.iter().fold(Column::new().spacing(5), |col, conversion| {
+            let status: Element<'_, Message> = match &conversion.result {
+                None => progress_bar(0.0..=1.0, conversion.progress).height(Length::Fixed(10.0)).width(Length::Fixed(120.0)).into(),
+                Some(Ok(_)) => Text::new("Done").into(),
+                Some(Err(e)) => tooltip(Text::new("Failed").style(ERROR_COLOR), e, tooltip::Position::Left)
+                    .style(theme::Container::Box)
+                    .into(),
+            };
+            col.push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(&conversion.name).width(Length::Fill))
+                    .push(status),
+            )
+        });
+
+        let mut header = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Conversions").width(Length::Fill));
+        if self.conversions.iter().any(|conversion| conversion.result.is_some()) {
+            header = header.push(button("Clear finished").on_press(Message::ClearFinishedConversions));
+        }
+        Column::new().spacing(5).push(header).push(rows).into()
+    }
+
+    fn now_playing_pane(&self) -> Element<'_, Message> {
+        // Place album art above the controls, clicking it opens it at full size
+        let album_art_view = mouse_area(self.album_art_image().width(Length::Fixed(270.0)).height(Length::Fixed(270.0)))
+            .on_press(Message::ViewCover);
+
+        // Display song title and artist if available
+        let mut song_info = if let (Some(title), Some(artist)) = (self.song_title.clone(), self.artist.clone()) {
+            Column::new()
+                .spacing(5)
+                .push(Text::new(format!("Title: {}", title)))
+                .push(Text::new(format!("Artist: {}", artist)))
+        } else {
+            Column::new().push(Text::new("No metadata available"))
+        };
+        // Classical tracks are lost without who wrote them and which piece they're from
+        if let Some(info) = self.now_playing.as_ref().and_then(|file_path| self.track_info.get(file_path)) {
+            let work = work_and_movement(info);
+            let credits = [("Composer", info.composer.clone()), ("Performer", info.performer.clone()), ("Work", Some(work).filter(|work| !work.is_empty()))];
+            for (label, value) in credits {
+                if let Some(value) = value {
+                    song_info = song_info.push(Text::new(format!("{}: {}", label, value)));
+                }
+            }
+        }
+    
+        let spectrum = canvas(SpectrumBars { bars: self.spectrum.bars() })
+            .width(Length::Fill)
+            .height(Length::Fixed(60.0));
+
+        // Modify the controls to be in a horizontal row
+        let controls = if self.now_playing.is_some() {
+            let pause_or_resume = if self.paused {
+                button("Resume").on_press(Message::ResumePlayback)
+            } else {
+                button("Pause").on_press(Message::PausePlayback)
+            };
+            let mut next = button("Next");
+            if !self.queue.is_empty() {
+                next = next.on_press(Message::PlayNext);
+            }
+            let buttons = Row::new()
+                .spacing(10)
+                .push(pause_or_resume)
+                .push(button("Stop").on_press(Message::StopPlayback))
+                .push(next);
+
+            let gain_db = self.now_playing.as_deref().map_or(0.0, |file_path| self.db.gain_db(file_path));
+            let mut gain = Row::new()
+                .spacing(10)
+                .align_items(Alignment::Center)
+                .push(Text::new("Track gain"))
+                .push(
+                    slider(MIN_TRACK_GAIN..=MAX_TRACK_GAIN, gain_db, Message::TrackGainChanged)
+                        .on_release(Message::TrackGainReleased)
+                        .step(0.5),
+                )
+                .push(Text::new(format!("{:+.1} dB", gain_db)));
+            if self.limiting {
+                let limit = Text::new("LIMIT").size(14).style(WARNING_COLOR);
+                let explanation = "The preamp and track gain push this track past full scale, so its peaks are being turned down";
+                gain = gain.push(tooltip(limit, explanation, tooltip::Position::Left).style(theme::Container::Box));
+            }
+
+            let mut controls = Column::new().spacing(10).push(self.seek_bar());
+            if let Some(chapters) = self.chapter_picker() {
+                controls = controls.push(chapters);
+            }
+            controls = controls.push(buttons).push(gain);
+            if self.settings.features.level_meters {
+                let mut meters = Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(canvas(&self.meters).width(Length::Fill).height(Length::Fixed(16.0)));
+                if self.meters.clipped() {
+                    let clip = Text::new("CLIP").size(14).style(ERROR_COLOR);
+                    let explanation = "This track has samples at full scale";
+                    meters = meters.push(tooltip(clip, explanation, tooltip::Position::Left).style(theme::Container::Box));
+                }
+                controls = controls.push(meters);
+            }
+            controls
+        } else {
+            Column::new().push(Text::new("No audio playing"))
+        };
+
+        let bookmarks = self.bookmarks_panel();
+
+        let unavailable = self.unavailable.as_ref().map(|file_path| {
+            Column::new()
+                .spacing(5)
+                .push(
+                    Text::new(format!("{} can't be reached. If it's on a network share, it may have dropped.", display_name(file_path)))
+                        .style(WARNING_COLOR),
+                )
+                .push(Text::new("Trying again every few seconds; it'll carry on from where it stopped.").size(14))
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(button("Retry now").on_press(Message::RetrySource))
+                        .push(button("Skip").on_press(Message::SkipUnavailable)),
+                )
+        });
+
+        let volume = Row::new()
+            .spacing(10)
+            .push(Text::new("Volume"))
+            .push(slider(0.0..=1.0, self.settings.volume, Message::VolumeChanged).step(0.01));
+
+        let mut column = Column::new()
+            .spacing(10)
+            .push(album_art_view)  // Place album art above the controls
+            .push(song_info);      // Add song info below the album art
+        if self.settings.features.spectrum {
+            column = column.push(spectrum);
+        }
+        column = column.push(Text::new("Playback Controls"));
+        if let Some(unavailable) = unavailable {
+            column = column.push(unavailable);
+        }
+        column = column.push(controls).push(bookmarks).push(volume);
+        if let Some(lyrics) = &self.lyrics {
+            column = column.push(Text::new("Lyrics")).push(Text::new(lyrics.as_str()).size(14));
+        }
+        column.into()
+    }
+
+    /// What's been played, newest first, under a heading for each day.
+    fn history_tab(&self) -> Element<'_, Message> {
+        let playlists: Vec<String> = self.db.playlists.iter().map(|playlist| playlist.name.clone()).collect();
+        let playlist = Row::new()
+            .spacing(10)
+            .align_items(Alignment::Center)
+            .push(Text::new("Add to playlist"))
+            .push(
+                text_input("New or existing playlist", &self.history_playlist)
+                    .on_input(Message::HistoryPlaylistChanged)
+                    .style(self.text_input_style()),
+            )
+            .push(pick_list(playlists, None::<String>, Message::HistoryPlaylistChanged).placeholder("Existing…"));
+
+        let can_add = !self.history_playlist.trim().is_empty();
+        let mut list = Column::new().spacing(2);
+        let mut last_day = None;
+        for play in self.db.history.iter().rev().take(HISTORY_SHOWN) {
+            let started = DateTime::<Local>::from(play.started);
+            let day = started.date_naive();
+            if last_day != Some(day) {
+                list = list.push(Container::new(Text::new(started.format("%A %-d %B %Y").to_string()).size(18)).padding([10, 5, 5, 5]));
+                last_day = Some(day);
+            }
+            let mut add = button("Add to playlist").style(theme::Button::Secondary);
+            if can_add {
+                add = add.on_press(Message::AddToPlaylist(play.file_path.clone()));
+            }
+            list = list.push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(started.format("%H:%M").to_string()).width(Length::Fixed(50.0)))
+                    .push(Text::new(self.track_name(&play.file_path)).width(Length::Fill))
+                    .push(button("Play again").on_press(Message::PlayAudio(play.file_path.clone())).style(theme::Button::Secondary))
+                    .push(add),
+            );
+        }
+        if self.db.history.is_empty() {
+            list = list.push(Text::new("Nothing played yet"));
+        } else if self.db.history.len() > HISTORY_SHOWN {
+            list = list.push(Text::new(format!("Showing the latest {} plays", HISTORY_SHOWN)).size(14));
+        }
+
+        Column::new()
+            .spacing(10)
+            .push(Text::new("History").size(24))
+            .push(playlist)
+            .push(scrollable(list).height(Length::Fill))
+            .into()
+    }
+
+    fn queue_tab(&self) -> Element<'_, Message> {
+        let mut shuffle = button("Shuffle library");
+        if !self.audio_files.is_empty() {
+            shuffle = shuffle.on_press(Message::ShuffleLibrary);
+        }
+        Column::new()
+            .spacing(10)
+            .push(Text::new("Queue").size(24))
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(pick_list(&ShuffleMode::ALL[..], Some(self.settings.shuffle), Message::ShuffleModeSelected))
+                    .push(shuffle),
+            )
+            .push(self.up_next(true))
+            .push(self.snapshots_panel())
+            .into()
+    }
+
+    /// Signing in to a Jellyfin server, then browsing its albums and playlists.
+    fn jellyfin_tab(&self) -> Element<'_, Message> {
+        let jellyfin = &self.settings.jellyfin;
+        let mut column = Column::new().spacing(10).push(Text::new("Jellyfin").size(24));
+        if !jellyfin.signed_in() {
+            let mut sign_in = button("Sign in");
+            if self.jellyfin_status.is_empty() && !jellyfin.server.trim().is_empty() && !jellyfin.username.trim().is_empty() {
+                sign_in = sign_in.on_press(Message::JellyfinSignIn);
+            }
+            column = column
+                .push(
+                    text_input("Server, e.g. http://192.168.1.10:8096", &jellyfin.server)
+                        .on_input(Message::JellyfinServerChanged)
+                        .style(self.text_input_style()),
+                )
+                .push(text_input("Username", &jellyfin.username).on_input(Message::JellyfinUsernameChanged).style(self.text_input_style()))
+                .push(
+                    text_input("Password", &self.jellyfin_password)
+                        .password()
+                        .on_input(Message::JellyfinPasswordChanged)
+                        .style(self.text_input_style())
+                        .on_submit(Message::JellyfinSignIn),
+                )
+                .push(sign_in);
+        } else {
+            let collections = Collection::ALL.iter().fold(Row::new().spacing(10), |row, &collection| {
+                let style = if collection == self.jellyfin_collection { theme::Button::Primary } else { theme::Button::Secondary };
+                row.push(button(Text::new(collection.to_string())).style(style).on_press(Message::JellyfinBrowse(collection)))
+            });
+            column = column
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Alignment::Center)
+                        .push(Text::new(format!("Signed in to {} as {}", jellyfin.server, jellyfin.username)))
+                        .push(button("Sign out").style(theme::Button::Secondary).on_press(Message::JellyfinSignOut)),
+                )
+                .push(checkbox("Convert to MP3 on the server (smaller downloads)", jellyfin.transcode, Message::JellyfinTranscodeToggled))
+                .push(collections);
+        }
+        if !self.jellyfin_status.is_empty() {
+            column = column.push(Text::new(&self.jellyfin_status));
+        }
+
+        let mut list = Column::new().spacing(2);
+        match &self.jellyfin_open {
+            Some((item, tracks)) => {
+                column = column.push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Alignment::Center)
+                        .push(button("Back").on_press(Message::JellyfinBack))
+                        .push(Text::new(item.to_string()).size(18)),
+                );
+                for track in tracks {
+                    let title = track.info.title.as_deref().unwrap_or("Untitled");
+                    let label = match &track.info.artist {
+                        Some(artist) => format!("{} – {}", title, artist),
+                        None => title.to_string(),
+                    };
+                    list = list.push(
+                        Row::new()
+                            .spacing(10)
+                            .align_items(Alignment::Center)
+                            .push(Text::new(label).width(Length::Fill))
+                            .push(button("Play").on_press(Message::JellyfinFetch(track.clone(), true)))
+                            .push(button("Add to queue").style(theme::Button::Secondary).on_press(Message::JellyfinFetch(track.clone(), false))),
+                    );
+                }
+            }
+            None => {
+                for item in &self.jellyfin_items {
+                    list = list.push(button(Text::new(item.to_string())).style(theme::Button::Text).on_press(Message::JellyfinOpen(item.clone())));
+                }
+            }
+        }
+        column.push(scrollable(list).height(Length::Fill)).into()
+    }
+
+    /// Preferences, and moving the library's data in and out.
+    fn settings_tab(&self) -> Element<'_, Message> {
+        let mut library: Element<'_, Message> = Row::new()
+            .spacing(10)
+            .push(button("Import library…").on_press(Message::ImportPressed))
+            .push(button("Export library…").on_press(Message::ExportPressed))
+            .push(button("Back up…").on_press(Message::BackupPressed))
+            .push(button("Restore…").on_press(Message::RestorePressed))
+            .into();
+        if let Some(backup) = &self.pending_restore {
+            let old_root = backup.library_root().map(|root| root.display().to_string()).unwrap_or_default();
+            library = Column::new()
+                .spacing(5)
+                .push(library)
+                .push(Text::new(format!("The backup's library was in {}, which isn't on this computer. Where is it now?", old_root)))
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(button("Choose folder…").on_press(Message::RestoreRootPressed))
+                        .push(button("Keep the old paths").on_press(Message::RestoreKeepingPaths))
+                        .push(button("Cancel").on_press(Message::CancelRestore)),
+                )
+                .into();
+        }
+
+        let preferences = Column::new()
+            .spacing(10)
+            .push(Text::new("Settings").size(24))
+            .push(library)
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(Text::new("Theme"))
+                    .push(pick_list(&ThemeChoice::ALL[..], Some(self.settings.theme), Message::ThemeSelected)),
+            )
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(Text::new("Output"))
+                    .push(pick_list(
+                        &self.output_devices[..],
+                        Some(OutputDevice(self.settings.output_device.clone())),
+                        Message::OutputDeviceSelected,
+                    )),
+            )
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(Text::new("Sample rate"))
+                    .push(pick_list(&SampleRate::ALL[..], Some(SampleRate(self.settings.sample_rate)), Message::SampleRateSelected))
+                    .push(Text::new("Resampling"))
+                    .push(pick_list(&ResampleQuality::ALL[..], Some(self.settings.resample_quality), Message::ResampleQualitySelected)),
+            )
+            .push(
+                tooltip(
+                    checkbox("Bit-perfect output", self.settings.bit_perfect, Message::BitPerfectToggled),
+                    "Opens the device at each track's own sample rate and channel count so nothing is resampled. \
+                     Samples are 16-bit, so 24-bit files lose their lowest 8 bits. \
+                     Volume, track gain and effects are bypassed, so turn the volume down on your DAC or amp. \
+                     On Linux pick a hw: device to skip the sound server; on Windows this uses shared mode, so \
+                     set the device's format in the sound control panel to match. If the device refuses a \
+                     track's format, it's played converted as usual.",
+                    tooltip::Position::Top,
+                )
+                .style(theme::Container::Box),
+            )
+            .push(
+                tooltip(
+                    checkbox("Normalize loudness", self.settings.normalize, Message::NormalizeToggled),
+                    "Plays tracks at their ReplayGain level. Tracks without ReplayGain tags are measured \
+                     over their first few seconds as they play and eased to the same level.",
+                    tooltip::Position::Top,
+                )
+                .style(theme::Container::Box),
+            )
+            .push(checkbox("Spectrum analyzer", self.settings.features.spectrum, Message::SpectrumToggled))
+            .push(checkbox("Level meters", self.settings.features.level_meters, Message::LevelMetersToggled))
+            .push(checkbox("Scroll to each track as it starts", self.settings.features.follow_playing, Message::FollowPlayingToggled))
+            .push(checkbox("Follow symlinks when scanning", self.settings.scan.follow_symlinks, Message::FollowSymlinksToggled))
+            .push(self.effects_panel())
+            .push(self.audiobooks_panel())
+            .push(self.hooks_panel())
+            .push(self.plugins_panel())
+            .push(self.columns_panel())
+            .push(self.jukebox_settings())
+            .push(button("Open log").on_press(Message::OpenLog));
+        scrollable(preferences).height(Length::Fill).into()
+    }
+
+    /// The effect chain, top to bottom in the order tracks go through it.
+    fn effects_panel(&self) -> Element<'_, Message> {
+        let toggle = button(if self.show_effects { "Hide effects" } else { "Effects…" }).on_press(Message::EffectsPressed);
+        if !self.show_effects {
+            return toggle.into();
+        }
+        let last = self.settings.effects.len().saturating_sub(1);
+        let mut panel = Column::new().spacing(5).push(toggle);
+        for (index, slot) in self.settings.effects.iter().enumerate() {
+            let up = icon_button("↑", "Move up", (index > 0).then(|| Message::MoveEffect(index, index - 1)));
+            let down = icon_button("↓", "Move down", (index < last).then(|| Message::MoveEffect(index, index + 1)));
+            let mut row = Row::new()
+                .spacing(5)
+                .align_items(Alignment::Center)
+                .push(checkbox(slot.effect.label(), slot.enabled, move |enabled| Message::EffectToggled(index, enabled)).width(Length::Fill));
+            if let Some((value, range, step)) = slot.effect.parameter() {
+                row = row.push(
+                    slider(range, value, move |value| Message::EffectParameterChanged(index, value))
+                        .on_release(Message::EffectParameterReleased)
+                        .step(step)
+                        .width(Length::Fixed(120.0)),
+                );
+            }
+            panel = panel.push(row.push(up).push(down));
+        }
+
+        let silence = &self.settings.silence;
+        panel = panel.push(checkbox("Skip long silences", silence.enabled, Message::SilenceTrimmingToggled));
+        if silence.enabled {
+            panel = panel
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(Text::new(format!("Quieter than {:.0} dB", silence.threshold_db)).width(Length::Fixed(180.0)))
+                        .push(
+                            slider(-80.0..=-30.0, silence.threshold_db, Message::SilenceThresholdChanged)
+                                .on_release(Message::SilenceSettingsReleased)
+                                .step(1.0),
+                        ),
+                )
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(Text::new(format!("For at least {:.1} s", silence.min_seconds)).width(Length::Fixed(180.0)))
+                        .push(
+                            slider(0.5..=10.0, silence.min_seconds, Message::SilenceLengthChanged)
+                                .on_release(Message::SilenceSettingsReleased)
+                                .step(0.5),
+                        ),
+                );
+        }
+        panel.into()
+    }
+
+    /// Which columns the track list shows, and in what order.
+    fn columns_panel(&self) -> Element<'_, Message> {
+        let toggle = button(if self.show_columns { "Hide columns" } else { "Columns…" }).on_press(Message::ColumnsPressed);
+        if !self.show_columns {
+            return toggle.into();
+        }
+        let last = self.settings.columns.len().saturating_sub(1);
+        let mut panel = Column::new().spacing(5).push(toggle);
+        for (index, slot) in self.settings.columns.iter().enumerate() {
+            let up = icon_button("↑", "Move up", (index > 0).then(|| Message::MoveColumn(index, index - 1)));
+            let down = icon_button("↓", "Move down", (index < last).then(|| Message::MoveColumn(index, index + 1)));
+            panel = panel.push(
+                Row::new()
+                    .spacing(5)
+                    .align_items(Alignment::Center)
+                    .push(checkbox(slot.column.label(), slot.shown, move |shown| Message::ColumnToggled(index, shown)).width(Length::Fill))
+                    .push(up)
+                    .push(down),
+            );
+        }
+        panel.into()
+    }
+
+    /// A one-click way back to folders picked before.
+    fn recent_folders(&self) -> Element<'_, Message> {
+        let recent: Vec<RecentFolder> = self
+            .settings
+            .recent_folders
+            .iter()
+            .filter(|folder| !self.settings.library_folders.contains(folder))
+            .map(|folder| RecentFolder(folder.clone()))
+            .collect();
+        if recent.is_empty() {
+            return Space::with_width(Length::Shrink).into();
+        }
+        pick_list(recent, None, |folder: RecentFolder| Message::FolderSelected(Some(folder.0.display().to_string())))
+            .placeholder("Recent folders")
+            .into()
+    }
+
+    /// Folders marked as audiobooks, and how fast they play.
+    fn audiobooks_panel(&self) -> Element<'_, Message> {
+        let speed = self.settings.audiobooks.speed;
+        let mut panel = Column::new().spacing(5).push(Text::new("Audiobooks"));
+        for (index, folder) in self.settings.audiobooks.folders.iter().enumerate() {
+            panel = panel.push(
+                Row::new()
+                    .spacing(5)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(folder.display().to_string()).width(Length::Fill))
+                    .push(icon_button("×", "No longer audiobooks", Some(Message::RemoveAudiobookFolder(index)))),
+            );
+        }
+        panel
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(button("Add folder…").on_press(Message::AddAudiobookFolderPressed))
+                    .push(Text::new("Speed"))
+                    .push(slider(MIN_AUDIOBOOK_SPEED..=MAX_AUDIOBOOK_SPEED, speed, Message::AudiobookSpeedChanged).step(0.05))
+                    .push(Text::new(format!("{:.2}×", speed))),
+            )
+            .into()
+    }
+
+    /// Shell commands to run when tracks start, finish or are paused.
+    fn hooks_panel(&self) -> Element<'_, Message> {
+        let toggle = button(if self.show_hooks { "Hide event hooks" } else { "Event hooks…" }).on_press(Message::HooksPressed);
+        if !self.show_hooks {
+            return toggle.into();
+        }
+        let mut panel = Column::new()
+            .spacing(5)
+            .push(toggle)
+            .push(Text::new("Shell commands to run. {title}, {artist}, {album} and {path} are filled in, already quoted.").size(14));
+        for event in HookEvent::ALL {
+            panel = panel.push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(event.to_string()).width(Length::Fixed(120.0)))
+                    .push(
+                        text_input("Command", self.settings.hooks.command(event))
+                            .on_input(move |command| Message::HookChanged(event, command))
+                            .style(self.text_input_style()),
+                    ),
+            );
+        }
+        panel.into()
+    }
+
+    /// What's in the plugins folder, and what each plugin adds.
+    fn plugins_panel(&self) -> Element<'_, Message> {
+        let toggle = button(if self.show_plugins { "Hide plugins" } else { "Plugins…" }).on_press(Message::PluginsPressed);
+        if !self.show_plugins {
+            return toggle.into();
+        }
+        let mut panel = Column::new().spacing(5).push(toggle);
+        if self.plugins.loaded.is_empty() && self.plugins.failed.is_empty() {
+            panel = panel.push(Text::new("No plugins. They're picked up from the plugins folder at startup.").size(14));
+        }
+        for plugin in &self.plugins.loaded {
+            let provides = if plugin.provides.is_empty() { "nothing".to_string() } else { plugin.provides.join(", ") };
+            panel = panel.push(Text::new(format!("{} ({}): {}", plugin.name, display_name(&plugin.file_path), provides)));
+        }
+        for (file_path, e) in &self.plugins.failed {
+            panel = panel.push(Text::new(format!("{}: {}", display_name(file_path), e)).style(ERROR_COLOR));
+        }
+        panel.push(button("Open plugins folder").on_press(Message::OpenPluginsFolder)).into()
+    }
+
+    fn jukebox_settings(&self) -> Element<'_, Message> {
+        match &self.jukebox {
+            Jukebox::ChoosingPin(pin) => Column::new()
+                .spacing(5)
+                .push(Text::new("Choose a PIN. Guests can only search and add to the queue until it's entered again."))
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(
+                            text_input("PIN", pin)
+                                .password()
+                                .on_input(Message::JukeboxPinChanged)
+                                .style(self.text_input_style())
+                                .on_submit(Message::StartJukebox),
+                        )
+                        .push(button("Start").on_press(Message::StartJukebox))
+                        .push(button("Cancel").on_press(Message::CancelJukebox)),
+                )
+                .into(),
+            _ => button("Jukebox mode…").on_press(Message::JukeboxPressed).into(),
+        }
+    }
+
+    /// Bookmarks for every track, the playing one first, plus a way to add one here.
+    fn bookmarks_panel(&self) -> Element<'_, Message> {
+        let mut panel = Column::new().spacing(5).push(Text::new("Bookmarks"));
+        if self.now_playing.is_some() {
+            panel = panel.push(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        text_input("Name (optional)", &self.bookmark_name)
+                            .on_input(Message::BookmarkNameChanged)
+                            .style(self.text_input_style())
+                            .on_submit(Message::AddBookmark),
+                    )
+                    .push(button("Add bookmark").on_press(Message::AddBookmark)),
+            );
+        }
+
+        let playing = self.now_playing.as_deref().and_then(|file_path| self.db.tracks.get_key_value(file_path));
+        let others = self.db.tracks.iter().filter(|(file_path, _)| Some(file_path.as_path()) != self.now_playing.as_deref());
+        let mut list = Column::new().spacing(5);
+        for (file_path, record) in playing.into_iter().chain(others) {
+            if record.bookmarks.is_empty() {
+                continue;
+            }
+            list = list.push(Text::new(display_name(file_path)).size(14));
+            for (index, bookmark) in record.bookmarks.iter().enumerate() {
+                let label = format!("{}  {}", format_duration(bookmark.position), bookmark.name);
+                list = list.push(
+                    Row::new()
+                        .spacing(5)
+                        .push(
+                            button(Text::new(label))
+                                .on_press(Message::JumpToBookmark(file_path.clone(), bookmark.position))
+                                .style(theme::Button::Secondary)
+                                .width(Length::Fill),
+                        )
+                        .push(icon_button("×", "Delete bookmark", Some(Message::DeleteBookmark(file_path.clone(), index)))),
+                );
+            }
+        }
+        if self.db.tracks.values().all(|record| record.bookmarks.is_empty()) {
+            list = list.push(Text::new("No bookmarks yet").size(14));
+        }
+
+        panel.push(scrollable(list).height(Length::Fixed(120.0))).into()
+    }
+
+    fn fullscreen_view(&self) -> Element<'_, Message> {
+        let cover = mouse_area(self.album_art_image().width(Length::Fill).height(Length::Fill))
+            .on_press(Message::ToggleFullscreen);
+
+        let title = self.song_title.clone().unwrap_or_else(|| {
+            self.now_playing.as_deref().map(display_name).unwrap_or_else(|| "No audio playing".to_string())
+        });
+        let mut info = Column::new()
+            .spacing(10)
+            .align_items(Alignment::Center)
+            .push(Text::new(title).size(40).horizontal_alignment(alignment::Horizontal::Center));
+        if let Some(artist) = &self.artist {
+            info = info.push(Text::new(artist).size(28));
+        }
+        if self.now_playing.is_some() {
+            let pause_or_resume = if self.paused {
+                button("Resume").on_press(Message::ResumePlayback)
+            } else {
+                button("Pause").on_press(Message::PausePlayback)
+            };
+            info = info.push(self.seek_bar()).push(pause_or_resume);
+        }
+
+        Column::new()
+            .spacing(30)
+            .padding(40)
+            .align_items(Alignment::Center)
+            .push(cover)
+            .push(info)
+            .push(Text::new("Press F11 or Esc, or click the cover, to leave full screen").size(14))
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    }
+
+    /// The playing track's album art at full size, zoomed to fit unless asked otherwise.
+    fn cover_view<'a>(&'a self, viewer: &'a CoverViewer) -> Element<'a, Message> {
+        let picture: Element<'_, Message> = match &viewer.cover {
+            None => Text::new("Loading…").into(),
+            Some(Err(e)) => Text::new(e.as_str()).into(),
+            Some(Ok((cover, handle))) => match viewer.zoom {
+                None => image(handle.clone()).width(Length::Fill).height(Length::Fill).content_fit(ContentFit::ScaleDown).into(),
+                Some(zoom) => scrollable(
+                    image(handle.clone())
+                        .width(Length::Fixed(cover.width as f32 * zoom))
+                        .height(Length::Fixed(cover.height as f32 * zoom)),
+                )
+                .horizontal_scroll(scrollable::Properties::new())
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into(),
+            },
+        };
+
+        let mut toolbar = Row::new().spacing(10).align_items(Alignment::Center);
+        if let Some(Ok((cover, _))) = &viewer.cover {
+            let zoom = viewer.zoom.unwrap_or_else(|| self.fit_zoom(cover));
+            let zoom_out = (zoom > MIN_COVER_ZOOM).then_some(Message::ZoomCover(Some(zoom / COVER_ZOOM_STEP)));
+            let zoom_in = (zoom < MAX_COVER_ZOOM).then_some(Message::ZoomCover(Some(zoom * COVER_ZOOM_STEP)));
+            toolbar = toolbar
+                .push(icon_button("−", "Zoom out", zoom_out))
+                .push(Text::new(format!("{:.0}%", zoom * 100.0)))
+                .push(icon_button("+", "Zoom in", zoom_in))
+                .push(button("Fit").on_press(Message::ZoomCover(None)))
+                .push(button("Actual size").on_press(Message::ZoomCover(Some(1.0))))
+                .push(button("Save image as…").on_press(Message::SaveCoverPressed))
+                .push(Text::new(format!("{} × {} {}", cover.width, cover.height, cover.extension.to_uppercase())).size(14));
+        }
+        toolbar = toolbar.push(Space::with_width(Length::Fill)).push(button("Close").on_press(Message::CloseCover));
+
+        Column::new()
+            .spacing(10)
+            .padding(20)
+            .push(toolbar)
+            .push(Container::new(picture).width(Length::Fill).height(Length::Fill).center_x().center_y())
+            .into()
+    }
+
+    /// The zoom that fits `cover` in the window, no bigger than actual size.
+    fn fit_zoom(&self, cover: &Cover) -> f32 {
+        let (width, height) = self.settings.layout.window_size;
+        let scale = self.scale_factor() as f32;
+        // Less the padding and the toolbar
+        let (room_x, room_y) = (width as f32 / scale - 40.0, height as f32 / scale - 90.0);
+        (room_x / cover.width.max(1) as f32).min(room_y / cover.height.max(1) as f32).clamp(MIN_COVER_ZOOM, 1.0)
+    }
+
+    fn album_art_image(&self) -> iced::widget::Image {
+        let handle = match &self.album_art {
+            Some(bytes) => image::Handle::from_memory(bytes.clone()),
+            // Load fallback image
+            None => image::Handle::from_memory(include_bytes!("../assets/fallback_image.png").to_vec()),
+        };
+        image(handle)
+    }
+
+    fn seek_bar(&self) -> Element<'_, Message> {
+        let position = self.seek_preview.unwrap_or(self.position.as_secs_f32());
+        match self.track_duration {
+            Some(duration) if let Some(peaks) = &self.waveform => {
+                let waveform = WaveformBar {
+                    peaks,
+                    progress: position / duration.as_secs_f32(),
+                    markers: self.chapters.iter().map(|chapter| chapter.start.as_secs_f32() / duration.as_secs_f32()).collect(),
+                    on_change: Message::WaveformDragged,
+                    on_release: Message::SeekReleased,
+                };
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(format_duration(Duration::from_secs_f32(position))))
+                    .push(canvas(waveform).width(Length::Fill).height(Length::Fixed(40.0)))
+                    .push(Text::new(format_duration(duration)))
+                    .into()
+            }
+            Some(duration) => Row::new()
+                .spacing(10)
+                .push(Text::new(format_duration(Duration::from_secs_f32(position))))
+                .push(
+                    slider(0.0..=duration.as_secs_f32(), position, Message::SeekChanged)
+                        .on_release(Message::SeekReleased)
+                        .step(0.1),
+                )
+                .push(Text::new(format_duration(duration)))
+                .into(),
+            None => Text::new(format_duration(self.position)).into(),
+        }
+    }
+
+    /// The chapter playing, to pick another from, with buttons for the ones either side.
+    fn chapter_picker(&self) -> Option<Element<'_, Message>> {
+        if self.chapters.is_empty() {
+            return None;
+        }
+        let position = self.seek_preview.map_or(self.position, Duration::from_secs_f32);
+        let current = chapter_at(&self.chapters, position);
+        // Back to the start of this chapter, unless it's only just begun
+        let previous = match current {
+            Some(index) if position - self.chapters[index].start > Duration::from_secs(3) => Some(index),
+            Some(index) => index.checked_sub(1),
+            None => None,
+        };
+        let next = current.map_or(0, |index| index + 1);
+        Some(
+            Row::new()
+                .spacing(5)
+                .align_items(Alignment::Center)
+                .push(Text::new("Chapter"))
+                .push(icon_button("⏮", "Previous chapter", previous.map(|index| Message::JumpToChapter(self.chapters[index].start))))
+                .push(
+                    pick_list(&self.chapters[..], current.map(|index| self.chapters[index].clone()), |chapter| Message::JumpToChapter(chapter.start))
+                        .width(Length::Fill),
+                )
+                .push(icon_button("⏭", "Next chapter", self.chapters.get(next).map(|chapter| Message::JumpToChapter(chapter.start))))
+                .into(),
+        )
+    }
+
+    fn set_fullscreen(&mut self, fullscreen: bool) -> Command<Message> {
+        self.fullscreen = fullscreen;
+        window::change_mode(if fullscreen { window::Mode::Fullscreen } else { window::Mode::Windowed })
+    }
+
+    fn stop_playback(&mut self) {
+        if let Some(audio) = &self.audio {
+            audio.send(AudioCommand::Stop);
+        }
+        if let Some(file_path) = &self.now_playing {
+            self.report_to_server(file_path, Playback::Stopped { position: self.position });
+        }
+        self.paused = false;
+        self.position = Duration::ZERO;
+        self.track_duration = None;
+        self.seek_preview = None;
+        self.waveform = None;
+        self.chapters.clear();
+        self.preloaded = None;
+        self.unavailable = None;
+        self.between_tracks = false;
+        self.now_playing = None;
+        self.starting = None;
+        self.album_art = None; // Clear album art
+        self.song_title = None; // Clear song title
+        self.artist = None;     // Clear artist
+        self.spectrum.reset();
+        self.meters.reset();
+        self.limiting = false;
+    }
+
+    /// Scans the library folders from scratch.
+    fn start_scan(&mut self) -> Command<Message> {
+        self.audio_files.clear();
+        self.pending_tags.clear();
+        self.tags_total = 0;
+        self.selected.clear();
+        self.selection_anchor = None;
+        self.cursor = None;
+        self.list_scroll = RelativeOffset::START;
+        self.scan_status = "Scanning...".to_string();
+        let (folders, scan) = (self.settings.library_folders.clone(), self.settings.scan.clone());
+        Command::perform(async move { scan_folders(&folders, &scan) }, Message::ScanComplete)
+    }
+
+    /// Reads the tags of the next batch of scanned files. Going a batch at a
+    /// time shows how far it's got, which takes a while for a big library.
+    fn read_more_tags(&mut self) -> Command<Message> {
+        let batch: Vec<PathBuf> = self.pending_tags.drain(..self.pending_tags.len().min(TAG_BATCH)).collect();
+        let done = self.tags_total - self.pending_tags.len() - batch.len();
+        self.scan_status = format!("Found {} audio files, reading tags ({} of {})...", self.tags_total, done, self.tags_total);
+        let use_cache = self.settings.features.metadata_cache;
+        let plugins = self.plugins.clone();
+        Command::perform(
+            async move {
+                let mut track_info = load_track_info(&batch, use_cache);
+                // Plugins only fill the gaps, and what they find isn't cached with the tags
+                for (file_path, info) in &mut track_info {
+                    if let Ok(info) = info {
+                        plugins.fill_in(file_path, info);
+                    }
+                }
+                track_info
+            },
+            Message::TrackInfoLoaded,
+        )
+    }
+
+    /// Has the audio thread get the next track in the queue ready, so it starts
+    /// without a pause however it's moved on to.
+    fn preload_next(&mut self) {
+        let next = self.queue.iter().next().cloned();
+        if next != self.preloaded
+            && let Some(audio) = &self.audio
+        {
+            audio.send(AudioCommand::Preload(next.clone()));
+            self.preloaded = next;
+        }
+    }
+
+    /// Lets the Jellyfin server know how playback's going, if `file_path` came from it.
+    fn report_to_server(&self, file_path: &Path, playback: Playback) {
+        if let Some(item_id) = jellyfin::item_id(file_path)
+            && let Some(session) = Session::new(&self.settings.jellyfin)
+        {
+            session.report(&item_id, playback);
+        }
+    }
+
+    fn visualisations_enabled(&self) -> bool {
+        self.settings.features.spectrum || self.settings.features.level_meters
+    }
+
+    fn apply_effects(&self) {
+        self.settings.save();
+        if let Some(audio) = &self.audio {
+            audio.send(AudioCommand::SetEffects(self.settings.effects.clone()));
+        }
+    }
+
+    fn apply_silence_trimming(&self) {
+        self.settings.save();
+        if let Some(audio) = &self.audio {
+            audio.send(AudioCommand::SetSilenceTrimming(self.settings.silence.clone()));
+        }
+    }
+
+    /// Only copy samples out of the audio thread when something will draw them.
+    fn update_tap(&self) {
+        if let Some(audio) = &self.audio {
+            audio.tap.set_enabled(self.visualisations_enabled());
+        }
+    }
+
+    /// Selected tracks in list order.
+    fn selected_files(&self) -> Vec<PathBuf> {
+        self.audio_files.iter().filter(|file| self.selected.contains(*file)).cloned().collect()
+    }
+
+    /// Swaps in restored settings and library data, then rescans.
+    fn restore(&mut self, backup: Backup) -> Command<Message> {
+        let Backup { mut settings, db } = backup;
+        // Window geometry and sound devices belong to this machine, not the backup's
+        settings.layout = self.settings.layout.clone();
+        settings.output_device = self.settings.output_device.clone();
+        settings.bit_perfect = self.settings.bit_perfect;
+        settings.sample_rate = self.settings.sample_rate;
+        // Backups don't carry the Jellyfin token, so stay signed in to the same account
+        if settings.jellyfin.server == self.settings.jellyfin.server && settings.jellyfin.user_id == self.settings.jellyfin.user_id {
+            settings.jellyfin.token = self.settings.jellyfin.token.clone();
+        }
+        self.settings = settings;
+        self.db = db;
+        self.settings.save();
+        self.db.save();
+        if let Some(audio) = &self.audio {
+            audio.send(AudioCommand::SetVolume(self.settings.volume));
+            audio.send(AudioCommand::SetEffects(self.settings.effects.clone()));
+            audio.send(AudioCommand::SetSilenceTrimming(self.settings.silence.clone()));
+            audio.send(AudioCommand::SetResampleQuality(self.settings.resample_quality));
+            if let Some(file_path) = &self.now_playing {
+                audio.send(AudioCommand::SetLeveling(self.leveling(file_path)));
+            }
+        }
+        self.update_tap();
+
+        self.selected_folder = self.settings.library_folders.first().map(|path| path.display().to_string()).unwrap_or_default();
+        self.audio_files.clear();
+        self.albums.clear();
+        self.artists.clear();
+        self.composers.clear();
+        // What it would undo belonged to the library being replaced
+        self.journal = Journal::default();
+        self.track_list = TrackList::All;
+        self.search_results = None;
+        self.queue.clear();
+        self.selected.clear();
+        self.selection_anchor = None;
+        self.cursor = None;
+        self.list_scroll = RelativeOffset::START;
+        self.pending_tags.clear();
+        self.scan_status = "Restored, scanning...".to_string();
+        let (folders, scan) = (self.settings.library_folders.clone(), self.settings.scan.clone());
+        Command::perform(async move { scan_folders(&folders, &scan) }, Message::ScanComplete)
+    }
+
+    /// The tracks in the list, in the order they're shown.
+    fn listed_files(&self) -> Cow<'_, [PathBuf]> {
+        match &self.search_results {
+            Some(results) => Cow::Borrowed(results),
+            None => self.unfiltered_files(),
+        }
+    }
+
+    fn unfiltered_files(&self) -> Cow<'_, [PathBuf]> {
+        match &self.track_list {
+            TrackList::All => Cow::Borrowed(&self.audio_files),
+            TrackList::Albums | TrackList::Artists | TrackList::Composers | TrackList::Playlists => Cow::Owned(Vec::new()),
+            TrackList::Album(key) => match self.albums.iter().find(|album| &album.key == key) {
+                Some(album) => Cow::Borrowed(&album.tracks),
+                None => Cow::Owned(Vec::new()),
+            },
+            TrackList::Artist(name) => match self.artists.iter().find(|artist| &artist.name == name) {
+                Some(artist) => Cow::Borrowed(&artist.tracks),
+                None => Cow::Owned(Vec::new()),
+            },
+            TrackList::Composer(name) => match self.composers.iter().find(|composer| &composer.name == name) {
+                Some(composer) => Cow::Borrowed(&composer.tracks),
+                None => Cow::Owned(Vec::new()),
+            },
+            TrackList::Playlist(name) => match self.db.playlist(name) {
+                Some(playlist) => Cow::Borrowed(&playlist.tracks),
+                None => Cow::Owned(Vec::new()),
+            },
+            TrackList::Skipped => Cow::Owned(self.db.frequently_skipped()),
+        }
+    }
+
+    fn refresh_albums(&mut self) {
+        self.albums = group_albums(&self.audio_files, &self.track_info);
+        self.artists = group_artists(&self.audio_files, &self.track_info);
+        self.composers = group_composers(&self.audio_files, &self.track_info);
+    }
+
+    /// Filters the list again, after the search or what's being searched changed.
+    fn refresh_search(&mut self) {
+        self.search_results = (!self.search.trim().is_empty()).then(|| {
+            self.unfiltered_files()
+                .iter()
+                .filter(|file_path| matches_search(file_path, self.track_info.get(*file_path), &self.search))
+                .cloned()
+                .collect()
+        });
+    }
+
+    /// With Auto-DJ on, adds tracks that fit the last one queued (or the one playing)
+    /// until enough are waiting.
+    fn top_up_queue(&mut self) {
+        if !self.settings.auto_dj.enabled || self.queue.len() >= QUEUE_TARGET {
+            return;
+        }
+        let seed = self.queue.iter().last().or(self.now_playing.as_ref());
+        let audiobooks = self.audio_files.iter().filter(|file_path| self.settings.audiobooks.contains(file_path));
+        let skip = self.queue.iter().chain(&self.now_playing).chain(audiobooks).map(PathBuf::as_path).collect();
+        let count = QUEUE_TARGET - self.queue.len();
+        let picks = pick_tracks(
+            self.settings.auto_dj.rule,
+            seed.map(PathBuf::as_path),
+            &self.audio_files,
+            &self.track_info,
+            &self.db,
+            &skip,
+            count,
+        );
+        for file_path in picks {
+            self.queue.push(file_path);
+        }
+    }
+
+    /// Starts `file_path` at `position`, with its gain offset. Audiobooks started
+    /// from the top pick up where they were left instead. Decoding happens on the
+    /// audio thread, which answers with `Started`.
+    fn play_track(&mut self, file_path: PathBuf, position: Duration) {
+        if let Some(audio) = &self.audio {
+            self.starting = Some(file_path.clone());
+            let position = if position.is_zero() && self.settings.audiobooks.contains(&file_path) {
+                self.db.resume_position(&file_path)
+            } else {
+                position
+            };
+            audio.send(AudioCommand::SetTrackGain(self.db.gain_db(&file_path)));
+            audio.send(AudioCommand::SetLeveling(self.leveling(&file_path)));
+            audio.send(AudioCommand::SetSpeed(self.settings.audiobooks.speed_for(&file_path)));
+            audio.send(AudioCommand::PlayFrom(file_path, position));
+        }
+    }
+
+    fn leveling(&self, file_path: &Path) -> Leveling {
+        Leveling::for_track(self.settings.normalize, self.track_info.get(file_path).and_then(|info| info.replay_gain_db))
+    }
+
+    /// Plays `file_path` straight away if nothing else is, otherwise queues it.
+    /// A track that's been asked to start but hasn't yet counts as playing, so
+    /// adding several in a row starts only the first.
+    fn enqueue(&mut self, file_path: PathBuf) {
+        let busy = self.now_playing.is_some() || self.starting.is_some();
+        if let Some(file_path) = self.queue.enqueue(file_path, busy) {
+            self.play_track(file_path, Duration::ZERO);
+        }
+    }
+
+    /// Moves the keyboard cursor `step` rows through the list, selecting the row
+    /// it lands on, or extending the selection to it with Shift, and scrolls it
+    /// into view.
+    fn move_cursor(&mut self, step: isize) -> Command<Message> {
+        if !self.list_showing() {
+            return Command::none();
+        }
+        let files = self.listed_files().into_owned();
+        let Some(last) = files.len().checked_sub(1) else { return Command::none() };
+        let index = match self.cursor.as_deref().and_then(|cursor| files.iter().position(|file| file == cursor)) {
+            Some(index) => index.saturating_add_signed(step).min(last),
+            None if step > 0 => 0,
+            None => last,
+        };
+        let file_path = files[index].clone();
+        if self.modifiers.shift() {
+            self.select_range_to(&file_path);
+        } else {
+            self.selected.clear();
+            self.selected.insert(file_path.clone());
+            self.selection_anchor = Some(file_path.clone());
+        }
+        self.cursor = Some(file_path);
+        self.scroll_to_row(index, files.len())
+    }
+
+    /// Whether the track list is on screen, as opposed to the queue or settings.
+    fn list_showing(&self) -> bool {
+        self.tab.track_list().is_some() || matches!(self.jukebox, Jukebox::Locked { .. })
+    }
+
+    /// Scrolls the list to the playing track and puts the cursor on it. With
+    /// `reveal`, a list it isn't in is swapped for the whole library first.
+    fn scroll_to_playing(&mut self, reveal: bool) -> Command<Message> {
+        let Some(file_path) = self.now_playing.clone() else { return Command::none() };
+        if reveal && !self.listed_files().contains(&file_path) {
+            self.show_tab(Tab::Library);
+            self.track_list = TrackList::All;
+            self.search.clear();
+            self.search_results = None;
+        }
+        let files = self.listed_files();
+        let Some(index) = files.iter().position(|file| *file == file_path) else { return Command::none() };
+        let count = files.len();
+        self.cursor = Some(file_path);
+        self.scroll_to_row(index, count)
+    }
+
+    fn scroll_to_row(&mut self, index: usize, count: usize) -> Command<Message> {
+        // The same proportion of the way down the list as the row, which keeps it on screen
+        let last = count.saturating_sub(1);
+        let offset = RelativeOffset { x: 0.0, y: if last == 0 { 0.0 } else { index as f32 / last as f32 } };
+        self.list_scroll = offset;
+        scrollable::snap_to(track_list_id(), offset)
+    }
+
+    /// Switches to `tab`, remembering it for next time.
+    fn show_tab(&mut self, tab: Tab) {
+        self.tab = tab;
+        self.settings.layout.tab = tab;
+    }
+
+    /// Shift+click: select everything between the anchor and `file_path`.
+    fn select_range_to(&mut self, file_path: &Path) {
+        let files = self.listed_files().into_owned();
+        let position = |target: &Path| files.iter().position(|file| file == target);
+        let Some(end) = position(file_path) else { return };
+        let start = self.selection_anchor.as_deref().and_then(position).unwrap_or(end);
+        let (from, to) = if start <= end { (start, end) } else { (end, start) };
+        if !self.modifiers.command() {
+            self.selected.clear();
+        }
+        self.selected.extend(files[from..=to].iter().cloned());
+        if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(file_path.to_path_buf());
+        }
+    }
+
+    /// Shows `message` to the user until they dismiss it or it times out.
+    fn notify(&mut self, message: String) -> Command<Message> {
+        let id = self.next_toast_id;
+        self.next_toast_id += 1;
+        self.toasts.push(Toast { id, message });
+        Command::perform(Timer::after(TOAST_DURATION), move |_| Message::DismissToast(id))
+    }
+
+    /// Takes `file_path` out of every list, returning what's needed to put it back.
+    fn remove_from_library(&mut self, file_path: &Path) -> Removal {
+        let position = self.audio_files.iter().position(|file| file == file_path).unwrap_or(self.audio_files.len());
+        let removal = Removal { file_path: file_path.to_path_buf(), position, record: self.db.tracks.get(file_path).cloned() };
+        self.audio_files.retain(|file| file != file_path);
+        for album in &mut self.albums {
+            album.tracks.retain(|file| file != file_path);
+        }
+        for person in self.artists.iter_mut().chain(&mut self.composers) {
+            person.tracks.retain(|file| file != file_path);
+        }
+        if let Some(results) = &mut self.search_results {
+            results.retain(|file| file != file_path);
+        }
+        self.queue.forget(file_path);
+        self.selected.remove(file_path);
+        if self.selection_anchor.as_deref() == Some(file_path) {
+            self.selection_anchor = None;
+        }
+        if self.cursor.as_deref() == Some(file_path) {
+            self.cursor = None;
+        }
+        self.scan_status = format!("Found {} audio files", self.audio_files.len());
+        if self.now_playing.as_deref() == Some(file_path) {
+            self.stop_playback();
+        }
+        removal
+    }
+
+    /// Puts back what `change` took away. Tracks that can't be brought back from
+    /// the trash are reported and left out.
+    fn undo(&mut self, change: Change) -> Command<Message> {
+        let mut failures = Vec::new();
+        match change {
+            Change::Removed(removals) => self.restore_to_library(removals),
+            Change::Trashed(removals) => {
+                let mut restored = Vec::new();
+                for removal in removals {
+                    match restore_from_trash(&removal.file_path) {
+                        Ok(()) => restored.push(removal),
+                        Err(e) => {
+                            tracing::warn!(path = %removal.file_path.display(), "Failed to restore from trash: {}", e);
+                            failures.push(self.notify(e));
+                        }
+                    }
+                }
+                self.restore_to_library(restored);
+            }
+            Change::DeletedBookmark { file_path, bookmark } => {
+                self.db.add_bookmark(&file_path, bookmark);
+                self.db.save();
+            }
+        }
+        Command::batch(failures)
+    }
+
+    fn restore_to_library(&mut self, mut removals: Vec<Removal>) {
+        // Lowest first, so each goes back where it was among the others
+        removals.sort_by_key(|removal| removal.position);
+        for removal in removals {
+            if !self.audio_files.contains(&removal.file_path) {
+                let position = removal.position.min(self.audio_files.len());
+                self.audio_files.insert(position, removal.file_path.clone());
+            }
+            if let Some(record) = removal.record {
+                self.db.tracks.insert(removal.file_path, record);
+            }
+        }
+        self.db.save();
+        self.refresh_albums();
+        self.refresh_search();
+        self.scan_status = format!("Found {} audio files", self.audio_files.len());
+    }
+}
+
+/// The Properties dialog: technical details of a file, once they've been read.
+struct PropertiesDialog {
+    file_path: PathBuf,
+    properties: Option<Result<Properties, String>>, // None while it's read
+}
+
+impl PropertiesDialog {
+    fn view(&self) -> Element<'_, Message> {
+        let mut details = Column::new().spacing(8);
+        match &self.properties {
+            None => details = details.push(Text::new("Reading…")),
+            Some(Err(e)) => details = details.push(Text::new(format!("Can't read this file: {}", e))),
+            Some(Ok(properties)) => {
+                for (label, value) in properties.rows() {
+                    details = details.push(
+                        Row::new()
+                            .spacing(20)
+                            .push(Text::new(label).width(Length::Fixed(150.0)))
+                            .push(Text::new(value).width(Length::Fill)),
+                    );
+                }
+                for tag in properties.tags.iter().filter(|tag| tag.pictures > 0) {
+                    let pictures = if tag.pictures == 1 { "1 picture".to_string() } else { format!("{} pictures", tag.pictures) };
+                    details = details.push(Text::new(format!("{} has {}", tag.kind, pictures)).size(14));
+                }
+            }
+        }
+        Column::new()
+            .spacing(20)
+            .padding(20)
+            .push(
+                Row::new()
+                    .align_items(Alignment::Center)
+                    .push(Text::new(format!("Properties of {}", display_name(&self.file_path))).size(24).width(Length::Fill))
+                    .push(button("Close").on_press(Message::CloseProperties)),
+            )
+            .push(scrollable(details).height(Length::Fill))
+            .into()
+    }
+}
+
+/// The album art viewer and what it's showing.
+struct CoverViewer {
+    file_path: PathBuf,
+    cover: Option<Result<(Cover, image::Handle), String>>, // None while it's read
+    zoom: Option<f32>,                                      // None fits it to the window
+}
+
+/// Party mode, where guests can only search and add to the queue.
+enum Jukebox {
+    Off,
+    ChoosingPin(String),
+    Locked { pin: String, entry: String }, // `entry` is what's been typed towards unlocking
+}
+
+impl Jukebox {
+    /// Whether `message` is ignored while the jukebox is locked. Guests get the
+    /// search, the track list and the PIN; otherwise only the app carrying on by
+    /// itself (playback, timers and background work finishing) gets through.
+    fn keeps_guests_from(&self, message: &Message) -> bool {
+        matches!(self, Jukebox::Locked { .. }) && !allowed_while_locked(message)
+    }
+}
+
+fn allowed_while_locked(message: &Message) -> bool {
+    matches!(
+        message,
+        // What the jukebox view and its keyboard shortcuts send
+        Message::SearchChanged(_)
+            | Message::EnqueueTrack(_)
+            | Message::TrackListScrolled(_)
+            | Message::MoveCursor(_)
+            | Message::ActivateCursor
+            | Message::FocusNext(_)
+            | Message::ModifiersChanged(_)
+            | Message::JukeboxPinChanged(_)
+            | Message::UnlockJukebox
+            | Message::DismissToast(_)
+            | Message::WindowResized(..)
+            | Message::WindowMoved(..)
+            // Playback going on through the queue
+            | Message::Audio(_)
+            | Message::AudioReady(_)
+            | Message::PlayAudio(_)
+            | Message::Frame
+            // Work started before locking, finishing
+            | Message::ScanComplete(_)
+            | Message::TrackInfoLoaded(_)
+            | Message::WaveformLoaded(..)
+            | Message::ChaptersLoaded(..)
+            | Message::LyricsLoaded(..)
+            | Message::DisplayAlbumArtAndMetadata(..)
+            | Message::OutputDevicesListed(_)
+            | Message::ConverterReady(_)
+            | Message::Convert(_)
+            | Message::CoverLoaded(..)
+            | Message::CoverSaved(_)
+            | Message::PropertiesRead(..)
+            | Message::ImportFinished(_)
+            | Message::ExportFinished(_)
+            | Message::BackupFinished(_)
+            | Message::JellyfinSignedIn(_)
+            | Message::JellyfinListed(..)
+            | Message::JellyfinTracksLoaded(..)
+            | Message::JellyfinFetched(..)
+    )
+}
+
+enum PaneKind {
+    Library,
+    NowPlaying,
+}
+
+/// The pages of the first-run setup, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupStep {
+    Folders,
+    Theme,
+    Output,
+    Scanning,
+}
+
+impl SetupStep {
+    const ALL: [SetupStep; 4] = [SetupStep::Folders, SetupStep::Theme, SetupStep::Output, SetupStep::Scanning];
+}
+
+impl std::fmt::Display for SetupStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SetupStep::Folders => "Music folders",
+            SetupStep::Theme => "Theme",
+            SetupStep::Output => "Output device",
+            SetupStep::Scanning => "Scanning",
+        })
+    }
+}
+
+/// The views down the side of the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tab {
+    Library,
+    Albums,
+    Artists,
+    Playlists,
+    History,
+    Queue,
+    Jellyfin,
+    Settings,
+}
+
+impl Tab {
+    const ALL: [Tab; 8] = [Tab::Library, Tab::Albums, Tab::Artists, Tab::Playlists, Tab::History, Tab::Queue, Tab::Jellyfin, Tab::Settings];
+
+    /// The tab Ctrl+1 to Ctrl+8 (⌘ on macOS) switch to, in order down the side.
+    fn for_shortcut(key_code: keyboard::KeyCode) -> Option<Tab> {
+        use keyboard::KeyCode;
+        let keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8];
+        keys.iter().position(|key| *key == key_code).map(|index| Tab::ALL[index])
+    }
+
+    /// Where the list starts when this tab is opened, for tabs that show one.
+    fn track_list(self) -> Option<TrackList> {
+        match self {
+            Tab::Library => Some(TrackList::All),
+            Tab::Albums => Some(TrackList::Albums),
+            Tab::Artists => Some(TrackList::Artists),
+            Tab::Playlists => Some(TrackList::Playlists),
+            Tab::History | Tab::Queue | Tab::Jellyfin | Tab::Settings => None,
+        }
+    }
+
+    /// The tab `track_list` belongs in.
+    fn showing(track_list: &TrackList) -> Tab {
+        match track_list {
+            TrackList::All => Tab::Library,
+            TrackList::Albums | TrackList::Album(_) => Tab::Albums,
+            TrackList::Artists | TrackList::Artist(_) | TrackList::Composers | TrackList::Composer(_) => Tab::Artists,
+            TrackList::Playlists | TrackList::Playlist(_) | TrackList::Skipped => Tab::Playlists,
+        }
+    }
+}
+
+impl std::fmt::Display for Tab {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Tab::Library => "Library",
+            Tab::Albums => "Albums",
+            Tab::Artists => "Artists",
+            Tab::Playlists => "Playlists",
+            Tab::History => "History",
+            Tab::Queue => "Queue",
+            Tab::Jellyfin => "Jellyfin",
+            Tab::Settings => "Settings",
+        })
+    }
+}
+
+/// What the track list is showing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackList {
+    All,
+    Albums,
+    Album(AlbumKey),
+    Artists,
+    Artist(String),
+    Composers,
+    Composer(String),
+    Playlists,
+    Playlist(String),
+    Skipped, // Suggestions to remove or rate down
+}
+
+impl TrackList {
+    /// Lists of albums, people or playlists to pick from, rather than tracks.
+    fn is_groups(&self) -> bool {
+        matches!(self, TrackList::Albums | TrackList::Artists | TrackList::Composers | TrackList::Playlists)
+    }
+}
+
+impl std::fmt::Display for TrackList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrackList::All => f.write_str("All tracks"),
+            TrackList::Albums => f.write_str("Albums"),
+            TrackList::Album(key) => key.fmt(f),
+            TrackList::Artists => f.write_str("Artists"),
+            TrackList::Composers => f.write_str("Composers"),
+            TrackList::Playlists => f.write_str("Playlists"),
+            TrackList::Skipped => f.write_str("Frequently skipped"),
+            TrackList::Artist(name) | TrackList::Composer(name) | TrackList::Playlist(name) => f.write_str(name),
+        }
+    }
+}
+
+/// An album, artist, composer or playlist in a list of them.
+struct Group {
+    name: String,
+    tracks: usize,
+    opens: TrackList,
+}
+
+/// A queued or finished conversion, as shown in the library pane.
+struct Conversion {
+    id: u64,
+    name: String,
+    progress: f32,
+    result: Option<Result<PathBuf, String>>, // None while it's waiting or running
+}
+
+struct Toast {
+    id: u64,
+    message: String,
+}
+
+fn column_width(column: ListColumn) -> Length {
+    match column {
+        ListColumn::Title => Length::FillPortion(3),
+        _ if column.is_text() => Length::FillPortion(2),
+        ListColumn::Bitrate => Length::Fixed(80.0),
+        ListColumn::Rating => Length::Fixed(75.0),
+        _ => Length::Fixed(50.0),
+    }
+}
+
+/// The main track list, or the jukebox's search results, whichever is showing.
+fn track_list_id() -> scrollable::Id {
+    scrollable::Id::new("track-list")
+}
+
+/// A symbol-only button with `label` saying what it does on hover.
+fn icon_button<'a>(symbol: &'a str, label: &'a str, on_press: Option<Message>) -> Element<'a, Message> {
+    let mut button = button(symbol).style(theme::Button::Text);
+    if let Some(message) = on_press {
+        button = button.on_press(message);
+    }
+    tooltip(button, label, tooltip::Position::Top).style(theme::Container::Box).into()
+}
+
+fn display_name(file_path: &Path) -> String {
+    file_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// An entry in the output device picker; `None` follows the system default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDevice(Option<String>);
+
+impl std::fmt::Display for OutputDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0.as_deref().unwrap_or("System default"))
+    }
+}
+
+/// A library folder picked before, shown by its path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentFolder(PathBuf);
+
+impl std::fmt::Display for RecentFolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+/// A sample rate to run the output device at, or `None` for its default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRate(Option<u32>);
+
+impl SampleRate {
+    const ALL: [SampleRate; 4] = [SampleRate(None), SampleRate(Some(44100)), SampleRate(Some(48000)), SampleRate(Some(96000))];
+}
+
+impl std::fmt::Display for SampleRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(rate) => write!(f, "{} kHz", rate as f32 / 1000.0),
+            None => f.write_str("Device default"),
+        }
+    }
+}
+
+/// Starts the audio thread and forwards its events to the app.
+fn audio_subscription() -> Subscription<Message> {
+    enum State {
+        Starting,
+        Running(async_mpsc::Receiver<AudioEvent>),
+    }
+
+    struct Audio;
+
+    iced::subscription::unfold(std::any::TypeId::of::<Audio>(), State::Starting, |state| async move {
+        match state {
+            State::Starting => {
+                let (sender, receiver) = async_mpsc::channel(100);
+                (Message::AudioReady(AudioEngine::spawn(sender)), State::Running(receiver))
+            }
+            State::Running(mut receiver) => match receiver.next().await {
+                Some(event) => (Message::Audio(event), State::Running(receiver)),
+                // The audio thread is gone, nothing more will arrive
+                None => iced::futures::future::pending().await,
+            },
+        }
+    })
+}
+
+/// Ticks at `FRAME_INTERVAL` to drive animations.
+fn frame_subscription() -> Subscription<Message> {
+    struct Frames;
+
+    // One timer for the life of the subscription, woken by async-io's shared timer thread
+    iced::subscription::unfold(std::any::TypeId::of::<Frames>(), Timer::interval(FRAME_INTERVAL), |mut ticks| async move {
+        ticks.next().await;
+        (Message::Frame, ticks)
+    })
+}
+
+/// Starts the conversion thread and forwards its progress to the app.
+fn converter_subscription() -> Subscription<Message> {
+    enum State {
+        Starting,
+        Running(async_mpsc::Receiver<ConvertEvent>),
+    }
+
+    struct Convert;
+
+    iced::subscription::unfold(std::any::TypeId::of::<Convert>(), State::Starting, |state| async move {
+        match state {
+            State::Starting => {
+                let (sender, receiver) = async_mpsc::channel(100);
+                (Message::ConverterReady(Converter::spawn(sender)), State::Running(receiver))
+            }
+            State::Running(mut receiver) => match receiver.next().await {
+                Some(event) => (Message::Convert(event), State::Running(receiver)),
+                None => iced::futures::future::pending().await,
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locked_jukebox_ignores_pause_shortcut() {
+        let locked = Jukebox::Locked { pin: "1234".to_string(), entry: String::new() };
+        assert!(locked.keeps_guests_from(&Message::TogglePause));
+        assert!(!locked.keeps_guests_from(&Message::EnqueueTrack(PathBuf::from("a.mp3"))));
+        assert!(!Jukebox::Off.keeps_guests_from(&Message::TogglePause));
+        assert!(!Jukebox::ChoosingPin(String::new()).keeps_guests_from(&Message::TogglePause));
+    }
+
+    #[test]
+    fn locked_jukebox_ignores_undo() {
+        let locked = Jukebox::Locked { pin: "1234".to_string(), entry: String::new() };
+        assert!(locked.keeps_guests_from(&Message::Undo));
+        assert!(!Jukebox::Off.keeps_guests_from(&Message::Undo));
+    }
+
+    #[test]
+    fn number_shortcuts_pick_tabs_in_order() {
+        assert_eq!(Tab::for_shortcut(keyboard::KeyCode::Key1), Some(Tab::Library));
+        assert_eq!(Tab::for_shortcut(keyboard::KeyCode::Key8), Some(Tab::Settings));
+        assert_eq!(Tab::for_shortcut(keyboard::KeyCode::Key9), None);
+    }
+
+    #[test]
+    fn locked_jukebox_lets_through_only_what_guests_need() {
+        let locked = Jukebox::Locked { pin: "1234".to_string(), entry: String::new() };
+        for message in [Message::CloseRequested, Message::StopPlayback, Message::PlayNext, Message::TabSelected(Tab::Settings), Message::JukeboxPressed] {
+            assert!(locked.keeps_guests_from(&message), "{:?}", message);
+        }
+        for message in [Message::SearchChanged("abba".to_string()), Message::UnlockJukebox, Message::Audio(AudioEvent::TrackEnded), Message::Frame] {
+            assert!(!locked.keeps_guests_from(&message), "{:?}", message);
+        }
+        assert!(!Jukebox::Off.keeps_guests_from(&Message::CloseRequested));
+    }
+}
@@ -0,0 +1,158 @@
+use iced::event::Status;
+use iced::mouse;
+use iced::widget::canvas::{self, Cursor, Event, Frame, Geometry};
+use iced::{Point, Rectangle, Size, Theme};
+use rodio::{Sample, Source};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+// Plenty for the width of the seek bar, and cheap to draw every frame
+pub const PEAK_COUNT: usize = 400;
+// A click this close to a chapter marker, in pixels, lands on the chapter's start
+const MARKER_SNAP: f32 = 4.0;
+// The length isn't always known up front, so peaks are gathered for short chunks first
+pub const CHUNK_DURATION: Duration = Duration::from_millis(10);
+
+/// Loudest sample in each of `buckets` equal slices of the track, between 0 and 1.
+/// Decodes the whole file, so run it off the UI thread.
+#[tracing::instrument(level = "debug", fields(path = %file_path.display()))]
+pub fn compute_peaks(file_path: &Path, buckets: usize) -> Result<Vec<f32>, String> {
+    let chunks = chunk_peaks(file_path)?;
+    if chunks.len() <= buckets {
+        return Ok(chunks);
+    }
+    Ok((0..buckets)
+        .map(|bucket| {
+            let start = bucket * chunks.len() / buckets;
+            let end = (bucket + 1) * chunks.len() / buckets;
+            chunks[start..end].iter().copied().fold(0.0, f32::max)
+        })
+        .collect())
+}
+
+/// Loudest sample in every `CHUNK_DURATION` of the track. Decodes the whole file.
+pub fn chunk_peaks(file_path: &Path) -> Result<Vec<f32>, String> {
+    let file = fs::File::open(file_path).map_err(|e| format!("Failed to open the file: {}", e))?;
+    let decoder = rodio::Decoder::new(std::io::BufReader::new(file)).map_err(|e| format!("Failed to decode: {}", e))?;
+
+    let chunks_per_second = (Duration::from_secs(1).as_micros() / CHUNK_DURATION.as_micros()) as usize;
+    let chunk = (decoder.sample_rate() as usize * decoder.channels() as usize / chunks_per_second).max(1);
+    let mut chunks = Vec::new();
+    let mut peak: f32 = 0.0;
+    for (i, sample) in decoder.enumerate() {
+        peak = peak.max(sample.to_f32().abs());
+        if (i + 1) % chunk == 0 {
+            chunks.push(peak);
+            peak = 0.0;
+        }
+    }
+    if peak > 0.0 {
+        chunks.push(peak);
+    }
+    Ok(chunks)
+}
+
+/// A seek bar drawn as the track's waveform. Click or drag anywhere on it to
+/// pick a spot; `on_change` gets how far through the track that is, from 0 to 1.
+/// Chapters are marked with ticks, and clicking next to one jumps to its start.
+pub struct WaveformBar<'a, Message> {
+    pub peaks: &'a [f32],
+    pub progress: f32,
+    pub markers: Vec<f32>, // Where chapters start, from 0 to 1
+    pub on_change: fn(f32) -> Message,
+    pub on_release: Message,
+}
+
+#[derive(Default)]
+pub struct Dragging(bool);
+
+impl<Message: Clone> canvas::Program<Message> for WaveformBar<'_, Message> {
+    type State = Dragging;
+
+    fn update(&self, dragging: &mut Dragging, event: Event, bounds: Rectangle, cursor: Cursor) -> (Status, Option<Message>) {
+        let fraction = |cursor: Cursor| cursor.position().map(|point| ((point.x - bounds.x) / bounds.width).clamp(0.0, 1.0));
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) if cursor.is_over(&bounds) => {
+                dragging.0 = true;
+                let snapped = fraction(cursor).map(|clicked| {
+                    let nearest = self.markers.iter().copied().min_by(|a, b| (a - clicked).abs().total_cmp(&(b - clicked).abs()));
+                    nearest.filter(|marker| (marker - clicked).abs() * bounds.width <= MARKER_SNAP).unwrap_or(clicked)
+                });
+                (Status::Captured, snapped.map(self.on_change))
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) if dragging.0 => (Status::Captured, fraction(cursor).map(self.on_change)),
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) if dragging.0 => {
+                dragging.0 = false;
+                (Status::Captured, Some(self.on_release.clone()))
+            }
+            _ => (Status::Ignored, None),
+        }
+    }
+
+    fn draw(&self, _dragging: &Dragging, theme: &Theme, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let palette = theme.extended_palette();
+        let played_until = self.progress.clamp(0.0, 1.0) * bounds.width;
+        let slot = bounds.width / self.peaks.len().max(1) as f32;
+        let middle = bounds.height / 2.0;
+        for (i, peak) in self.peaks.iter().enumerate() {
+            let x = i as f32 * slot;
+            let height = (peak * bounds.height).max(1.0);
+            let color = if x < played_until { palette.primary.base.color } else { palette.background.strong.color };
+            frame.fill_rectangle(Point::new(x, middle - height / 2.0), Size::new(slot.max(1.0), height), color);
+        }
+        for marker in &self.markers {
+            frame.fill_rectangle(Point::new(marker * bounds.width, 0.0), Size::new(1.0, bounds.height), palette.background.base.text);
+        }
+        // Playhead
+        frame.fill_rectangle(Point::new(played_until - 1.0, 0.0), Size::new(2.0, bounds.height), palette.primary.strong.color);
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(&self, _dragging: &Dragging, bounds: Rectangle, cursor: Cursor) -> mouse::Interaction {
+        if cursor.is_over(&bounds) { mouse::Interaction::Pointer } else { mouse::Interaction::default() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_audio::write_wav;
+
+    /// A second of 8 kHz full-swing square wave at each of `levels`.
+    fn square_wave(levels: &[i16]) -> Vec<i16> {
+        levels.iter().flat_map(|&level| (0..8000).map(move |i| if i % 2 == 0 { level } else { -level })).collect()
+    }
+
+    #[test]
+    fn finds_the_loud_part() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quiet-then-loud.wav");
+        write_wav(&path, 1, 8000, &square_wave(&[i16::MAX / 10, i16::MAX]));
+
+        let peaks = compute_peaks(&path, 10).unwrap();
+        assert_eq!(peaks.len(), 10);
+        assert!(peaks[..5].iter().all(|&peak| (0.09..0.11).contains(&peak)));
+        assert!(peaks[5..].iter().all(|&peak| peak > 0.99));
+    }
+
+    #[test]
+    fn short_track_keeps_every_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("short.wav");
+        write_wav(&path, 1, 8000, &square_wave(&[i16::MAX]));
+
+        // One second is only 100 chunks of 10ms
+        assert_eq!(compute_peaks(&path, PEAK_COUNT).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn unreadable_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.mp3");
+        fs::write(&path, b"not really an mp3").unwrap();
+
+        assert!(compute_peaks(&path, PEAK_COUNT).is_err());
+    }
+}